@@ -1,20 +1,34 @@
+use std::collections::HashSet;
 use std::fs;
-use std::path::PathBuf;
-use std::process::{Command, Stdio};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::Command;
 use std::time::Duration;
 
 use anyhow::{anyhow, bail, Context, Result};
 use chrono::{DateTime, Utc};
-use clap::{ArgAction, Args, Parser, Subcommand};
+use clap::{ArgAction, Args, Parser, Subcommand, ValueEnum};
+use regex::Regex;
 use serde_json::{self, json};
 
-use crate::ignore::IgnoreMatcher;
-use crate::models::TimelineEntry;
-use crate::pipeline::decompress_patch;
-use crate::runtime;
-use crate::storage::{find_project_entry, read_registry_global, StorageEngine};
+use crate::git;
+use crate::ignore::{IgnoreMatcher, PathGlobs};
+use crate::models::{FileOp, RecordMeta, TimelineEntry};
+use crate::pipeline::{decompress_patch, render_semantic, DiffOptions};
+use crate::runtime::{self, LogOutput, ReadySignal, ServiceTarget};
+use crate::storage::{
+    find_project_entry, read_registry_global, relocate, set_project_enabled, set_project_stopped,
+    LineSide, Registry, RelocateOptions, SearchQuery, StorageEngine, TimelineFilter, Tree,
+    LATEST_REF,
+};
 use crate::util::{self, colorize_patch};
-use crate::watcher::{self, is_process_alive, send_terminate, WatchLock, WatchOptions};
+use crate::watcher::{
+    self, is_process_alive, send_terminate, BaselineSource, ControlState, LockInfo, RecordEvent,
+    SupervisorOptions, WatchLock, WatchOptions, WatchSettings,
+};
+
+/// Priming the baseline of a large tree can take a while.
+const DAEMON_READY_TIMEOUT: Duration = Duration::from_secs(120);
 
 #[derive(Parser)]
 #[command(author, version, about = "MeowDiff local change tracker")]
@@ -38,22 +52,172 @@ pub enum Commands {
     Inspect(InspectArgs),
     Ignore(IgnoreArgs),
     Extract(ExtractArgs),
+    Pause(PauseArgs),
+    Resume(ResumeArgs),
+    Mute(MuteArgs),
+    Tail(TailArgs),
+    Daemon(DaemonArgs),
+    Enable(EnableArgs),
+    Disable(DisableArgs),
+    Service(ServiceArgs),
+    Relocate(RelocateArgs),
+    Export(ExportArgs),
+    Import(ImportArgs),
+    ToGit(ToGitArgs),
+    Rebaseline(RebaselineArgs),
+    Search(SearchArgs),
+    Blame(BlameArgs),
+    Cat(CatArgs),
+    Ls(LsArgs),
+    Snapshot(SnapshotArgs),
+    Note(NoteArgs),
+    Tag(TagArgs),
+    Bookmark(BookmarkArgs),
 }
 
 #[derive(Args)]
 pub struct WatchArgs {
     #[arg(short, long, help = "Project path (defaults to CWD)")]
     pub path: Option<PathBuf>,
+    #[command(flatten)]
+    pub flags: WatchFlags,
     #[arg(
         long,
-        help = "Micro-batch window in milliseconds",
-        default_value_t = 50
+        help = "Worker threads for diffing a batch (0 = one per CPU)",
+        default_value_t = 0
     )]
-    pub window_ms: u64,
+    pub jobs: usize,
+    #[arg(
+        long,
+        value_name = "REV",
+        help = "Start a new store from this git revision instead of the files on disk"
+    )]
+    pub baseline_from: Option<String>,
     #[arg(long, help = "Run watcher as background daemon")]
     pub daemon: bool,
     #[arg(long, hide = true)]
     pub foreground: bool,
+    #[arg(
+        long,
+        help = "Log to stderr instead of a log file, for systemd and other supervisors"
+    )]
+    pub log_stderr: bool,
+}
+
+/// Flags shared by `watch` and `enable`, which saves them for the daemon.
+#[derive(Args)]
+pub struct WatchFlags {
+    #[arg(
+        long,
+        help = "Micro-batch window in milliseconds",
+        default_value_t = 50
+    )]
+    pub window_ms: u64,
+    #[arg(
+        long,
+        help = "Close a batch after this many milliseconds even if events keep arriving (0 = never)",
+        default_value_t = 5000
+    )]
+    pub max_batch_ms: u64,
+    #[arg(
+        long,
+        help = "Close a batch once it holds this many events (0 = unlimited)",
+        default_value_t = 10000
+    )]
+    pub max_batch_events: usize,
+    #[arg(
+        long,
+        help = "Close a batch once it touches this many files (0 = unlimited)",
+        default_value_t = 0
+    )]
+    pub max_batch_files: usize,
+    #[arg(
+        long,
+        help = "Record every file write separately, even repeated writes to one file"
+    )]
+    pub per_file: bool,
+    #[arg(
+        long,
+        help = "Memory budget for cached snapshot contents in MiB",
+        default_value_t = 64
+    )]
+    pub cache_mb: usize,
+    #[arg(long, help = "Record structural diffs for JSON, TOML and YAML files")]
+    pub semantic: bool,
+    #[arg(long, help = "Diff Jupyter notebooks cell by cell")]
+    pub notebook: bool,
+    #[arg(
+        long,
+        value_enum,
+        value_delimiter = ',',
+        help = "Notebook fields to leave out of cell diffs"
+    )]
+    pub notebook_ignore: Vec<NotebookField>,
+    #[arg(long, help = "Do not tag records with git branch and HEAD")]
+    pub no_git: bool,
+    #[arg(
+        long,
+        help = "Hold changes while git rewrites the tree and record them as one batch"
+    )]
+    pub git_collapse: bool,
+}
+
+impl WatchFlags {
+    fn settings(&self) -> WatchSettings {
+        WatchSettings {
+            window_ms: self.window_ms,
+            max_batch_ms: self.max_batch_ms,
+            max_batch_events: self.max_batch_events,
+            max_batch_files: self.max_batch_files,
+            per_file: self.per_file,
+            cache_mb: self.cache_mb,
+            semantic: self.semantic,
+            notebook: self.notebook,
+            notebook_ignore_outputs: self.notebook_ignore.contains(&NotebookField::Outputs),
+            notebook_ignore_execution_count: self
+                .notebook_ignore
+                .contains(&NotebookField::ExecutionCount),
+            notebook_ignore_metadata: self.notebook_ignore.contains(&NotebookField::Metadata),
+            git_tags: !self.no_git,
+            git_collapse: self.git_collapse,
+        }
+    }
+
+    /// The same flags as arguments for another `watch` process.
+    fn to_args(&self) -> Vec<String> {
+        let mut args = vec![
+            format!("--window-ms={}", self.window_ms),
+            format!("--max-batch-ms={}", self.max_batch_ms),
+            format!("--max-batch-events={}", self.max_batch_events),
+            format!("--max-batch-files={}", self.max_batch_files),
+            format!("--cache-mb={}", self.cache_mb),
+        ];
+        let switches = [
+            (self.per_file, "--per-file"),
+            (self.semantic, "--semantic"),
+            (self.notebook, "--notebook"),
+            (self.no_git, "--no-git"),
+            (self.git_collapse, "--git-collapse"),
+        ];
+        for (set, flag) in switches {
+            if set {
+                args.push(flag.to_string());
+            }
+        }
+        for field in &self.notebook_ignore {
+            if let Some(value) = field.to_possible_value() {
+                args.push(format!("--notebook-ignore={}", value.get_name()));
+            }
+        }
+        args
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum NotebookField {
+    Outputs,
+    ExecutionCount,
+    Metadata,
 }
 
 #[derive(Args)]
@@ -76,6 +240,9 @@ pub struct TimelineArgs {
     pub from: Option<String>,
     #[arg(long, value_name = "RFC3339")]
     pub to: Option<String>,
+    /// Only show records made while this git branch was checked out
+    #[arg(long)]
+    pub branch: Option<String>,
     #[arg(long)]
     pub json: bool,
 }
@@ -91,6 +258,7 @@ pub struct ShowArgs {
 
 #[derive(Args)]
 pub struct DiffArgs {
+    /// Record id, tag or `now`, or FROM..TO to compare two points
     pub record_id: String,
     #[arg(short, long)]
     pub path: Option<PathBuf>,
@@ -100,6 +268,12 @@ pub struct DiffArgs {
     pub stat: bool,
     #[arg(long)]
     pub file: Option<String>,
+    #[arg(
+        long,
+        conflicts_with_all = ["json", "stat"],
+        help = "Show structural changes for JSON, TOML and YAML files"
+    )]
+    pub semantic: bool,
 }
 
 #[derive(Args)]
@@ -109,126 +283,546 @@ pub struct RestoreArgs {
     pub path: Option<PathBuf>,
     #[arg(long, help = "Apply changes instead of dry-run")]
     pub apply: bool,
+    #[arg(long, help = "Restore the state before the record instead of after it")]
+    pub revert: bool,
 }
 
 #[derive(Args)]
-pub struct StatusArgs {
+pub struct PauseArgs {
     #[arg(short, long)]
     pub path: Option<PathBuf>,
-    #[arg(long)]
+    #[arg(
+        long = "for",
+        value_name = "DURATION",
+        help = "Resume automatically after this long (e.g. 90s, 10m, 2h)"
+    )]
+    pub duration: Option<String>,
+}
+
+#[derive(Args)]
+pub struct ResumeArgs {
+    #[arg(short, long)]
+    pub path: Option<PathBuf>,
+}
+
+#[derive(Args)]
+pub struct MuteArgs {
+    #[arg(value_name = "GLOB")]
+    pub globs: Vec<String>,
+    #[arg(short, long)]
+    pub path: Option<PathBuf>,
+    #[arg(long, help = "Unmute the given globs")]
+    pub remove: bool,
+}
+
+#[derive(Args)]
+pub struct TailArgs {
+    #[arg(short, long)]
+    pub path: Option<PathBuf>,
+    #[arg(long, help = "Print each record's patch")]
+    pub patch: bool,
+    #[arg(long, help = "Print one JSON object per record")]
     pub json: bool,
+    #[arg(
+        long,
+        value_name = "GLOB",
+        help = "Only show records touching files matching this glob"
+    )]
+    pub file: Option<String>,
+    #[arg(
+        long,
+        help = "Polling interval in milliseconds when no watcher socket is available",
+        default_value_t = 500
+    )]
+    pub poll_ms: u64,
 }
 
 #[derive(Args)]
-pub struct ProjectsArgs {
+pub struct DaemonArgs {
+    #[command(subcommand)]
+    pub command: Option<DaemonCommands>,
+    #[arg(long, help = "Run the daemon in the background")]
+    pub detach: bool,
+    #[arg(
+        long,
+        help = "Worker threads shared by all projects (0 = one per CPU)",
+        default_value_t = 0
+    )]
+    pub jobs: usize,
+    #[arg(long, hide = true)]
+    pub foreground: bool,
+    #[arg(
+        long,
+        help = "Log to stderr instead of a log file, for systemd and other supervisors"
+    )]
+    pub log_stderr: bool,
+}
+
+#[derive(Subcommand)]
+pub enum DaemonCommands {
+    Stop,
+    Status(DaemonStatusArgs),
+}
+
+#[derive(Args)]
+pub struct DaemonStatusArgs {
     #[arg(long)]
     pub json: bool,
 }
 
 #[derive(Args)]
-pub struct InspectArgs {
-    #[arg(long)]
+pub struct EnableArgs {
+    #[arg(help = "Project path (defaults to CWD)")]
     pub path: Option<PathBuf>,
-    #[arg(long)]
+    // saved for the daemon; enabling again replaces them
+    #[command(flatten)]
+    pub flags: WatchFlags,
+}
+
+#[derive(Args)]
+pub struct DisableArgs {
+    #[arg(help = "Project path (defaults to CWD)")]
+    pub path: Option<PathBuf>,
+    #[arg(long, help = "Specify project-id instead of path")]
     pub project_id: Option<String>,
+}
+
+#[derive(Args)]
+pub struct ServiceArgs {
+    #[command(subcommand)]
+    pub command: ServiceCommands,
+}
+
+#[derive(Subcommand)]
+pub enum ServiceCommands {
+    Install(ServiceInstallArgs),
+    Uninstall(ServiceUninstallArgs),
+    Status(ServiceStatusArgs),
+}
+
+#[derive(Args)]
+pub struct ServiceInstallArgs {
+    #[arg(short, long, help = "Project path (defaults to CWD)")]
+    pub path: Option<PathBuf>,
+    #[arg(
+        long,
+        conflicts_with = "path",
+        help = "Run the daemon for all enabled projects instead of one watcher"
+    )]
+    pub all: bool,
+    #[arg(long, help = "Only write the unit file; do not enable or start it")]
+    pub no_start: bool,
+}
+
+#[derive(Args)]
+pub struct ServiceUninstallArgs {
+    #[arg(short, long)]
+    pub path: Option<PathBuf>,
+    #[arg(long, conflicts_with = "path")]
+    pub all: bool,
+}
+
+#[derive(Args)]
+pub struct ServiceStatusArgs {
+    #[arg(short, long)]
+    pub path: Option<PathBuf>,
+    #[arg(long, conflicts_with = "path")]
+    pub all: bool,
     #[arg(long)]
     pub json: bool,
 }
 
-#[derive(Subcommand)]
-pub enum IgnoreCommands {
-    List(IgnoreListArgs),
-    Test(IgnoreTestArgs),
+#[derive(Args)]
+pub struct RelocateArgs {
+    #[arg(help = "Old project path or project id")]
+    pub source: String,
+    #[arg(help = "Where the checkout lives now")]
+    pub new_path: PathBuf,
+    #[arg(long, help = "Combine with history already recorded for the new path")]
+    pub merge: bool,
+    #[arg(
+        long,
+        help = "Percentage of tracked files that must match the new tree",
+        default_value_t = 80,
+        value_parser = clap::value_parser!(u8).range(0..=100)
+    )]
+    pub min_match: u8,
+    #[arg(long, help = "Relocate even if the new tree does not match")]
+    pub force: bool,
 }
 
 #[derive(Args)]
-pub struct IgnoreArgs {
-    #[command(subcommand)]
-    pub command: IgnoreCommands,
+pub struct ExportArgs {
+    #[arg(short, long)]
+    pub path: Option<PathBuf>,
+    #[arg(
+        short,
+        long,
+        value_name = "FILE",
+        help = "Bundle to write, e.g. history.mdiff"
+    )]
+    pub output: PathBuf,
+    #[arg(long, value_name = "RFC3339")]
+    pub from: Option<String>,
+    #[arg(long, value_name = "RFC3339")]
+    pub to: Option<String>,
 }
 
 #[derive(Args)]
-pub struct IgnoreListArgs {
+pub struct ImportArgs {
+    #[arg(value_name = "FILE")]
+    pub bundle: PathBuf,
+    #[arg(short, long, help = "Project to import into (defaults to CWD)")]
+    pub path: Option<PathBuf>,
+}
+
+#[derive(Args)]
+pub struct ToGitArgs {
+    #[arg(short, long)]
+    pub path: Option<PathBuf>,
+    /// Branch to create, e.g. meowdiff/session-1
+    #[arg(long)]
+    pub branch: String,
+    #[arg(long, value_name = "RFC3339")]
+    pub from: Option<String>,
+    #[arg(long, value_name = "RFC3339")]
+    pub to: Option<String>,
+    /// Fold records within this window (e.g. 5m) into one commit
+    #[arg(long, value_name = "DURATION")]
+    pub squash_by: Option<String>,
+    /// Replace the branch if it already exists
+    #[arg(long)]
+    pub force: bool,
+    #[arg(long)]
+    pub json: bool,
+}
+
+#[derive(Args)]
+pub struct SearchArgs {
+    /// Regular expression matched against changed lines
+    pub pattern: String,
     #[arg(short, long)]
     pub path: Option<PathBuf>,
+    /// Only match lines the record added
+    #[arg(long, conflicts_with = "removed")]
+    pub added: bool,
+    /// Only match lines the record removed
+    #[arg(long)]
+    pub removed: bool,
+    /// Only search files matching this glob
+    #[arg(long)]
+    pub file: Option<String>,
+    #[arg(long, value_name = "RFC3339")]
+    pub from: Option<String>,
+    #[arg(long, value_name = "RFC3339")]
+    pub to: Option<String>,
     #[arg(long)]
     pub json: bool,
 }
 
 #[derive(Args)]
-pub struct IgnoreTestArgs {
+pub struct BlameArgs {
+    /// File to annotate, relative to the project root
+    pub file: String,
     #[arg(short, long)]
-    pub project: Option<PathBuf>,
-    pub target: PathBuf,
+    pub path: Option<PathBuf>,
+    #[arg(long)]
+    pub json: bool,
 }
 
 #[derive(Args)]
-pub struct ExtractArgs {
-    pub record_id: String,
+pub struct CatArgs {
+    /// File to print, relative to the project root
+    pub file: String,
+    #[arg(short, long)]
+    pub path: Option<PathBuf>,
+    /// Record id, tag or RFC3339 timestamp (defaults to the current state)
+    #[arg(long)]
+    pub at: Option<String>,
+}
+
+#[derive(Args)]
+pub struct LsArgs {
+    #[arg(short, long)]
+    pub path: Option<PathBuf>,
+    /// Record id, tag or RFC3339 timestamp (defaults to the current state)
+    #[arg(long)]
+    pub at: Option<String>,
+    #[arg(long)]
+    pub json: bool,
+}
+
+#[derive(Args)]
+pub struct SnapshotArgs {
     #[arg(short, long)]
     pub path: Option<PathBuf>,
+    /// Record id, tag or RFC3339 timestamp (defaults to the current state)
+    #[arg(long)]
+    pub at: Option<String>,
     #[arg(long, value_name = "DIR")]
     pub output: PathBuf,
     #[arg(long)]
     pub overwrite: bool,
 }
 
-pub async fn run_cli() -> Result<()> {
-    let cli = Cli::parse();
-    runtime::init_tracing(cli.verbose)?;
-    match cli.command {
-        Commands::Watch(args) => handle_watch(args).await,
-        Commands::Stop(args) => handle_stop(args),
-        Commands::Timeline(args) => handle_timeline(args),
-        Commands::Show(args) => handle_show(args),
-        Commands::Diff(args) => handle_diff(args),
-        Commands::Restore(args) => handle_restore(args),
-        Commands::Status(args) => handle_status(args),
-        Commands::Projects(args) => handle_projects(args),
-        Commands::Inspect(args) => handle_inspect(args),
-        Commands::Ignore(args) => handle_ignore(args.command),
-        Commands::Extract(args) => handle_extract(args),
-    }
+#[derive(Args)]
+pub struct NoteArgs {
+    /// Record id, tag or `now`
+    pub record_id: String,
+    /// Note to add; without it, the record's notes are listed
+    pub text: Option<String>,
+    #[arg(short, long)]
+    pub path: Option<PathBuf>,
+    #[arg(long)]
+    pub json: bool,
 }
 
-async fn handle_watch(args: WatchArgs) -> Result<()> {
-    let WatchArgs {
-        path,
-        window_ms,
-        daemon,
-        foreground,
-    } = args;
-
-    let project_root = util::resolve_project_root(path.clone())?;
-    if daemon && !foreground {
-        let exe = std::env::current_exe().context("failed to resolve current executable")?;
-        let mut cmd = Command::new(exe);
-        cmd.arg("watch")
-            .arg("--foreground")
-            .arg("--window-ms")
-            .arg(window_ms.to_string())
-            .arg("--path")
-            .arg(project_root.to_string_lossy().to_string());
-        cmd.stdin(Stdio::null())
-            .stdout(Stdio::null())
-            .stderr(Stdio::null());
-        let child = cmd.spawn().context("failed to spawn watcher daemon")?;
-        println!(
-            "Watcher daemon started (pid {}) for {}",
-            child.id(),
-            project_root.display()
-        );
-        return Ok(());
-    }
-
-    let options = WatchOptions {
-        project_root,
-        window: Duration::from_millis(window_ms),
-    };
-    watcher::watch(options).await
+#[derive(Args)]
+pub struct TagArgs {
+    /// Tag to create or move; without it, all tags are listed
+    pub name: Option<String>,
+    /// Record id, tag or `now`
+    #[arg(default_value = LATEST_REF)]
+    pub record_id: String,
+    #[arg(short, long)]
+    pub path: Option<PathBuf>,
+    /// Move the tag if it already points elsewhere
+    #[arg(long)]
+    pub force: bool,
+    #[arg(long, requires = "name", conflicts_with = "force")]
+    pub delete: bool,
+    #[arg(long)]
+    pub json: bool,
 }
 
-fn handle_timeline(args: TimelineArgs) -> Result<()> {
+#[derive(Args)]
+pub struct BookmarkArgs {
+    /// What to remember about this point
+    pub message: Option<String>,
+    #[arg(short, long)]
+    pub path: Option<PathBuf>,
+    /// Record id or tag to bookmark instead of the newest record
+    #[arg(long, default_value = LATEST_REF)]
+    pub record: String,
+    #[arg(long, conflicts_with = "message")]
+    pub list: bool,
+    #[arg(long)]
+    pub json: bool,
+}
+
+#[derive(Args)]
+pub struct RebaselineArgs {
+    #[arg(short, long)]
+    pub path: Option<PathBuf>,
+    /// Reset snapshots to this git revision
+    #[arg(long, value_name = "REV", conflicts_with = "from_tree")]
+    pub from_git: Option<String>,
+    /// Reset snapshots to the files on disk (the default)
+    #[arg(long)]
+    pub from_tree: bool,
+    #[arg(long)]
+    pub json: bool,
+}
+
+#[derive(Args)]
+pub struct StatusArgs {
+    #[arg(short, long)]
+    pub path: Option<PathBuf>,
+    #[arg(long)]
+    pub json: bool,
+}
+
+#[derive(Args)]
+pub struct ProjectsArgs {
+    #[arg(long)]
+    pub json: bool,
+    #[arg(
+        long,
+        help = "Forget projects whose root no longer exists (their history stays on disk)"
+    )]
+    pub prune: bool,
+}
+
+#[derive(Args)]
+pub struct InspectArgs {
+    #[arg(long)]
+    pub path: Option<PathBuf>,
+    #[arg(long)]
+    pub project_id: Option<String>,
+    #[arg(long)]
+    pub json: bool,
+}
+
+#[derive(Subcommand)]
+pub enum IgnoreCommands {
+    List(IgnoreListArgs),
+    Test(IgnoreTestArgs),
+}
+
+#[derive(Args)]
+pub struct IgnoreArgs {
+    #[command(subcommand)]
+    pub command: IgnoreCommands,
+}
+
+#[derive(Args)]
+pub struct IgnoreListArgs {
+    #[arg(short, long)]
+    pub path: Option<PathBuf>,
+    #[arg(long)]
+    pub json: bool,
+}
+
+#[derive(Args)]
+pub struct IgnoreTestArgs {
+    #[arg(short, long)]
+    pub project: Option<PathBuf>,
+    pub target: PathBuf,
+}
+
+#[derive(Args)]
+pub struct ExtractArgs {
+    pub record_id: String,
+    #[arg(short, long)]
+    pub path: Option<PathBuf>,
+    #[arg(long, value_name = "DIR")]
+    pub output: PathBuf,
+    #[arg(long)]
+    pub overwrite: bool,
+}
+
+pub async fn run_cli() -> Result<()> {
+    let cli = Cli::parse();
+    let log_stderr = match cli.command {
+        Commands::Watch(ref args) => args.log_stderr,
+        Commands::Daemon(ref args) => args.log_stderr,
+        _ => false,
+    };
+    // a detached watcher has no terminal, so it logs under its meta dir
+    let log_dir = match cli.command {
+        _ if log_stderr => None,
+        Commands::Watch(ref args) if args.foreground => Some(daemon_log_dir(
+            &util::resolve_project_root(args.path.clone())?,
+        )?),
+        Commands::Daemon(ref args) if args.foreground => Some(supervisor_log_dir()?),
+        _ => None,
+    };
+    let output = match log_dir {
+        _ if log_stderr => LogOutput::Stderr,
+        Some(ref dir) => LogOutput::File(dir),
+        None => LogOutput::Terminal,
+    };
+    runtime::init_tracing(cli.verbose, output)?;
+    match cli.command {
+        Commands::Watch(args) => handle_watch(args).await,
+        Commands::Stop(args) => handle_stop(args),
+        Commands::Timeline(args) => handle_timeline(args),
+        Commands::Show(args) => handle_show(args),
+        Commands::Diff(args) => handle_diff(args),
+        Commands::Restore(args) => handle_restore(args),
+        Commands::Status(args) => handle_status(args),
+        Commands::Projects(args) => handle_projects(args),
+        Commands::Inspect(args) => handle_inspect(args),
+        Commands::Ignore(args) => handle_ignore(args.command),
+        Commands::Extract(args) => handle_extract(args),
+        Commands::Pause(args) => handle_pause(args),
+        Commands::Resume(args) => handle_resume(args),
+        Commands::Mute(args) => handle_mute(args),
+        Commands::Tail(args) => handle_tail(args),
+        Commands::Daemon(args) => handle_daemon(args).await,
+        Commands::Enable(args) => handle_enable(args),
+        Commands::Disable(args) => handle_disable(args),
+        Commands::Service(args) => handle_service(args.command),
+        Commands::Relocate(args) => handle_relocate(args),
+        Commands::Export(args) => handle_export(args),
+        Commands::Import(args) => handle_import(args),
+        Commands::ToGit(args) => handle_to_git(args),
+        Commands::Rebaseline(args) => handle_rebaseline(args),
+        Commands::Search(args) => handle_search(args),
+        Commands::Blame(args) => handle_blame(args),
+        Commands::Cat(args) => handle_cat(args),
+        Commands::Ls(args) => handle_ls(args),
+        Commands::Snapshot(args) => handle_snapshot(args),
+        Commands::Note(args) => handle_note(args),
+        Commands::Tag(args) => handle_tag(args),
+        Commands::Bookmark(args) => handle_bookmark(args),
+    }
+}
+
+async fn handle_watch(args: WatchArgs) -> Result<()> {
+    // set when this process is the detached child of `watch --daemon`
+    let ready = ReadySignal::from_env();
+    let result = start_watch(args, ready.clone()).await;
+    if let Err(ref err) = result {
+        ready.failed(err);
+    }
+    result
+}
+
+async fn start_watch(args: WatchArgs, ready: ReadySignal) -> Result<()> {
+    let WatchArgs {
+        path,
+        flags,
+        jobs,
+        baseline_from,
+        daemon,
+        foreground,
+        // read by run_cli when setting up logging
+        log_stderr: _,
+    } = args;
+
+    let project_root = util::resolve_project_root(path.clone())?;
+    if daemon && !foreground {
+        let exe = std::env::current_exe().context("failed to resolve current executable")?;
+        let mut cmd = Command::new(exe);
+        cmd.arg("watch")
+            .arg("--foreground")
+            .args(flags.to_args())
+            .arg("--jobs")
+            .arg(jobs.to_string())
+            .arg("--path")
+            .arg(project_root.to_string_lossy().to_string());
+        if let Some(rev) = &baseline_from {
+            cmd.arg("--baseline-from").arg(rev);
+        }
+        #[cfg(unix)]
+        {
+            let log_dir = daemon_log_dir(&project_root)?;
+            let pid = runtime::spawn_detached(cmd, &log_dir, DAEMON_READY_TIMEOUT)?;
+            println!(
+                "Watcher daemon started (pid {}) for {}",
+                pid,
+                project_root.display()
+            );
+            println!("Log: {}", log_dir.join(runtime::CURRENT_LOG).display());
+        }
+        #[cfg(not(unix))]
+        {
+            use std::process::Stdio;
+            cmd.stdin(Stdio::null())
+                .stdout(Stdio::null())
+                .stderr(Stdio::null());
+            let child = cmd.spawn().context("failed to spawn watcher daemon")?;
+            println!(
+                "Watcher daemon started (pid {}) for {}",
+                child.id(),
+                project_root.display()
+            );
+        }
+        return Ok(());
+    }
+
+    let options = WatchOptions {
+        project_root,
+        jobs,
+        ready,
+        baseline: baseline_from.map_or(BaselineSource::WorkingTree, BaselineSource::Git),
+        ..flags.settings().options()
+    };
+    watcher::watch(options).await
+}
+
+fn handle_timeline(args: TimelineArgs) -> Result<()> {
     let storage = open_storage(args.path)?;
     let from_ts = match args.from {
         Some(ref ts) => Some(parse_datetime(ts)?),
@@ -238,176 +832,1514 @@ fn handle_timeline(args: TimelineArgs) -> Result<()> {
         Some(ref ts) => Some(parse_datetime(ts)?),
         None => None,
     };
-    let entries = storage.timeline(args.limit, from_ts, to_ts)?;
+    let entries = storage.timeline(&TimelineFilter {
+        limit: args.limit,
+        from: from_ts,
+        to: to_ts,
+        branch: args.branch,
+    })?;
     if args.json {
         println!("{}", serde_json::to_string_pretty(&entries)?);
     } else {
-        print_timeline(&entries);
+        print_timeline(&entries);
+    }
+    Ok(())
+}
+
+fn handle_show(args: ShowArgs) -> Result<()> {
+    let storage = open_storage(args.path)?;
+    let record_id = storage.resolve_record(&args.record_id)?;
+    let meta = storage.read_record_meta(&record_id)?;
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&meta)?);
+    } else {
+        println!("Record: {}", meta.record_id);
+        println!("Project: {}", meta.project_id);
+        println!("Started: {}", meta.started_at);
+        println!("Ended:   {}", meta.ended_at);
+        if let Some(prev) = meta.prev_record_id {
+            println!("Previous: {}", prev);
+        }
+        if let Some(ref label) = meta.label {
+            println!("Label: {}", label);
+        }
+        let tags: Vec<String> = storage
+            .tags()?
+            .into_iter()
+            .filter(|tag| tag.record_id == record_id)
+            .map(|tag| tag.name)
+            .collect();
+        if !tags.is_empty() {
+            println!("Tags: {}", tags.join(", "));
+        }
+        for note in storage.notes(&record_id)? {
+            println!("Note: {}", note.text);
+        }
+        if let Some(ref git) = meta.git {
+            println!(
+                "Git: {} at {}{}",
+                git.branch.as_deref().unwrap_or("(detached)"),
+                git.head.as_deref().map(git::short_sha).unwrap_or("-"),
+                git.operation
+                    .map(|op| format!(" ({op:?} in progress)"))
+                    .unwrap_or_default()
+            );
+        }
+        println!(
+            "Stats: files={}, +{}, -{}",
+            meta.stats.files, meta.stats.lines_added, meta.stats.lines_removed
+        );
+        println!("Files:");
+        for file in meta.files {
+            match (file.before_mode, file.after_mode, &file.symlink_target) {
+                (_, _, Some(target)) => {
+                    println!("  - {} ({:?}) -> {}", file.path, file.op, target)
+                }
+                (Some(old), Some(new), None) if old != new => {
+                    println!(
+                        "  - {} ({:?}) mode {:o} -> {:o}",
+                        file.path, file.op, old, new
+                    )
+                }
+                _ => println!("  - {} ({:?})", file.path, file.op),
+            }
+        }
+    }
+    Ok(())
+}
+
+fn handle_diff(args: DiffArgs) -> Result<()> {
+    let DiffArgs {
+        record_id,
+        path,
+        json,
+        stat,
+        file,
+        semantic,
+    } = args;
+
+    let storage = open_storage(path)?;
+    if let Some((from, to)) = record_id.split_once("..") {
+        let from = storage.resolve_record(from)?;
+        let to = storage.resolve_record(to)?;
+        return print_range_diff(&storage, &from, &to, file, stat, json, semantic);
+    }
+    let record_id = storage.resolve_record(&record_id)?;
+    let meta = storage.read_record_meta(&record_id)?;
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&meta.files)?);
+        return Ok(());
+    }
+
+    if stat {
+        println!("Diff summary for record {}:", record_id);
+        for entry in &meta.files {
+            println!(
+                "  - {:<40} {:>5} added {:>5} removed",
+                entry.path, entry.stats.added, entry.stats.removed
+            );
+            if let Some(ref cells) = entry.stats.cells {
+                if cells.ignored_only {
+                    println!("    cells: ignored fields only");
+                } else {
+                    println!(
+                        "    cells: {} added, {} removed, {} modified",
+                        cells.added, cells.removed, cells.modified
+                    );
+                }
+            }
+        }
+        println!(
+            "Totals: files={} +{} -{}",
+            meta.stats.files, meta.stats.lines_added, meta.stats.lines_removed
+        );
+        return Ok(());
+    }
+
+    let compressed = storage.read_patch(&record_id)?;
+    let mut patch = decompress_patch(&compressed)?;
+
+    if semantic {
+        let structural = storage.read_semantic(&record_id)?;
+        let mut output = String::new();
+        for entry in &meta.files {
+            if file.as_ref().is_some_and(|filter| filter != &entry.path) {
+                continue;
+            }
+            // files without a structural diff (unknown format, parse error) fall back to lines
+            match structural.iter().find(|diff| diff.path == entry.path) {
+                Some(diff) => output.push_str(&render_semantic(diff)),
+                None => output.push_str(&filter_patch_for_file(&patch, &entry.path)),
+            }
+            if !output.ends_with('\n') {
+                output.push('\n');
+            }
+        }
+        print!("{}", colorize_patch(&output));
+        return Ok(());
+    }
+    if let Some(filter) = file {
+        patch = filter_patch_for_file(&patch, &filter);
+        if patch.trim().is_empty() {
+            println!("No diff found for {} in record {}", filter, record_id);
+            return Ok(());
+        }
+    }
+
+    print!("{}", colorize_patch(&patch));
+    Ok(())
+}
+
+/// Diff between the trees right after two records, rendered like a
+/// record's own diff.
+fn print_range_diff(
+    storage: &StorageEngine,
+    from: &str,
+    to: &str,
+    file: Option<String>,
+    stat: bool,
+    json: bool,
+    semantic: bool,
+) -> Result<()> {
+    let options = DiffOptions {
+        semantic,
+        ..Default::default()
+    };
+    let artifacts: Vec<_> = storage
+        .diff_records(from, to, &options)?
+        .into_iter()
+        .filter(|artifact| {
+            file.as_ref()
+                .is_none_or(|file| file == &artifact.record.path)
+        })
+        .collect();
+    if json {
+        let files: Vec<_> = artifacts.iter().map(|artifact| &artifact.record).collect();
+        println!("{}", serde_json::to_string_pretty(&files)?);
+        return Ok(());
+    }
+    if artifacts.is_empty() {
+        println!("No differences between {from} and {to}");
+        return Ok(());
+    }
+    if stat {
+        println!("Diff summary for {from}..{to}:");
+        let (mut added, mut removed) = (0, 0);
+        for artifact in &artifacts {
+            let stats = &artifact.record.stats;
+            println!(
+                "  - {:<40} {:>5} added {:>5} removed",
+                artifact.record.path, stats.added, stats.removed
+            );
+            added += stats.added;
+            removed += stats.removed;
+        }
+        println!("Totals: files={} +{added} -{removed}", artifacts.len());
+        return Ok(());
+    }
+    let mut output = String::new();
+    for artifact in &artifacts {
+        match artifact.semantic.as_ref().filter(|_| semantic) {
+            Some(diff) => output.push_str(&render_semantic(diff)),
+            None => output.push_str(&artifact.patch),
+        }
+        if !output.ends_with('\n') {
+            output.push('\n');
+        }
+        output.push('\n');
+    }
+    print!("{}", colorize_patch(&output));
+    Ok(())
+}
+
+fn handle_restore(args: RestoreArgs) -> Result<()> {
+    let RestoreArgs {
+        record_id,
+        path,
+        apply,
+        revert,
+    } = args;
+    let storage = open_storage(path.clone())?;
+    let project_root = util::resolve_project_root(path)?;
+    let record_id = storage.resolve_record(&record_id)?;
+    let meta = storage.read_record_meta(&record_id)?;
+    if !apply {
+        let dirs = meta
+            .files
+            .iter()
+            .filter(|file| matches!(file.op, FileOp::DirCreated | FileOp::DirRemoved))
+            .count();
+        let files = meta.files.len() - dirs;
+        if dirs == 0 {
+            println!("Would restore {files} files:");
+        } else {
+            println!("Would restore {files} files and {dirs} directories:");
+        }
+        for file in &meta.files {
+            match file.op {
+                FileOp::DirCreated | FileOp::DirRemoved => println!("  - {}/", file.path),
+                _ => println!("  - {}", file.path),
+            }
+        }
+        println!("Use --apply to write changes to disk.");
+        return Ok(());
+    }
+    // directories are created first; files are removed before directories go
+    // and written last, so an entry can take the place of a removed directory
+    let mut create_dirs = Vec::new();
+    let mut remove_dirs = Vec::new();
+    let mut removals = Vec::new();
+    let mut writes = Vec::new();
+    for file in &meta.files {
+        match (&file.op, revert) {
+            (FileOp::DirCreated, false) | (FileOp::DirRemoved, true) => create_dirs.push(file),
+            (FileOp::DirRemoved, false) | (FileOp::DirCreated, true) => remove_dirs.push(file),
+            _ => {
+                let (sha, mode) = if revert {
+                    (&file.before_sha, file.before_mode)
+                } else {
+                    (&file.after_sha, file.after_mode)
+                };
+                match sha {
+                    Some(sha) => writes.push((file, sha, mode)),
+                    None => removals.push(file),
+                }
+            }
+        }
+    }
+    if revert {
+        remove_dirs.reverse();
+    }
+    for dir in create_dirs {
+        util::ensure_dir(&project_root.join(&dir.path))?;
+    }
+    for file in removals {
+        let target = project_root.join(&file.path);
+        if target.symlink_metadata().is_ok() {
+            std::fs::remove_file(&target)
+                .with_context(|| format!("failed to remove {}", target.display()))?;
+        }
+    }
+    for dir in remove_dirs {
+        let target = project_root.join(&dir.path);
+        if target.is_dir() {
+            if let Err(err) = fs::remove_dir(&target) {
+                println!("  ! kept {}: {}", target.display(), err);
+            }
+        }
+    }
+    for (file, sha, mode) in writes {
+        let data = storage.read_blob(sha)?;
+        util::write_worktree_entry(&project_root.join(&file.path), &data, mode)?;
+    }
+    if revert {
+        println!("Reverted record {}", meta.record_id);
+    } else {
+        println!("Restored record {}", meta.record_id);
+    }
+    Ok(())
+}
+
+fn handle_stop(args: StopArgs) -> Result<()> {
+    let StopArgs {
+        path,
+        project_id,
+        force,
+    } = args;
+
+    let (project_id, meta_dir) = if let Some(path) = path {
+        let root = util::resolve_project_root(Some(path))?;
+        let project_id = util::compute_project_id(&root)?;
+        let meta_dir = util::meowdiff_root()?.join(&project_id).join("meta");
+        (project_id, meta_dir)
+    } else if let Some(requested_id) = project_id {
+        let entry = find_project_entry(&requested_id)?
+            .ok_or_else(|| anyhow!("project {requested_id} not found in registry"))?;
+        let meta_dir = util::meowdiff_root()?.join(&entry.project_id).join("meta");
+        (entry.project_id, meta_dir)
+    } else {
+        let root = util::resolve_project_root(None)?;
+        let project_id = util::compute_project_id(&root)?;
+        let meta_dir = util::meowdiff_root()?.join(&project_id).join("meta");
+        (project_id, meta_dir)
+    };
+
+    let lock_info = match WatchLock::read(&meta_dir)? {
+        Some(info) => info,
+        None => {
+            println!("No active watcher for project {project_id}");
+            return Ok(());
+        }
+    };
+
+    if lock_info.supervised && is_process_alive(lock_info.pid) {
+        // the pid is the daemon's; only this project's watcher should go,
+        // and the flag keeps the daemon from starting it again
+        set_project_stopped(&project_id, true)?;
+        stop_supervised(&lock_info)?;
+        println!(
+            "Stopped watcher for project {project_id} in daemon pid {}",
+            lock_info.pid
+        );
+        println!("The daemon leaves it stopped, even after a restart, until `meowdiff enable`");
+        return Ok(());
+    }
+
+    if is_process_alive(lock_info.pid) {
+        send_terminate(lock_info.pid)?;
+        println!("Sent SIGTERM to watcher pid {}", lock_info.pid);
+    } else if !force {
+        println!(
+            "Watcher process {} not running; use --force to clear lock",
+            lock_info.pid
+        );
+        return Ok(());
+    } else {
+        println!("Removing stale lock for project {project_id}");
+    }
+
+    let lock_path = WatchLock::path(&meta_dir);
+    fs::remove_file(&lock_path).ok();
+    println!("Stopped watcher for project {project_id}");
+    Ok(())
+}
+
+#[cfg(unix)]
+fn stop_supervised(info: &LockInfo) -> Result<()> {
+    let socket = info
+        .socket
+        .as_ref()
+        .ok_or_else(|| anyhow!("supervised watcher has no control socket"))?;
+    watcher::rpc_call(socket, "shutdown", serde_json::Value::Null)?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn stop_supervised(_info: &LockInfo) -> Result<()> {
+    bail!("stopping a single project inside the daemon needs a control socket; use `meowdiff disable`")
+}
+
+async fn handle_daemon(args: DaemonArgs) -> Result<()> {
+    let DaemonArgs {
+        command,
+        detach,
+        jobs,
+        foreground,
+        log_stderr: _,
+    } = args;
+
+    match command {
+        Some(DaemonCommands::Stop) => return handle_daemon_stop(),
+        Some(DaemonCommands::Status(args)) => return handle_daemon_status(args),
+        None => {}
+    }
+
+    if detach && !foreground {
+        let exe = std::env::current_exe().context("failed to resolve current executable")?;
+        let mut cmd = Command::new(exe);
+        cmd.arg("daemon")
+            .arg("--foreground")
+            .arg("--jobs")
+            .arg(jobs.to_string());
+        #[cfg(unix)]
+        {
+            let log_dir = supervisor_log_dir()?;
+            let pid = runtime::spawn_detached(cmd, &log_dir, DAEMON_READY_TIMEOUT)?;
+            println!("Daemon started (pid {pid})");
+            println!("Log: {}", log_dir.join(runtime::CURRENT_LOG).display());
+        }
+        #[cfg(not(unix))]
+        {
+            use std::process::Stdio;
+            cmd.stdin(Stdio::null())
+                .stdout(Stdio::null())
+                .stderr(Stdio::null());
+            let child = cmd.spawn().context("failed to spawn daemon")?;
+            println!("Daemon started (pid {})", child.id());
+        }
+        return Ok(());
+    }
+
+    let ready = ReadySignal::from_env();
+    let enabled = read_registry_global()?
+        .into_iter()
+        .filter(|entry| entry.enabled)
+        .count();
+    if !foreground {
+        println!("Daemon watching {enabled} enabled project(s); Ctrl+C to stop");
+    }
+    let result = watcher::supervise(SupervisorOptions {
+        jobs,
+        ready: ready.clone(),
+    })
+    .await;
+    if let Err(ref err) = result {
+        ready.failed(err);
+    }
+    result
+}
+
+fn handle_daemon_stop() -> Result<()> {
+    let dir = watcher::supervisor_dir()?;
+    match WatchLock::read(&dir)? {
+        Some(info) if is_process_alive(info.pid) => {
+            send_terminate(info.pid)?;
+            println!("Sent SIGTERM to daemon pid {}", info.pid);
+        }
+        Some(info) => {
+            fs::remove_file(WatchLock::path(&dir)).ok();
+            println!("Daemon pid {} not running; removed stale lock", info.pid);
+        }
+        None => println!("Daemon is not running"),
+    }
+    Ok(())
+}
+
+fn handle_daemon_status(args: DaemonStatusArgs) -> Result<()> {
+    let daemon = running_daemon()?;
+    let projects: Vec<_> = read_registry_global()?
+        .into_iter()
+        .filter(|entry| entry.enabled)
+        .map(|entry| {
+            let state = WatcherState::of(&entry.project_id)?;
+            Ok((entry, state))
+        })
+        .collect::<Result<_>>()?;
+
+    if args.json {
+        let payload = json!({
+            "running": daemon.is_some(),
+            "pid": daemon.as_ref().map(|info| info.pid),
+            "started_at": daemon.as_ref().map(|info| info.started_at),
+            "projects": projects
+                .iter()
+                .map(|(entry, state)| json!({
+                    "project_id": entry.project_id,
+                    "path": entry.path,
+                    "stopped": entry.stopped,
+                    "watcher": state.to_json(),
+                }))
+                .collect::<Vec<_>>(),
+        });
+        println!("{}", serde_json::to_string_pretty(&payload)?);
+        return Ok(());
+    }
+
+    match daemon {
+        Some(info) => println!(
+            "Daemon: running (pid {}, since {})",
+            info.pid, info.started_at
+        ),
+        None => println!("Daemon: not running"),
+    }
+    if projects.is_empty() {
+        println!("No enabled projects; add one with `meowdiff enable <path>`");
+    } else {
+        println!("Enabled projects:");
+        for (entry, state) in projects {
+            if entry.stopped {
+                println!(
+                    "  - {} ({}): stopped until `meowdiff enable`",
+                    entry.project_id, entry.path
+                );
+            } else {
+                println!("  - {} ({}): {}", entry.project_id, entry.path, state);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn handle_enable(args: EnableArgs) -> Result<()> {
+    // opening the store registers the project if it is new
+    let storage = open_storage(args.path)?;
+    args.flags.settings().save(&storage.paths().meta_dir)?;
+    let entry = set_project_enabled(storage.project_id(), true)?;
+    println!("Enabled {} ({})", entry.project_id, entry.path);
+    if let Some(info) = WatchLock::read(&storage.paths().meta_dir)? {
+        if !info.supervised && is_process_alive(info.pid) {
+            println!(
+                "A standalone watcher (pid {}) holds this project; the daemon takes over once it stops",
+                info.pid
+            );
+        }
+    }
+    if running_daemon()?.is_none() {
+        println!("Daemon is not running; start it with `meowdiff daemon --detach`");
+    }
+    Ok(())
+}
+
+fn handle_disable(args: DisableArgs) -> Result<()> {
+    let DisableArgs { path, project_id } = args;
+    let project_id = match project_id {
+        Some(project_id) => project_id,
+        None => util::compute_project_id(&util::resolve_project_root(path)?)?,
+    };
+    let entry = find_project_entry(&project_id)?
+        .ok_or_else(|| anyhow!("project {project_id} not found in registry"))?;
+    if !entry.enabled {
+        println!("Project {} is not enabled", entry.project_id);
+        return Ok(());
+    }
+    let entry = set_project_enabled(&entry.project_id, false)?;
+    println!("Disabled {} ({})", entry.project_id, entry.path);
+    if running_daemon()?.is_some() {
+        println!("The daemon will stop watching it shortly");
+    }
+    Ok(())
+}
+
+fn handle_service(cmd: ServiceCommands) -> Result<()> {
+    match cmd {
+        ServiceCommands::Install(args) => {
+            let ServiceInstallArgs {
+                path,
+                all,
+                no_start,
+            } = args;
+            let target = service_target(path, all)?;
+            if let ServiceTarget::Project { ref root, .. } = target {
+                // registers the project so `projects` lists it
+                open_storage(Some(root.clone()))?;
+            }
+            let unit = target.unit_name();
+            let exe = std::env::current_exe().context("failed to resolve current executable")?;
+            let dir = runtime::unit_dir()?;
+            util::ensure_dir(&dir)?;
+            let unit_path = dir.join(&unit);
+            fs::write(&unit_path, runtime::render_unit(&exe, &target))
+                .with_context(|| format!("failed to write {}", unit_path.display()))?;
+            println!("Wrote {}", unit_path.display());
+            if all && !read_registry_global()?.iter().any(|entry| entry.enabled) {
+                println!("No projects are enabled yet; add them with `meowdiff enable <path>`");
+            }
+            if no_start {
+                println!("Start it with `systemctl --user daemon-reload && systemctl --user enable --now {unit}`");
+                return Ok(());
+            }
+            let started =
+                systemctl(&["daemon-reload"]).and_then(|_| systemctl(&["enable", "--now", &unit]));
+            match started {
+                Ok(()) => println!("Enabled and started {unit}"),
+                Err(err) => println!(
+                    "Could not start {unit}: {err:#}\nRun `systemctl --user daemon-reload && systemctl --user enable --now {unit}` once systemd is available"
+                ),
+            }
+        }
+        ServiceCommands::Uninstall(args) => {
+            let ServiceUninstallArgs { path, all } = args;
+            let unit = service_target(path, all)?.unit_name();
+            let unit_path = runtime::unit_dir()?.join(&unit);
+            if !unit_path.exists() {
+                println!("{unit} is not installed");
+                return Ok(());
+            }
+            if let Err(err) = systemctl(&["disable", "--now", &unit]) {
+                tracing::warn!(error = %err, "failed to stop {unit}");
+            }
+            fs::remove_file(&unit_path)
+                .with_context(|| format!("failed to remove {}", unit_path.display()))?;
+            if let Err(err) = systemctl(&["daemon-reload"]) {
+                tracing::debug!(error = %err, "daemon-reload failed");
+            }
+            println!("Removed {}", unit_path.display());
+        }
+        ServiceCommands::Status(args) => {
+            let ServiceStatusArgs { path, all, json } = args;
+            let unit = service_target(path, all)?.unit_name();
+            let status = ServiceStatus::of(&unit)?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&status.to_json())?);
+            } else if !status.installed {
+                println!("{unit} is not installed");
+            } else {
+                println!("Unit: {}", status.path.display());
+                println!("Active: {}", status.active.as_deref().unwrap_or("unknown"));
+                println!(
+                    "Enabled: {}",
+                    status.enabled.as_deref().unwrap_or("unknown")
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Names the unit without opening the store, so `status` and `uninstall`
+/// leave no trace behind.
+fn service_target(path: Option<PathBuf>, all: bool) -> Result<ServiceTarget> {
+    if all {
+        return Ok(ServiceTarget::Daemon);
+    }
+    let root = util::resolve_project_root(path)?;
+    Ok(ServiceTarget::Project {
+        project_id: util::compute_project_id(&root)?,
+        root,
+    })
+}
+
+fn systemctl(args: &[&str]) -> Result<()> {
+    let output = Command::new("systemctl")
+        .arg("--user")
+        .args(args)
+        .output()
+        .context("failed to run systemctl")?;
+    if !output.status.success() {
+        bail!(
+            "systemctl --user {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
+
+/// Installed state of a generated unit, as far as systemd will tell us.
+struct ServiceStatus {
+    unit: String,
+    path: PathBuf,
+    installed: bool,
+    active: Option<String>,
+    enabled: Option<String>,
+}
+
+impl ServiceStatus {
+    fn of(unit: &str) -> Result<Self> {
+        let path = runtime::unit_dir()?.join(unit);
+        let installed = path.exists();
+        // is-active/is-enabled exit non-zero for inactive units but still
+        // print the state
+        let query = |verb: &str| {
+            let output = Command::new("systemctl")
+                .args(["--user", verb, unit])
+                .output()
+                .ok()?;
+            let state = String::from_utf8_lossy(&output.stdout).trim().to_string();
+            (!state.is_empty()).then_some(state)
+        };
+        let (active, enabled) = if installed {
+            (query("is-active"), query("is-enabled"))
+        } else {
+            (None, None)
+        };
+        Ok(Self {
+            unit: unit.to_string(),
+            path,
+            installed,
+            active,
+            enabled,
+        })
+    }
+
+    fn to_json(&self) -> serde_json::Value {
+        json!({
+            "unit": self.unit,
+            "path": self.path,
+            "installed": self.installed,
+            "active": self.active,
+            "enabled": self.enabled,
+        })
+    }
+}
+
+fn running_daemon() -> Result<Option<LockInfo>> {
+    let info = WatchLock::read(&watcher::supervisor_dir()?)?;
+    Ok(info.filter(|info| is_process_alive(info.pid)))
+}
+
+/// Liveness of a project's watcher, from its lock file.
+enum WatcherState {
+    Idle,
+    Running(LockInfo),
+    Stale(LockInfo),
+}
+
+impl WatcherState {
+    fn of(project_id: &str) -> Result<Self> {
+        let meta_dir = util::meowdiff_root()?.join(project_id).join("meta");
+        Ok(match WatchLock::read(&meta_dir)? {
+            None => Self::Idle,
+            Some(info) if is_process_alive(info.pid) => Self::Running(info),
+            Some(info) => Self::Stale(info),
+        })
+    }
+
+    fn to_json(&self) -> serde_json::Value {
+        match self {
+            Self::Idle => json!({ "state": "idle" }),
+            Self::Running(info) => json!({
+                "state": "running",
+                "pid": info.pid,
+                "supervised": info.supervised,
+                "started_at": info.started_at,
+            }),
+            Self::Stale(info) => json!({ "state": "stale", "pid": info.pid }),
+        }
+    }
+}
+
+impl std::fmt::Display for WatcherState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Idle => write!(f, "idle"),
+            Self::Running(info) if info.supervised => {
+                write!(f, "watched by daemon (pid {})", info.pid)
+            }
+            Self::Running(info) => write!(f, "watching (pid {})", info.pid),
+            Self::Stale(info) => write!(f, "stale lock (pid {} not running)", info.pid),
+        }
+    }
+}
+
+fn handle_relocate(args: RelocateArgs) -> Result<()> {
+    let RelocateArgs {
+        source,
+        new_path,
+        merge,
+        min_match,
+        force,
+    } = args;
+
+    let root = util::meowdiff_root()?;
+    let (old_id, old_path) = resolve_relocate_source(&source)?;
+    let new_root = util::resolve_project_root(Some(new_path))?;
+    let new_id = util::compute_project_id(&new_root)?;
+    for project_id in [&old_id, &new_id] {
+        if let Some(info) = WatchLock::read(&root.join(project_id).join("meta"))? {
+            if is_process_alive(info.pid) {
+                bail!(
+                    "a watcher (pid {}) is running for project {project_id}; stop it first",
+                    info.pid
+                );
+            }
+        }
+    }
+
+    let options = RelocateOptions {
+        merge,
+        min_match,
+        force,
+    };
+    let relocation = relocate(&root, &old_id, &new_root, options)?;
+    println!("{}: {}", new_root.display(), relocation.check);
+    if relocation.merged {
+        println!(
+            "Merged {} record(s) from {old_id} into {}",
+            relocation.records, relocation.new_id
+        );
+    } else {
+        println!(
+            "Moved {} record(s) from {old_id} to {}",
+            relocation.records, relocation.new_id
+        );
+    }
+    println!(
+        "History for {} now follows {}",
+        old_path.as_deref().unwrap_or(&old_id),
+        new_root.display()
+    );
+    Ok(())
+}
+
+/// Finds the project a `relocate` source names: a project id, a registered
+/// path (which may no longer exist), or an existing checkout.
+fn resolve_relocate_source(source: &str) -> Result<(String, Option<String>)> {
+    if let Some(entry) = find_project_entry(source)? {
+        return Ok((entry.project_id, Some(entry.path)));
+    }
+    let path = Path::new(source);
+    let absolute = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        std::env::current_dir()?.join(path)
+    };
+    let candidates = [
+        absolute.to_string_lossy().into_owned(),
+        absolute
+            .canonicalize()
+            .map(|path| path.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+    ];
+    if let Some(entry) = read_registry_global()?
+        .into_iter()
+        .find(|entry| candidates.contains(&entry.path))
+    {
+        return Ok((entry.project_id, Some(entry.path)));
+    }
+    if absolute.exists() {
+        let project_id = util::compute_project_id(&absolute)?;
+        return Ok((project_id, Some(absolute.to_string_lossy().into_owned())));
+    }
+    bail!("no project found for {source}")
+}
+
+fn handle_export(args: ExportArgs) -> Result<()> {
+    let ExportArgs {
+        path,
+        output,
+        from,
+        to,
+    } = args;
+    let storage = open_storage(path)?;
+    let filter = TimelineFilter {
+        from: from.as_deref().map(parse_datetime).transpose()?,
+        to: to.as_deref().map(parse_datetime).transpose()?,
+        ..Default::default()
+    };
+    let manifest = storage.export_bundle(&filter, &output)?;
+    println!(
+        "Exported {} record(s) and {} blob(s) to {}",
+        manifest.records.len(),
+        manifest.blobs,
+        output.display()
+    );
+    Ok(())
+}
+
+fn handle_import(args: ImportArgs) -> Result<()> {
+    let ImportArgs { bundle, path } = args;
+    let storage = open_storage(path)?;
+    let (manifest, added) = storage.import_bundle(&bundle)?;
+    println!(
+        "Imported {added} of {} record(s) exported from {} at {}",
+        manifest.records.len(),
+        manifest.source_path,
+        manifest.exported_at
+    );
+    let skipped = manifest.records.len() - added;
+    if skipped > 0 {
+        println!("{skipped} record(s) were already present");
+    }
+    Ok(())
+}
+
+fn handle_to_git(args: ToGitArgs) -> Result<()> {
+    let ToGitArgs {
+        path,
+        branch,
+        from,
+        to,
+        squash_by,
+        force,
+        json,
+    } = args;
+    let storage = open_storage(path)?;
+    let options = git::ReplayOptions {
+        branch,
+        filter: TimelineFilter {
+            from: from.as_deref().map(parse_datetime).transpose()?,
+            to: to.as_deref().map(parse_datetime).transpose()?,
+            ..Default::default()
+        },
+        squash_by: squash_by.as_deref().map(parse_duration).transpose()?,
+        force,
+    };
+    let summary = git::replay_to_git(&storage, &options)?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&summary)?);
+        return Ok(());
+    }
+    println!(
+        "Replayed {} record(s) as {} commit(s) onto {} ({})",
+        summary.records,
+        summary.commits,
+        summary.branch,
+        git::short_sha(&summary.head)
+    );
+    if let Some(base) = summary.base {
+        println!("Branch starts from {}", git::short_sha(&base));
+    }
+    println!("Review with: git log --stat {}", summary.branch);
+    Ok(())
+}
+
+fn handle_search(args: SearchArgs) -> Result<()> {
+    let SearchArgs {
+        pattern,
+        path,
+        added,
+        removed,
+        file,
+        from,
+        to,
+        json,
+    } = args;
+    let pattern = Regex::new(&pattern).map_err(|err| anyhow!("invalid pattern: {err}"))?;
+    let storage = open_storage(path)?;
+    let files = match file {
+        Some(glob) => Some(PathGlobs::new(storage.project_root(), &[glob])?),
+        None => None,
+    };
+    let side = match (added, removed) {
+        (true, _) => LineSide::Added,
+        (_, true) => LineSide::Removed,
+        _ => LineSide::Either,
+    };
+    let hits = storage.search(&SearchQuery {
+        pattern: &pattern,
+        side,
+        files: files.as_ref(),
+        filter: TimelineFilter {
+            from: from.as_deref().map(parse_datetime).transpose()?,
+            to: to.as_deref().map(parse_datetime).transpose()?,
+            ..Default::default()
+        },
+    })?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&hits)?);
+        return Ok(());
+    }
+    if hits.is_empty() {
+        println!("No matching changes");
+        return Ok(());
+    }
+    for hit in hits {
+        let label = hit
+            .label
+            .as_deref()
+            .map(|label| format!("  [{label}]"))
+            .unwrap_or_default();
+        println!("{} {} {}{}", hit.record_id, hit.timestamp, hit.path, label);
+        print!("{}", colorize_patch(&hit.hunk));
+        println!();
+    }
+    Ok(())
+}
+
+fn handle_blame(args: BlameArgs) -> Result<()> {
+    let BlameArgs { file, path, json } = args;
+    let storage = open_storage(path)?;
+    let lines = storage.blame(file.trim_start_matches("./"))?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&lines)?);
+        return Ok(());
+    }
+    let number_width = lines.len().to_string().len();
+    let label_width = lines
+        .iter()
+        .filter_map(|line| line.label.as_deref())
+        .map(|label| label.chars().count() + 3)
+        .max()
+        .unwrap_or(0);
+    for line in lines {
+        let origin = match (&line.record_id, line.timestamp) {
+            (Some(id), Some(ts)) => format!("{id:<12} {}", ts.format("%Y-%m-%d %H:%M:%S")),
+            _ => format!("{:<12} {:<19}", "baseline", ""),
+        };
+        let label = line
+            .label
+            .as_deref()
+            .map(|label| format!(" [{label}]"))
+            .unwrap_or_default();
+        println!(
+            "{origin}{label:<label_width$} {:>number_width$}) {}",
+            line.line, line.content
+        );
+    }
+    Ok(())
+}
+
+fn handle_cat(args: CatArgs) -> Result<()> {
+    let CatArgs { file, path, at } = args;
+    let storage = open_storage(path)?;
+    let file = file.trim_start_matches("./");
+    let tree = tree_at_point(&storage, at.as_deref())?;
+    let Some(entry) = tree.get(file) else {
+        match at {
+            Some(at) => bail!("{file} did not exist at {at}"),
+            None => bail!("{file} is not tracked"),
+        }
+    };
+    std::io::stdout().write_all(&storage.read_blob(&entry.sha)?)?;
+    Ok(())
+}
+
+fn handle_ls(args: LsArgs) -> Result<()> {
+    let LsArgs { path, at, json } = args;
+    let storage = open_storage(path)?;
+    let tree = tree_at_point(&storage, at.as_deref())?;
+    if json {
+        let files: Vec<_> = tree
+            .iter()
+            .map(|(path, file)| json!({ "path": path, "sha": file.sha, "mode": file.mode }))
+            .collect();
+        println!("{}", serde_json::to_string_pretty(&files)?);
+        return Ok(());
+    }
+    for (path, file) in &tree {
+        let mode = file
+            .mode
+            .map(|mode| format!("{mode:06o}"))
+            .unwrap_or_else(|| "-".repeat(6));
+        println!("{mode} {} {path}", &file.sha[..12.min(file.sha.len())]);
+    }
+    Ok(())
+}
+
+fn handle_snapshot(args: SnapshotArgs) -> Result<()> {
+    let SnapshotArgs {
+        path,
+        at,
+        output,
+        overwrite,
+    } = args;
+    let storage = open_storage(path)?;
+    let tree = tree_at_point(&storage, at.as_deref())?;
+    // refuse before writing anything rather than leave half a snapshot
+    if !overwrite {
+        if let Some(dest) = tree
+            .keys()
+            .map(|path| output.join(path))
+            .find(|dest| dest.symlink_metadata().is_ok())
+        {
+            bail!(
+                "{} already exists; use --overwrite to replace",
+                dest.display()
+            );
+        }
+    }
+    util::ensure_dir(&output)?;
+    for (path, file) in &tree {
+        let dest = output.join(path);
+        let data = storage.read_blob(&file.sha)?;
+        util::write_worktree_entry(&dest, &data, file.mode)
+            .with_context(|| format!("failed to write {}", dest.display()))?;
+    }
+    println!("Wrote {} file(s) to {}", tree.len(), output.display());
+    Ok(())
+}
+
+/// Resolves `--at`: an RFC3339 timestamp, otherwise a record id or tag.
+/// Without one, the tree as the watcher sees it now.
+fn tree_at_point(storage: &StorageEngine, at: Option<&str>) -> Result<Tree> {
+    let Some(at) = at else {
+        return storage.current_tree();
+    };
+    match DateTime::parse_from_rfc3339(at) {
+        Ok(ts) => storage.tree_at_time(ts.with_timezone(&Utc)),
+        Err(_) => storage.tree_at(&storage.resolve_record(at)?),
+    }
+}
+
+fn handle_note(args: NoteArgs) -> Result<()> {
+    let NoteArgs {
+        record_id,
+        text,
+        path,
+        json,
+    } = args;
+    let storage = open_storage(path)?;
+    let Some(text) = text else {
+        let notes = storage.notes(&storage.resolve_record(&record_id)?)?;
+        if json {
+            println!("{}", serde_json::to_string_pretty(&notes)?);
+        } else {
+            for note in notes {
+                println!(
+                    "{}  {}",
+                    note.created_at.format("%Y-%m-%d %H:%M:%S"),
+                    note.text
+                );
+            }
+        }
+        return Ok(());
+    };
+    let note = storage.add_note(&record_id, &text)?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&note)?);
+    } else {
+        println!("Added note to record {}", note.record_id);
     }
     Ok(())
 }
 
-fn handle_show(args: ShowArgs) -> Result<()> {
-    let storage = open_storage(args.path)?;
-    let meta = storage.read_record_meta(&args.record_id)?;
-    if args.json {
-        println!("{}", serde_json::to_string_pretty(&meta)?);
-    } else {
-        println!("Record: {}", meta.record_id);
-        println!("Project: {}", meta.project_id);
-        println!("Started: {}", meta.started_at);
-        println!("Ended:   {}", meta.ended_at);
-        if let Some(prev) = meta.prev_record_id {
-            println!("Previous: {}", prev);
+fn handle_tag(args: TagArgs) -> Result<()> {
+    let TagArgs {
+        name,
+        record_id,
+        path,
+        force,
+        delete,
+        json,
+    } = args;
+    let storage = open_storage(path)?;
+    let Some(name) = name else {
+        let tags = storage.tags()?;
+        if json {
+            println!("{}", serde_json::to_string_pretty(&tags)?);
+        } else if tags.is_empty() {
+            println!("No tags");
+        } else {
+            for tag in tags {
+                println!("{:<24} {}", tag.name, tag.record_id);
+            }
         }
-        println!(
-            "Stats: files={}, +{}, -{}",
-            meta.stats.files, meta.stats.lines_added, meta.stats.lines_removed
-        );
-        println!("Files:");
-        for file in meta.files {
-            println!("  - {} ({:?})", file.path, file.op);
+        return Ok(());
+    };
+    if delete {
+        if !storage.delete_tag(&name)? {
+            bail!("no tag named {name}");
+        }
+        if json {
+            println!("{}", json!({ "deleted": name }));
+        } else {
+            println!("Deleted tag {name}");
         }
+        return Ok(());
+    }
+    let (tag, previous) = storage.set_tag(&name, &record_id, force)?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&tag)?);
+        return Ok(());
+    }
+    match previous.filter(|previous| previous != &tag.record_id) {
+        Some(previous) => println!(
+            "Moved tag {} from {previous} to {}",
+            tag.name, tag.record_id
+        ),
+        None => println!("Tagged record {} as {}", tag.record_id, tag.name),
     }
     Ok(())
 }
 
-fn handle_diff(args: DiffArgs) -> Result<()> {
-    let DiffArgs {
-        record_id,
+fn handle_bookmark(args: BookmarkArgs) -> Result<()> {
+    let BookmarkArgs {
+        message,
         path,
+        record,
+        list,
         json,
-        stat,
-        file,
     } = args;
-
     let storage = open_storage(path)?;
-    let meta = storage.read_record_meta(&record_id)?;
-
-    if json {
-        println!("{}", serde_json::to_string_pretty(&meta.files)?);
+    if list {
+        let bookmarks = storage.bookmarks()?;
+        if json {
+            println!("{}", serde_json::to_string_pretty(&bookmarks)?);
+        } else if bookmarks.is_empty() {
+            println!("No bookmarks");
+        } else {
+            for bookmark in bookmarks {
+                println!(
+                    "{:<14} {}  {}",
+                    bookmark.record_id,
+                    bookmark.created_at.format("%Y-%m-%d %H:%M:%S"),
+                    bookmark.message.as_deref().unwrap_or("")
+                );
+            }
+        }
         return Ok(());
     }
+    let bookmark = storage.add_bookmark(&record, message.as_deref())?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&bookmark)?);
+    } else {
+        println!("Bookmarked record {}", bookmark.record_id);
+    }
+    Ok(())
+}
 
-    if stat {
-        println!("Diff summary for record {}:", record_id);
-        for entry in &meta.files {
-            println!(
-                "  - {:<40} {:>5} added {:>5} removed",
-                entry.path, entry.stats.added, entry.stats.removed
-            );
-        }
-        println!(
-            "Totals: files={} +{} -{}",
-            meta.stats.files, meta.stats.lines_added, meta.stats.lines_removed
-        );
+fn handle_rebaseline(args: RebaselineArgs) -> Result<()> {
+    let RebaselineArgs {
+        path,
+        from_git,
+        from_tree: _,
+        json,
+    } = args;
+    let project_root = util::resolve_project_root(path)?;
+    let source = from_git.map_or(BaselineSource::WorkingTree, BaselineSource::Git);
+    // diff the way the project's watcher does, so the reset renders alike
+    let meta_dir = util::meowdiff_root()?
+        .join(util::compute_project_id(&project_root)?)
+        .join("meta");
+    let diff = WatchSettings::load(&meta_dir)?.options().diff;
+    let meta = watcher::rebaseline(&project_root, &source, &diff)?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&meta)?);
         return Ok(());
     }
+    match meta {
+        Some(meta) => println!(
+            "Rebaselined from {source}: record {} ({} file(s), +{} -{})",
+            meta.record_id, meta.stats.files, meta.stats.lines_added, meta.stats.lines_removed
+        ),
+        None => println!("Snapshots already match the {source}; nothing recorded"),
+    }
+    Ok(())
+}
 
-    let compressed = storage.read_patch(&record_id)?;
-    let mut patch = decompress_patch(&compressed)?;
-    if let Some(filter) = file {
-        patch = filter_patch_for_file(&patch, &filter);
-        if patch.trim().is_empty() {
-            println!("No diff found for {} in record {}", filter, record_id);
-            return Ok(());
+fn handle_pause(args: PauseArgs) -> Result<()> {
+    let PauseArgs { path, duration } = args;
+    let now = util::now_utc();
+    let until = match duration {
+        Some(ref input) => {
+            let until = chrono::Duration::from_std(parse_duration(input)?)
+                .ok()
+                .and_then(|length| now.checked_add_signed(length));
+            Some(until.ok_or_else(|| anyhow!("duration too large: {input}"))?)
         }
+        None => None,
+    };
+    let storage = open_storage(path)?;
+    let meta_dir = storage.paths().meta_dir.clone();
+    let watcher = require_watcher(&meta_dir, storage.project_id())?;
+    ControlState::update(&meta_dir, |state| {
+        state.pause(now, until);
+        Ok(())
+    })?;
+    reload_watcher(&watcher);
+    match until {
+        Some(until) => println!("Recording paused until {until}"),
+        None => println!("Recording paused; run `meowdiff resume` to continue"),
     }
+    Ok(())
+}
 
-    print!("{}", colorize_patch(&patch));
+fn handle_resume(args: ResumeArgs) -> Result<()> {
+    let storage = open_storage(args.path)?;
+    let meta_dir = storage.paths().meta_dir.clone();
+    let watcher = require_watcher(&meta_dir, storage.project_id())?;
+    let (_, was_paused) = ControlState::update(&meta_dir, |state| {
+        let was_paused = state.is_paused(util::now_utc());
+        state.resume();
+        Ok(was_paused)
+    })?;
+    if !was_paused {
+        println!("Recording is not paused");
+        return Ok(());
+    }
+    reload_watcher(&watcher);
+    println!("Recording resumed; changes made while paused will be recorded as one record");
     Ok(())
 }
 
-fn handle_restore(args: RestoreArgs) -> Result<()> {
-    let RestoreArgs {
-        record_id,
+fn handle_mute(args: MuteArgs) -> Result<()> {
+    let MuteArgs {
+        globs,
         path,
-        apply,
+        remove,
     } = args;
-    let storage = open_storage(path.clone())?;
-    let project_root = util::resolve_project_root(path)?;
-    let meta = storage.read_record_meta(&record_id)?;
-    if !apply {
-        println!("Would restore {} files:", meta.files.len());
-        for file in &meta.files {
-            println!("  - {}", file.path);
+    let storage = open_storage(path)?;
+    let meta_dir = storage.paths().meta_dir.clone();
+    if globs.is_empty() {
+        let state = ControlState::load(&meta_dir)?;
+        if state.muted.is_empty() {
+            println!("No muted paths");
+        } else {
+            println!("Muted paths:");
+            for glob in &state.muted {
+                println!("  - {glob}");
+            }
         }
-        println!("Use --apply to write changes to disk.");
         return Ok(());
     }
-    for file in &meta.files {
-        let target = project_root.join(&file.path);
-        match &file.after_sha {
-            Some(sha) => {
-                let data = storage.read_blob(sha)?;
-                if let Some(parent) = target.parent() {
-                    std::fs::create_dir_all(parent)
-                        .with_context(|| format!("failed to create {}", parent.display()))?;
-                }
-                std::fs::write(&target, data)
-                    .with_context(|| format!("failed to write {}", target.display()))?;
-            }
-            None => {
-                if target.exists() {
-                    std::fs::remove_file(&target)
-                        .with_context(|| format!("failed to remove {}", target.display()))?;
+    let watcher = require_watcher(&meta_dir, storage.project_id())?;
+    if !remove {
+        // reject bad patterns here rather than in the watcher's log
+        IgnoreMatcher::new(storage.project_root())?.with_muted(&globs)?;
+    }
+    let (state, _) = ControlState::update(&meta_dir, |state| {
+        if remove {
+            state.muted.retain(|glob| !globs.contains(glob));
+        } else {
+            for glob in globs {
+                if !state.muted.contains(&glob) {
+                    state.muted.push(glob);
                 }
             }
         }
+        Ok(())
+    })?;
+    reload_watcher(&watcher);
+    if state.muted.is_empty() {
+        println!("No muted paths");
+    } else {
+        println!("Muted: {}", state.muted.join(", "));
     }
-    println!("Restored record {}", meta.record_id);
     Ok(())
 }
 
-fn handle_stop(args: StopArgs) -> Result<()> {
-    let StopArgs {
+fn require_watcher(meta_dir: &Path, project_id: &str) -> Result<LockInfo> {
+    match WatchLock::read(meta_dir)? {
+        Some(info) if is_process_alive(info.pid) => Ok(info),
+        _ => bail!("no watcher running for project {project_id}; start one with `meowdiff watch`"),
+    }
+}
+
+/// Asks the watcher to pick up control changes now instead of on its next
+/// poll. Best effort: the watcher re-reads the control file either way.
+fn reload_watcher(info: &LockInfo) {
+    #[cfg(unix)]
+    if let Some(ref socket) = info.socket {
+        if let Err(err) = watcher::rpc_call(socket, "reload", serde_json::Value::Null) {
+            tracing::debug!(error = %err, "control socket unavailable");
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = info;
+}
+
+fn handle_tail(args: TailArgs) -> Result<()> {
+    let TailArgs {
         path,
-        project_id,
-        force,
+        patch,
+        json,
+        file,
+        poll_ms,
     } = args;
-
-    let (project_id, meta_dir) = if let Some(path) = path {
-        let root = util::resolve_project_root(Some(path))?;
-        let project_id = util::compute_project_id(&root)?;
-        let meta_dir = util::meowdiff_root()?.join(&project_id).join("meta");
-        (project_id, meta_dir)
-    } else if let Some(requested_id) = project_id {
-        let entry = find_project_entry(&requested_id)?
-            .ok_or_else(|| anyhow!("project {requested_id} not found in registry"))?;
-        let meta_dir = util::meowdiff_root()?.join(&entry.project_id).join("meta");
-        (entry.project_id, meta_dir)
-    } else {
-        let root = util::resolve_project_root(None)?;
-        let project_id = util::compute_project_id(&root)?;
-        let meta_dir = util::meowdiff_root()?.join(&project_id).join("meta");
-        (project_id, meta_dir)
+    let storage = open_storage(path)?;
+    let filter = match file {
+        Some(glob) => Some(PathGlobs::new(storage.project_root(), &[glob])?),
+        None => None,
+    };
+    let printer = TailPrinter {
+        patch,
+        json,
+        filter,
     };
+    let mut cursor = TailCursor::start(&storage)?;
+    loop {
+        // follow the watcher's socket while one is up, fall back to polling
+        // the database when it stops or was never started
+        #[cfg(unix)]
+        if let Some(socket) = live_socket(&storage.paths().meta_dir)? {
+            match watcher::rpc_subscribe(&socket, patch) {
+                Ok(subscription) => {
+                    // records committed before the subscription started
+                    cursor.poll(&storage, &printer)?;
+                    for event in subscription {
+                        let event = match event {
+                            Ok(event) => event,
+                            Err(err) => {
+                                tracing::debug!(error = %err, "subscription ended");
+                                break;
+                            }
+                        };
+                        if cursor.accept(&event.meta.record_id, event.meta.ended_at) {
+                            printer.print(&event.meta, event.patch)?;
+                        }
+                    }
+                }
+                Err(err) => tracing::debug!(error = %err, "control socket unavailable"),
+            }
+        }
+        cursor.poll(&storage, &printer)?;
+        std::thread::sleep(Duration::from_millis(poll_ms));
+    }
+}
 
-    let lock_info = match WatchLock::read(&meta_dir)? {
-        Some(info) => info,
-        None => {
-            println!("No active watcher for project {project_id}");
-            return Ok(());
+#[cfg(unix)]
+fn live_socket(meta_dir: &Path) -> Result<Option<PathBuf>> {
+    Ok(WatchLock::read(meta_dir)?
+        .filter(|info| is_process_alive(info.pid))
+        .and_then(|info| info.socket)
+        .filter(|socket| socket.exists()))
+}
+
+/// Position in the record stream: the newest commit time shown so far and
+/// the records already shown at that time.
+struct TailCursor {
+    since: Option<i64>,
+    seen: HashSet<String>,
+}
+
+impl TailCursor {
+    fn start(storage: &StorageEngine) -> Result<Self> {
+        let mut cursor = Self {
+            since: None,
+            seen: HashSet::new(),
+        };
+        let latest = storage.timeline(&TimelineFilter {
+            limit: Some(1),
+            ..Default::default()
+        })?;
+        for entry in latest {
+            cursor.accept(&entry.record_id, entry.timestamp);
         }
-    };
+        Ok(cursor)
+    }
 
-    if is_process_alive(lock_info.pid) {
-        send_terminate(lock_info.pid)?;
-        println!("Sent SIGTERM to watcher pid {}", lock_info.pid);
-    } else if !force {
-        println!(
-            "Watcher process {} not running; use --force to clear lock",
-            lock_info.pid
-        );
-        return Ok(());
-    } else {
-        println!("Removing stale lock for project {project_id}");
+    /// True the first time a record newer than the cursor is offered.
+    fn accept(&mut self, record_id: &str, ended_at: DateTime<Utc>) -> bool {
+        // the index stores milliseconds, socket events carry full precision
+        let ts = ended_at.timestamp_millis();
+        match self.since {
+            Some(since) if ts < since => false,
+            Some(since) if ts == since => self.seen.insert(record_id.to_string()),
+            _ => {
+                self.since = Some(ts);
+                self.seen.clear();
+                self.seen.insert(record_id.to_string());
+                true
+            }
+        }
     }
 
-    let lock_path = WatchLock::path(&meta_dir);
-    fs::remove_file(&lock_path).ok();
-    println!("Stopped watcher for project {project_id}");
-    Ok(())
+    fn poll(&mut self, storage: &StorageEngine, printer: &TailPrinter) -> Result<()> {
+        let entries = storage.timeline(&TimelineFilter {
+            from: self.since.and_then(DateTime::<Utc>::from_timestamp_millis),
+            ..Default::default()
+        })?;
+        for entry in entries.into_iter().rev() {
+            if !self.accept(&entry.record_id, entry.timestamp) {
+                continue;
+            }
+            let meta = storage.read_record_meta(&entry.record_id)?;
+            let patch = if printer.patch {
+                Some(decompress_patch(&storage.read_patch(&entry.record_id)?)?)
+            } else {
+                None
+            };
+            printer.print(&meta, patch)?;
+        }
+        Ok(())
+    }
+}
+
+struct TailPrinter {
+    patch: bool,
+    json: bool,
+    filter: Option<PathGlobs>,
+}
+
+impl TailPrinter {
+    fn print(&self, meta: &RecordMeta, patch: Option<String>) -> Result<()> {
+        let patch = if self.patch { patch } else { None };
+        let patch = match self.filter {
+            Some(ref filter) => {
+                let matching: Vec<&str> = meta
+                    .files
+                    .iter()
+                    .map(|file| file.path.as_str())
+                    .filter(|path| filter.matches_relative(path))
+                    .collect();
+                if matching.is_empty() {
+                    return Ok(());
+                }
+                patch.map(|patch| {
+                    matching
+                        .iter()
+                        .map(|path| filter_patch_for_file(&patch, path))
+                        .filter(|section| !section.is_empty())
+                        .collect::<Vec<_>>()
+                        .join("\n")
+                })
+            }
+            None => patch,
+        };
+        if self.json {
+            let event = RecordEvent {
+                meta: meta.clone(),
+                patch,
+            };
+            println!("{}", serde_json::to_string(&event)?);
+        } else {
+            print!("{}", watcher::render_record(meta, patch.as_deref()));
+        }
+        std::io::stdout().flush()?;
+        Ok(())
+    }
 }
 
 fn handle_status(args: StatusArgs) -> Result<()> {
@@ -425,6 +2357,36 @@ fn handle_status(args: StatusArgs) -> Result<()> {
         .as_ref()
         .map(|info| is_process_alive(info.pid))
         .unwrap_or(false);
+    let control = ControlState::load(&meta_dir)?;
+    let paused = watching && control.is_paused(util::now_utc());
+    let daemon = match lock {
+        Some(ref info) if watching && info.supervised => running_daemon()?,
+        _ => None,
+    };
+    // the unit running the watcher, or the one installed for it
+    let service = match lock.as_ref().filter(|_| watching) {
+        Some(info) if info.supervised => daemon.as_ref().and_then(|info| info.service.clone()),
+        Some(info) => info.service.clone(),
+        None => None,
+    };
+    // batch counters live in the watcher; an unreachable socket just hides them
+    let batches = lock
+        .as_ref()
+        .filter(|_| watching)
+        .and_then(|info| info.socket.as_ref())
+        .and_then(|socket| watcher::rpc_call(socket, "status", serde_json::Value::Null).ok())
+        .and_then(|status| status.get("batches").cloned());
+    let service = match service {
+        Some(unit) => Some(ServiceStatus::of(&unit)?),
+        None => {
+            let unit = ServiceTarget::Project {
+                project_id: storage.project_id().to_string(),
+                root: storage.project_root().to_path_buf(),
+            }
+            .unit_name();
+            Some(ServiceStatus::of(&unit)?).filter(|status| status.installed)
+        }
+    };
 
     if json {
         let payload = json!({
@@ -433,7 +2395,14 @@ fn handle_status(args: StatusArgs) -> Result<()> {
             "watcher": {
                 "active": watching,
                 "lock": lock.clone(),
+                "paused": paused,
+                "paused_at": control.paused_at.filter(|_| paused),
+                "paused_until": control.paused_until.filter(|_| paused),
+                "muted": control.muted,
+                "daemon_pid": daemon.as_ref().map(|info| info.pid),
+                "batches": batches,
             },
+            "service": service.as_ref().map(ServiceStatus::to_json),
             "latest_record": latest_meta.as_ref().map(|meta| json!({
                 "record_id": meta.record_id,
                 "ended_at": meta.ended_at,
@@ -447,6 +2416,10 @@ fn handle_status(args: StatusArgs) -> Result<()> {
         println!("Project: {}", storage.project_id());
         println!("Root: {}", storage.project_root().display());
         match &lock {
+            Some(info) if watching && info.supervised => println!(
+                "Watcher running in daemon (pid {}) since {}",
+                info.pid, info.started_at
+            ),
             Some(info) if watching => println!(
                 "Watcher running (pid {}) since {}",
                 info.pid, info.started_at
@@ -454,6 +2427,39 @@ fn handle_status(args: StatusArgs) -> Result<()> {
             Some(info) => println!("Watcher lock present but process {} not running", info.pid),
             None => println!("Watcher: inactive"),
         }
+        if let Some(socket) = lock.as_ref().and_then(|info| info.socket.as_ref()) {
+            if watching {
+                println!("Control socket: {}", socket.display());
+            }
+        }
+        if let Some(serde_json::Value::Object(counts)) = &batches {
+            let closed: Vec<String> = counts
+                .iter()
+                .filter(|(_, count)| count.as_u64().is_some_and(|count| count > 0))
+                .map(|(reason, count)| format!("{reason} {count}"))
+                .collect();
+            if !closed.is_empty() {
+                println!("Batches closed: {}", closed.join(", "));
+            }
+        }
+        if let Some(ref service) = service {
+            let active = service.active.as_deref().unwrap_or("unknown");
+            if service.installed {
+                println!("Service: {} ({active})", service.unit);
+            } else {
+                println!("Service: {} ({active}, unit file missing)", service.unit);
+            }
+        }
+        if paused {
+            match (control.paused_at, control.paused_until) {
+                (Some(at), Some(until)) => println!("Recording paused since {at} (until {until})"),
+                (Some(at), None) => println!("Recording paused since {at}"),
+                _ => println!("Recording paused"),
+            }
+        }
+        if !control.muted.is_empty() {
+            println!("Muted: {}", control.muted.join(", "));
+        }
         if let Some(meta) = latest_meta {
             println!(
                 "Last record: {} at {} (files: {}, +{}, -{})",
@@ -472,20 +2478,98 @@ fn handle_status(args: StatusArgs) -> Result<()> {
 }
 
 fn handle_projects(args: ProjectsArgs) -> Result<()> {
+    let ProjectsArgs { json, prune } = args;
+    if prune {
+        prune_projects(json)?;
+    }
     let projects = read_registry_global()?;
-    if args.json {
-        println!("{}", serde_json::to_string_pretty(&projects)?);
+    if json {
+        let payload = projects
+            .iter()
+            .map(|entry| {
+                let mut value = serde_json::to_value(entry)?;
+                value["watcher"] = WatcherState::of(&entry.project_id)?.to_json();
+                Ok(value)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        println!("{}", serde_json::to_string_pretty(&payload)?);
     } else if projects.is_empty() {
         println!("No projects tracked yet");
     } else {
         println!("Known projects:");
         for entry in projects {
-            println!("  - {} ({})", entry.project_id, entry.path);
+            let state = WatcherState::of(&entry.project_id)?;
+            let enabled = if entry.enabled { ", enabled" } else { "" };
+            println!(
+                "  - {} ({}) [{}{}]",
+                entry.project_id, entry.path, state, enabled
+            );
+            let mut details = Vec::new();
+            if let Some(at) = entry
+                .last_record_at
+                .and_then(DateTime::from_timestamp_millis)
+            {
+                details.push(format!(
+                    "last record {}",
+                    at.format("%Y-%m-%d %H:%M:%S UTC")
+                ));
+            }
+            if let Some(bytes) = entry.storage_bytes {
+                details.push(format_bytes(bytes));
+            }
+            if !Path::new(&entry.path).exists() {
+                details.push("root missing".to_string());
+            }
+            if !details.is_empty() {
+                println!("      {}", details.join(", "));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn prune_projects(json: bool) -> Result<()> {
+    let registry = Registry::open()?;
+    let root = util::meowdiff_root()?;
+    for entry in registry.list()? {
+        if Path::new(&entry.path).exists() {
+            continue;
+        }
+        if let WatcherState::Running(info) = WatcherState::of(&entry.project_id)? {
+            tracing::warn!(project_id = %entry.project_id, pid = info.pid, "root is gone but a watcher is still running; skipping");
+            continue;
+        }
+        registry.remove(&entry.project_id)?;
+        let note = format!(
+            "Pruned {} ({}); history kept in {}",
+            entry.project_id,
+            entry.path,
+            root.join(&entry.project_id).display()
+        );
+        // keep stdout parseable
+        if json {
+            eprintln!("{note}");
+        } else {
+            println!("{note}");
         }
     }
     Ok(())
 }
 
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{value:.1} {}", UNITS[unit])
+}
+
 fn handle_inspect(args: InspectArgs) -> Result<()> {
     let storage = if let Some(path) = args.path {
         let root = util::resolve_project_root(Some(path))?;
@@ -499,7 +2583,7 @@ fn handle_inspect(args: InspectArgs) -> Result<()> {
         bail!("provide --path or --project-id")
     };
     let latest = storage.latest_record_id()?;
-    let records = storage.timeline(None, None, None)?;
+    let records = storage.timeline(&TimelineFilter::default())?;
     if args.json {
         let payload = json!({
             "project_id": storage.project_id(),
@@ -565,6 +2649,7 @@ fn handle_extract(args: ExtractArgs) -> Result<()> {
     } = args;
 
     let storage = open_storage(path)?;
+    let record_id = storage.resolve_record(&record_id)?;
     let meta = storage.read_record_meta(&record_id)?;
     util::ensure_dir(&output)?;
 
@@ -574,16 +2659,13 @@ fn handle_extract(args: ExtractArgs) -> Result<()> {
         };
         let data = storage.read_blob(sha)?;
         let dest = output.join(&file.path);
-        if dest.exists() && !overwrite {
+        if dest.symlink_metadata().is_ok() && !overwrite {
             bail!(
                 "{} already exists; use --overwrite to replace",
                 dest.display()
             );
         }
-        if let Some(parent) = dest.parent() {
-            util::ensure_dir(parent)?;
-        }
-        fs::write(&dest, data)
+        util::write_worktree_entry(&dest, &data, file.after_mode)
             .with_context(|| format!("failed to write extracted file {}", dest.display()))?;
     }
 
@@ -591,6 +2673,18 @@ fn handle_extract(args: ExtractArgs) -> Result<()> {
     Ok(())
 }
 
+fn daemon_log_dir(project_root: &Path) -> Result<PathBuf> {
+    let project_id = util::compute_project_id(project_root)?;
+    Ok(util::meowdiff_root()?
+        .join(project_id)
+        .join("meta")
+        .join("logs"))
+}
+
+fn supervisor_log_dir() -> Result<PathBuf> {
+    Ok(watcher::supervisor_dir()?.join("logs"))
+}
+
 fn open_storage(path: Option<PathBuf>) -> Result<StorageEngine> {
     let root = util::resolve_project_root(path)?;
     StorageEngine::open(&root)
@@ -602,20 +2696,72 @@ fn parse_datetime(input: &str) -> Result<DateTime<Utc>> {
     Ok(parsed.with_timezone(&Utc))
 }
 
+/// Parses `90s`, `10m`, `2h` or `1d`; a bare number is taken as seconds.
+fn parse_duration(input: &str) -> Result<Duration> {
+    let input = input.trim();
+    let split = input
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(input.len());
+    let (digits, unit) = input.split_at(split);
+    let value: u64 = digits
+        .parse()
+        .with_context(|| format!("invalid duration: {input}"))?;
+    let factor = match unit {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 60 * 60 * 24,
+        _ => bail!("invalid duration unit in {input}; use s, m, h or d"),
+    };
+    let seconds = value
+        .checked_mul(factor)
+        .ok_or_else(|| anyhow!("duration too large: {input}"))?;
+    Ok(Duration::from_secs(seconds))
+}
+
 fn print_timeline(entries: &[TimelineEntry]) {
     println!(
         "{:<14} {:<25} {:>5} {:>6} {:>6}",
         "Record", "Timestamp", "Files", "+", "-"
     );
-    for entry in entries {
+    for (idx, entry) in entries.iter().enumerate() {
+        // entries are newest first, so a HEAD change against the next (older)
+        // entry means a commit or checkout happened in between
+        if let (Some(head), Some(older)) = (
+            entry.git_head.as_deref(),
+            entries.get(idx + 1).and_then(|e| e.git_head.as_deref()),
+        ) {
+            if head != older {
+                println!(
+                    "-- HEAD moved to {} ({})",
+                    git::short_sha(head),
+                    entry.git_branch.as_deref().unwrap_or("detached")
+                );
+            }
+        }
+        let mut decorations = entry
+            .label
+            .as_deref()
+            .map(|label| format!("  [{label}]"))
+            .unwrap_or_default();
+        if !entry.tags.is_empty() {
+            decorations.push_str(&format!("  (tag: {})", entry.tags.join(", ")));
+        }
+        if entry.bookmarked {
+            decorations.push_str("  *bookmarked*");
+        }
         println!(
-            "{:<14} {:<25} {:>5} {:>6} {:>6}",
+            "{:<14} {:<25} {:>5} {:>6} {:>6}{}",
             entry.record_id.as_str(),
             entry.timestamp,
             entry.files,
             entry.lines_added,
-            entry.lines_removed
+            entry.lines_removed,
+            decorations
         );
+        if let Some(ref notes) = entry.notes {
+            println!("{:<14} note: {notes}", "");
+        }
     }
 }
 
@@ -0,0 +1,124 @@
+mod replay;
+mod tree;
+pub use replay::{replay_to_git, ReplayOptions, ReplaySummary};
+pub use tree::{for_each_blob, resolve_commit};
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use anyhow::{bail, Context, Result};
+
+use crate::models::{GitContext, GitOperation};
+
+/// Locates the git directory for a working tree, following `.git` files
+/// used by worktrees and submodules.
+pub fn find_git_dir(project_root: &Path) -> Option<PathBuf> {
+    let dot_git = project_root.join(".git");
+    let meta = fs::metadata(&dot_git).ok()?;
+    if meta.is_dir() {
+        return Some(dot_git);
+    }
+    let contents = fs::read_to_string(&dot_git).ok()?;
+    let target = contents.trim().strip_prefix("gitdir:")?.trim();
+    let path = PathBuf::from(target);
+    let resolved = if path.is_absolute() {
+        path
+    } else {
+        project_root.join(path)
+    };
+    resolved.is_dir().then_some(resolved)
+}
+
+/// Reads branch, HEAD and in-progress operation straight from `.git`.
+pub fn read_context(project_root: &Path) -> Option<GitContext> {
+    let git_dir = find_git_dir(project_root)?;
+    let head = fs::read_to_string(git_dir.join("HEAD")).ok()?;
+    let head = head.trim();
+    let (branch, commit) = match head.strip_prefix("ref:") {
+        Some(reference) => {
+            let reference = reference.trim();
+            let branch = reference
+                .strip_prefix("refs/heads/")
+                .unwrap_or(reference)
+                .to_string();
+            (Some(branch), resolve_ref(&git_dir, reference))
+        }
+        None => (None, Some(head.to_string())),
+    };
+    Some(GitContext {
+        branch,
+        head: commit,
+        operation: current_operation(&git_dir),
+    })
+}
+
+/// True while git is rewriting the working tree (checkout, stash, reset,
+/// or a rebase that has not stopped for user input).
+pub fn is_rewriting(project_root: &Path) -> bool {
+    let Some(git_dir) = find_git_dir(project_root) else {
+        return false;
+    };
+    if git_dir.join("index.lock").exists() {
+        return true;
+    }
+    let rebase = git_dir.join("rebase-merge");
+    rebase.is_dir() && !rebase.join("stopped-sha").exists()
+}
+
+pub fn short_sha(sha: &str) -> &str {
+    sha.get(..8).unwrap_or(sha)
+}
+
+fn current_operation(git_dir: &Path) -> Option<GitOperation> {
+    if git_dir.join("rebase-merge").is_dir() || git_dir.join("rebase-apply").is_dir() {
+        Some(GitOperation::Rebase)
+    } else if git_dir.join("MERGE_HEAD").exists() {
+        Some(GitOperation::Merge)
+    } else if git_dir.join("CHERRY_PICK_HEAD").exists() {
+        Some(GitOperation::CherryPick)
+    } else if git_dir.join("REVERT_HEAD").exists() {
+        Some(GitOperation::Revert)
+    } else if git_dir.join("BISECT_LOG").exists() {
+        Some(GitOperation::Bisect)
+    } else if git_dir.join("index.lock").exists() {
+        Some(GitOperation::TreeUpdate)
+    } else {
+        None
+    }
+}
+
+fn resolve_ref(git_dir: &Path, reference: &str) -> Option<String> {
+    // linked worktrees keep shared refs in the common dir
+    let common = match fs::read_to_string(git_dir.join("commondir")) {
+        Ok(rel) => git_dir.join(rel.trim()),
+        Err(_) => git_dir.to_path_buf(),
+    };
+    for dir in [git_dir, common.as_path()] {
+        if let Ok(sha) = fs::read_to_string(dir.join(reference)) {
+            return Some(sha.trim().to_string());
+        }
+    }
+    let packed = fs::read_to_string(common.join("packed-refs")).ok()?;
+    packed
+        .lines()
+        .filter(|line| !line.starts_with('#') && !line.starts_with('^'))
+        .filter_map(|line| line.split_once(' '))
+        .find(|(_, name)| *name == reference)
+        .map(|(sha, _)| sha.to_string())
+}
+
+/// Runs git in `root` and returns its trimmed stdout.
+fn git_output(root: &Path, args: &[&str]) -> Result<String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(root)
+        .args(args)
+        .stderr(Stdio::null())
+        .output()
+        .context("failed to run git")?;
+    if !output.status.success() {
+        bail!("git {} failed", args.join(" "));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
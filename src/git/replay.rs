@@ -0,0 +1,362 @@
+//! Replaying records as commits on a git branch.
+//!
+//! Everything goes through `git fast-import`, which writes objects and the
+//! branch ref directly; the index, the working tree and HEAD are never
+//! touched.
+
+use std::collections::HashMap;
+use std::io::{BufWriter, Write};
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use super::{find_git_dir, git_output, short_sha};
+use crate::models::{FileOp, RecordMeta};
+use crate::storage::{apply_record, StorageEngine, TimelineFilter, Tree, TreeFile};
+use crate::util;
+
+/// Used when git has no `user.name` / `user.email` configured.
+const FALLBACK_IDENT: &str = "MeowDiff <meowdiff@localhost>";
+
+pub struct ReplayOptions {
+    /// Branch to create, without `refs/heads/`.
+    pub branch: String,
+    pub filter: TimelineFilter,
+    /// Fold records starting within this window of a group's first record
+    /// into one commit.
+    pub squash_by: Option<Duration>,
+    /// Replace the branch if it already exists.
+    pub force: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ReplaySummary {
+    pub branch: String,
+    pub head: String,
+    /// Commit the branch starts from, when the first record's HEAD is known.
+    pub base: Option<String>,
+    pub commits: usize,
+    pub records: usize,
+}
+
+/// Writes the records selected by `options.filter` to a new branch: a
+/// baseline commit holding the tree they start from, then one commit per
+/// record (or per squashed group).
+///
+/// The baseline sits on top of the HEAD commit recorded with the first
+/// record when the repository still has it. Files MeowDiff ignores keep
+/// their content from that commit.
+pub fn replay_to_git(storage: &StorageEngine, options: &ReplayOptions) -> Result<ReplaySummary> {
+    let root = storage.project_root();
+    if find_git_dir(root).is_none() {
+        bail!("{} is not a git repository", root.display());
+    }
+    let branch = git_output(root, &["check-ref-format", "--branch", &options.branch])
+        .with_context(|| format!("invalid branch name {}", options.branch))?;
+    let reference = format!("refs/heads/{branch}");
+    if git_output(root, &["symbolic-ref", "-q", "HEAD"])
+        .ok()
+        .as_deref()
+        == Some(&*reference)
+    {
+        bail!("refusing to replay onto the checked-out branch {branch}");
+    }
+    if git_output(root, &["rev-parse", "--verify", "-q", &reference]).is_ok() && !options.force {
+        bail!("branch {branch} already exists; use --force to replace it");
+    }
+
+    let mut records = Vec::new();
+    for entry in storage.timeline(&options.filter)?.into_iter().rev() {
+        records.push(storage.read_record_meta(&entry.record_id)?);
+    }
+    let Some(first) = records.first() else {
+        bail!("no records in the selected range");
+    };
+    let base = first
+        .git
+        .as_ref()
+        .and_then(|git| git.head.clone())
+        .filter(|head| {
+            git_output(root, &["cat-file", "-e", &format!("{head}^{{commit}}")]).is_ok()
+        });
+    let baseline = storage.tree_before(&first.record_id)?;
+    let ident = git_output(root, &["var", "GIT_COMMITTER_IDENT"])
+        .ok()
+        .and_then(|ident| strip_ident_date(&ident).map(str::to_string))
+        .unwrap_or_else(|| FALLBACK_IDENT.to_string());
+
+    let mut child = Command::new("git")
+        .arg("-C")
+        .arg(root)
+        .args(["fast-import", "--quiet", "--done"])
+        .args(options.force.then_some("--force"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .spawn()
+        .context("failed to run git fast-import")?;
+    let stdin = child.stdin.take().expect("piped stdin");
+    let groups = group_records(&records, options.squash_by);
+    let mut stream = Stream {
+        out: BufWriter::new(stdin),
+        storage,
+        reference: &reference,
+        ident: &ident,
+        marks: HashMap::new(),
+        next_mark: 1,
+    };
+    let written = stream
+        .write_all(first, base.as_deref(), &baseline, &groups)
+        .and_then(|()| Ok(stream.out.flush()?));
+    // closing stdin lets fast-import finish, or give up on a partial stream
+    drop(stream);
+    let status = child.wait().context("failed to wait for git fast-import")?;
+    if !status.success() {
+        bail!("git fast-import failed ({status})");
+    }
+    written.context("failed to stream records to git fast-import")?;
+
+    Ok(ReplaySummary {
+        head: git_output(root, &["rev-parse", &reference])?,
+        branch,
+        base,
+        commits: groups.len() + 1,
+        records: records.len(),
+    })
+}
+
+fn group_records(records: &[RecordMeta], window: Option<Duration>) -> Vec<&[RecordMeta]> {
+    let Some(window) = window.and_then(|window| chrono::Duration::from_std(window).ok()) else {
+        return records.chunks(1).collect();
+    };
+    let mut groups = Vec::new();
+    let mut start = 0;
+    for (idx, record) in records.iter().enumerate() {
+        if record.started_at - records[start].started_at >= window {
+            groups.push(&records[start..idx]);
+            start = idx;
+        }
+    }
+    if start < records.len() {
+        groups.push(&records[start..]);
+    }
+    groups
+}
+
+struct Stream<'a, W: Write> {
+    out: W,
+    storage: &'a StorageEngine,
+    reference: &'a str,
+    ident: &'a str,
+    /// fast-import marks of blobs already sent, by MeowDiff sha.
+    marks: HashMap<String, usize>,
+    next_mark: usize,
+}
+
+impl<W: Write> Stream<'_, W> {
+    fn write_all(
+        &mut self,
+        first: &RecordMeta,
+        base: Option<&str>,
+        baseline: &Tree,
+        groups: &[&[RecordMeta]],
+    ) -> Result<()> {
+        writeln!(self.out, "reset {}", self.reference)?;
+        let mut message = format!(
+            "MeowDiff baseline before {}\n\nFiles as recorded before the first replayed record.\n",
+            first.record_id
+        );
+        if let Some(base) = base {
+            message.push_str(&format!("Based on {}.\n", short_sha(base)));
+        }
+        let changes: Vec<(&String, Option<&TreeFile>)> = baseline
+            .iter()
+            .map(|(path, file)| (path, Some(file)))
+            .collect();
+        self.commit(first.started_at, first.started_at, &message, base, &changes)?;
+
+        let mut tree = baseline.clone();
+        for group in groups {
+            let mut touched: Vec<&String> = group
+                .iter()
+                .flat_map(|record| &record.files)
+                .filter(|file| !matches!(file.op, FileOp::DirCreated | FileOp::DirRemoved))
+                .map(|file| &file.path)
+                .collect();
+            touched.sort();
+            touched.dedup();
+            let before: Vec<Option<TreeFile>> = touched
+                .iter()
+                .map(|path| tree.get(*path).cloned())
+                .collect();
+            for record in group.iter() {
+                apply_record(&mut tree, record);
+            }
+            // a file edited and reverted within a squashed group is no change
+            let changes: Vec<(&String, Option<&TreeFile>)> = touched
+                .into_iter()
+                .zip(before)
+                .map(|(path, before)| (path, tree.get(path), before))
+                .filter(|(_, after, before)| *after != before.as_ref())
+                .map(|(path, after, _)| (path, after))
+                .collect();
+            let (first, last) = (&group[0], &group[group.len() - 1]);
+            let message = commit_message(self.storage.project_id(), group);
+            self.commit(first.started_at, last.ended_at, &message, None, &changes)?;
+        }
+        writeln!(self.out, "done")?;
+        Ok(())
+    }
+
+    fn commit(
+        &mut self,
+        authored: DateTime<Utc>,
+        committed: DateTime<Utc>,
+        message: &str,
+        from: Option<&str>,
+        changes: &[(&String, Option<&TreeFile>)],
+    ) -> Result<()> {
+        for (_, file) in changes {
+            if let Some(file) = file {
+                self.blob(&file.sha)?;
+            }
+        }
+        writeln!(self.out, "commit {}", self.reference)?;
+        writeln!(
+            self.out,
+            "author {} {} +0000",
+            self.ident,
+            authored.timestamp()
+        )?;
+        writeln!(
+            self.out,
+            "committer {} {} +0000",
+            self.ident,
+            committed.timestamp()
+        )?;
+        writeln!(self.out, "data {}", message.len())?;
+        writeln!(self.out, "{message}")?;
+        if let Some(from) = from {
+            writeln!(self.out, "from {from}")?;
+        }
+        for (path, file) in changes {
+            let path = quote_path(path);
+            match file {
+                Some(file) => writeln!(
+                    self.out,
+                    "M {} :{} {path}",
+                    git_mode(file.mode),
+                    self.marks[&file.sha]
+                )?,
+                None => writeln!(self.out, "D {path}")?,
+            }
+        }
+        writeln!(self.out)?;
+        Ok(())
+    }
+
+    fn blob(&mut self, sha: &str) -> Result<()> {
+        if self.marks.contains_key(sha) {
+            return Ok(());
+        }
+        let data = self.storage.read_blob(sha)?;
+        let mark = self.next_mark;
+        self.next_mark += 1;
+        writeln!(self.out, "blob\nmark :{mark}\ndata {}", data.len())?;
+        self.out.write_all(&data)?;
+        writeln!(self.out)?;
+        self.marks.insert(sha.to_string(), mark);
+        Ok(())
+    }
+}
+
+fn commit_message(project_id: &str, group: &[RecordMeta]) -> String {
+    let added: usize = group.iter().map(|record| record.stats.lines_added).sum();
+    let removed: usize = group.iter().map(|record| record.stats.lines_removed).sum();
+    let labels: Vec<&str> = group
+        .iter()
+        .filter_map(|record| record.label.as_deref())
+        .collect();
+    let mut message = if !labels.is_empty() {
+        labels.join("; ")
+    } else if let [record] = group {
+        format!(
+            "MeowDiff record {} ({} file(s), +{added} -{removed})",
+            record.record_id, record.stats.files
+        )
+    } else {
+        format!("MeowDiff: {} records (+{added} -{removed})", group.len())
+    };
+    message.push_str("\n\n");
+    for record in group {
+        message.push_str(&format!(
+            "- {} {} .. {}, {} file(s), +{} -{}",
+            record.record_id,
+            record.started_at.format("%Y-%m-%d %H:%M:%S%.3f"),
+            record.ended_at.format("%H:%M:%S%.3f"),
+            record.stats.files,
+            record.stats.lines_added,
+            record.stats.lines_removed
+        ));
+        if let Some(git) = &record.git {
+            match (&git.branch, &git.head) {
+                (Some(branch), Some(head)) => {
+                    message.push_str(&format!(" on {branch} at {}", short_sha(head)))
+                }
+                (None, Some(head)) => message.push_str(&format!(" at {}", short_sha(head))),
+                _ => {}
+            }
+        }
+        if let Some(label) = &record.label {
+            message.push_str(&format!(" [{label}]"));
+        }
+        message.push('\n');
+    }
+    message.push('\n');
+    message.push_str(&format!("MeowDiff-Project: {project_id}\n"));
+    for record in group {
+        message.push_str(&format!("MeowDiff-Record: {}\n", record.record_id));
+    }
+    message.push_str(&format!(
+        "MeowDiff-Version: {}\n",
+        group[group.len() - 1].tool_version
+    ));
+    message
+}
+
+/// git only knows regular, executable and symlink entries.
+fn git_mode(mode: Option<u32>) -> &'static str {
+    match mode {
+        Some(mode) if util::is_symlink_mode(mode) => "120000",
+        Some(mode) if mode & 0o111 != 0 => "100755",
+        _ => "100644",
+    }
+}
+
+/// fast-import reads an unquoted path up to the end of the line, so only
+/// newlines and a leading quote need the C-style form.
+fn quote_path(path: &str) -> String {
+    if !path.starts_with('"') && !path.contains('\n') {
+        return path.to_string();
+    }
+    let mut quoted = String::from("\"");
+    for c in path.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            _ => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// `GIT_COMMITTER_IDENT` ends with `<timestamp> <offset>`.
+fn strip_ident_date(ident: &str) -> Option<&str> {
+    let (rest, _offset) = ident.rsplit_once(' ')?;
+    let (ident, _timestamp) = rest.rsplit_once(' ')?;
+    ident.ends_with('>').then_some(ident)
+}
@@ -0,0 +1,98 @@
+//! Reading file contents of a commit without checking it out.
+
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use anyhow::{anyhow, bail, Context, Result};
+
+use super::git_output;
+
+/// Full id of the commit `rev` names.
+pub fn resolve_commit(root: &Path, rev: &str) -> Result<String> {
+    git_output(
+        root,
+        &["rev-parse", "--verify", "-q", &format!("{rev}^{{commit}}")],
+    )
+    .with_context(|| format!("unknown git revision {rev}"))
+}
+
+/// Calls `f` with the path, git mode and content of every file in `rev`.
+/// Submodules are skipped. Contents are read through one
+/// `git cat-file --batch`, one object at a time.
+pub fn for_each_blob(
+    root: &Path,
+    rev: &str,
+    mut f: impl FnMut(&str, u32, Vec<u8>) -> Result<()>,
+) -> Result<()> {
+    let commit = resolve_commit(root, rev)?;
+    let listing = Command::new("git")
+        .arg("-C")
+        .arg(root)
+        .args(["ls-tree", "-r", "-z", "--full-tree", &commit])
+        .stderr(Stdio::inherit())
+        .output()
+        .context("failed to run git ls-tree")?;
+    if !listing.status.success() {
+        bail!("git ls-tree {rev} failed");
+    }
+
+    let mut batch = Command::new("git")
+        .arg("-C")
+        .arg(root)
+        .args(["cat-file", "--batch"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .context("failed to run git cat-file")?;
+    let mut input = batch.stdin.take().expect("piped stdin");
+    let mut output = BufReader::new(batch.stdout.take().expect("piped stdout"));
+    let result = (|| {
+        for entry in listing.stdout.split(|&b| b == 0).filter(|e| !e.is_empty()) {
+            let entry = String::from_utf8_lossy(entry);
+            // <mode> SP <type> SP <object> TAB <path>
+            let (info, path) = entry
+                .split_once('\t')
+                .ok_or_else(|| anyhow!("unexpected ls-tree entry {entry:?}"))?;
+            let mut fields = info.split(' ');
+            let (Some(mode), Some(kind), Some(object)) =
+                (fields.next(), fields.next(), fields.next())
+            else {
+                bail!("unexpected ls-tree entry {entry:?}");
+            };
+            if kind != "blob" {
+                continue;
+            }
+            let mode = u32::from_str_radix(mode, 8)
+                .with_context(|| format!("unexpected mode in ls-tree entry {entry:?}"))?;
+            writeln!(input, "{object}")?;
+            input.flush()?;
+            f(path, mode, read_object(&mut output, object)?)?;
+        }
+        Ok(())
+    })();
+    drop(input);
+    let status = batch.wait().context("failed to wait for git cat-file")?;
+    result?;
+    if !status.success() {
+        bail!("git cat-file failed ({status})");
+    }
+    Ok(())
+}
+
+/// Reads one `<object> <type> <size>` header and its content.
+fn read_object(output: &mut impl BufRead, object: &str) -> Result<Vec<u8>> {
+    let mut header = String::new();
+    output.read_line(&mut header)?;
+    let size: usize = header
+        .trim_end()
+        .rsplit(' ')
+        .next()
+        .and_then(|size| size.parse().ok())
+        .ok_or_else(|| anyhow!("git cat-file could not read {object}: {}", header.trim()))?;
+    let mut data = vec![0; size];
+    output.read_exact(&mut data)?;
+    let mut newline = [0; 1];
+    output.read_exact(&mut newline)?;
+    Ok(data)
+}
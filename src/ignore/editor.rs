@@ -0,0 +1,70 @@
+use std::path::{Path, PathBuf};
+
+/// Vim probes directory writability with `4913`, then `5036`, `5159`, ...
+const VIM_PROBE_START: u64 = 4913;
+const VIM_PROBE_STEP: u64 = 123;
+
+const SWAP_SUFFIXES: &[&str] = &[".swp", ".swo", ".swn", ".swx", ".kate-swp"];
+const JETBRAINS_SUFFIXES: &[&str] = &["___jb_tmp___", "___jb_old___"];
+
+pub const EDITOR_RULE: &str = "(builtin) editor swap, backup and atomic-save temp files";
+
+/// True for swap, backup and temp files that editors create around a save.
+pub fn is_editor_artifact(path: &Path) -> bool {
+    let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+        return false;
+    };
+    name.starts_with(".goutputstream-") || editor_save_target_name(name).is_some()
+}
+
+/// True for the names vim uses to probe whether it can write to a
+/// directory. Only a file with such a name that is created and deleted
+/// again straight away is a probe; anything else is a real file.
+pub fn is_vim_probe(path: &Path) -> bool {
+    let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+        return false;
+    };
+    match name.parse::<u64>() {
+        Ok(n) => n >= VIM_PROBE_START && (n - VIM_PROBE_START).is_multiple_of(VIM_PROBE_STEP),
+        Err(_) => false,
+    }
+}
+
+/// Maps an editor artifact to the file it is saving, e.g. `main.rs~`,
+/// `.main.rs.swp` or `main.rs___jb_tmp___` all map to `main.rs`.
+pub fn editor_save_target(path: &Path) -> Option<PathBuf> {
+    let name = path.file_name()?.to_str()?;
+    let target = editor_save_target_name(name)?;
+    Some(path.with_file_name(target))
+}
+
+fn editor_save_target_name(name: &str) -> Option<String> {
+    if let Some(base) = name.strip_suffix('~') {
+        return non_empty(base);
+    }
+    for suffix in JETBRAINS_SUFFIXES {
+        if let Some(base) = name.strip_suffix(suffix) {
+            return non_empty(base);
+        }
+    }
+    for suffix in SWAP_SUFFIXES {
+        if let Some(base) = name.strip_suffix(suffix) {
+            return non_empty(base.strip_prefix('.').unwrap_or(base));
+        }
+    }
+    if let Some(base) = name.strip_prefix(".#") {
+        return non_empty(base);
+    }
+    if name.len() > 2 && name.starts_with('#') && name.ends_with('#') {
+        return non_empty(&name[1..name.len() - 1]);
+    }
+    None
+}
+
+fn non_empty(base: &str) -> Option<String> {
+    if base.is_empty() || base == "." {
+        None
+    } else {
+        Some(base.to_string())
+    }
+}
@@ -1,3 +1,6 @@
+mod editor;
+pub use editor::{editor_save_target, is_editor_artifact, is_vim_probe};
+
 use std::path::{Path, PathBuf};
 
 use anyhow::{Context, Result};
@@ -24,6 +27,8 @@ const DEFAULT_PATTERNS: &[&str] = &[
 #[derive(Clone)]
 pub struct IgnoreMatcher {
     matcher: Gitignore,
+    /// Globs muted at runtime through `meowdiff mute`.
+    muted: Option<PathGlobs>,
     rules: Vec<String>,
     root: PathBuf,
 }
@@ -49,23 +54,52 @@ impl IgnoreMatcher {
             }
             rules.push(format!("(file) {}", custom.display()));
         }
+        rules.push(editor::EDITOR_RULE.to_string());
         let matcher = builder
             .build()
             .map_err(|err| anyhow::anyhow!("failed to build ignore matcher: {err}"))?;
         Ok(Self {
             matcher,
+            muted: None,
             rules,
             root: project_root.to_path_buf(),
         })
     }
 
     pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        if let Some(ref muted) = self.muted {
+            if muted.matches(path, is_dir) {
+                return true;
+            }
+        }
         match self.matcher.matched_path_or_any_parents(path, is_dir) {
-            Match::None | Match::Whitelist(_) => false,
             Match::Ignore(_) => true,
+            Match::Whitelist(_) => false,
+            Match::None => !is_dir && is_editor_artifact(path),
         }
     }
 
+    /// Like [`is_editor_artifact`], except that a negated rule such as
+    /// `!*.swp` makes the file an ordinary one again.
+    pub fn is_editor_artifact(&self, path: &Path) -> bool {
+        is_editor_artifact(path)
+            && !self
+                .matcher
+                .matched_path_or_any_parents(path, false)
+                .is_whitelist()
+    }
+
+    /// Copy of this matcher that also skips the given gitignore-style globs.
+    pub fn with_muted(&self, globs: &[String]) -> Result<Self> {
+        let mut matcher = self.clone();
+        matcher.muted = if globs.is_empty() {
+            None
+        } else {
+            Some(PathGlobs::new(&self.root, globs)?)
+        };
+        Ok(matcher)
+    }
+
     pub fn rules(&self) -> &[String] {
         &self.rules
     }
@@ -74,3 +108,39 @@ impl IgnoreMatcher {
         &self.root
     }
 }
+
+/// Gitignore-style globs matched against project paths, e.g. `*.rs` or `src/`.
+#[derive(Clone)]
+pub struct PathGlobs {
+    matcher: Gitignore,
+    root: PathBuf,
+}
+
+impl PathGlobs {
+    pub fn new(project_root: &Path, globs: &[String]) -> Result<Self> {
+        let mut builder = GitignoreBuilder::new(project_root);
+        for glob in globs {
+            builder
+                .add_line(None, glob)
+                .with_context(|| format!("invalid glob: {glob}"))?;
+        }
+        let matcher = builder
+            .build()
+            .map_err(|err| anyhow::anyhow!("failed to build glob matcher: {err}"))?;
+        Ok(Self {
+            matcher,
+            root: project_root.to_path_buf(),
+        })
+    }
+
+    pub fn matches(&self, path: &Path, is_dir: bool) -> bool {
+        self.matcher
+            .matched_path_or_any_parents(path, is_dir)
+            .is_ignore()
+    }
+
+    /// Matches a project-relative path as stored in records.
+    pub fn matches_relative(&self, rel_path: &str) -> bool {
+        self.matches(&self.root.join(rel_path), false)
+    }
+}
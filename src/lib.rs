@@ -1,4 +1,5 @@
 pub mod cli;
+pub mod git;
 pub mod ignore;
 pub mod models;
 pub mod pipeline;
@@ -7,6 +7,8 @@ pub enum FileOp {
     Added,
     Modified,
     Deleted,
+    DirCreated,
+    DirRemoved,
 }
 
 #[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
@@ -14,6 +16,19 @@ pub struct FileStats {
     pub added: usize,
     pub removed: usize,
     pub chunks: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cells: Option<CellStats>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct CellStats {
+    pub added: usize,
+    pub removed: usize,
+    pub modified: usize,
+    /// Only ignored fields (outputs, execution counts, metadata) changed, so
+    /// the patch is empty.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub ignored_only: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
@@ -23,6 +38,12 @@ pub struct FileRecord {
     pub before_sha: Option<String>,
     pub after_sha: Option<String>,
     pub stats: FileStats,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub before_mode: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub after_mode: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub symlink_target: Option<String>,
 }
 
 #[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
@@ -42,6 +63,30 @@ pub struct RecordMeta {
     pub stats: RecordStats,
     pub prev_record_id: Option<String>,
     pub tool_version: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub git: Option<GitContext>,
+    /// Free-form label set by a client over the control socket.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum GitOperation {
+    Rebase,
+    Merge,
+    CherryPick,
+    Revert,
+    Bisect,
+    /// `index.lock` is held: checkout, stash, reset or similar.
+    TreeUpdate,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct GitContext {
+    pub branch: Option<String>,
+    pub head: Option<String>,
+    pub operation: Option<GitOperation>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -53,10 +98,53 @@ pub struct TimelineEntry {
     pub lines_removed: usize,
     pub duration_ms: i64,
     pub notes: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub bookmarked: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub git_branch: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub git_head: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
 }
 
 #[derive(Debug, Clone)]
 pub struct SnapshotInfo {
     pub record_id: String,
     pub sha: String,
+    pub mode: Option<u32>,
+}
+
+/// What a rescan needs to tell an unchanged file from its snapshot without
+/// reading it. `size` is unknown for snapshots taken before it was stored.
+#[derive(Debug, Clone)]
+pub struct SnapshotStamp {
+    pub size: Option<u64>,
+    pub mode: Option<u32>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SemanticOp {
+    Added,
+    Removed,
+    Changed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SemanticChange {
+    pub path: String,
+    pub op: SemanticOp,
+    pub before: Option<serde_json::Value>,
+    pub after: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SemanticFileDiff {
+    pub path: String,
+    pub format: String,
+    pub changes: Vec<SemanticChange>,
 }
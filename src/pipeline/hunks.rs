@@ -0,0 +1,107 @@
+//! Reading stored unified diffs back into files and hunks.
+
+/// One file's section of a record patch.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PatchFile {
+    pub path: String,
+    pub hunks: Vec<Hunk>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hunk {
+    /// The `@@ -a,b +c,d @@` line.
+    pub header: String,
+    /// Body lines, each still starting with ` `, `+`, `-` or `\`.
+    pub lines: Vec<String>,
+}
+
+impl Hunk {
+    pub fn added(&self) -> impl Iterator<Item = &str> {
+        self.lines.iter().filter_map(|line| line.strip_prefix('+'))
+    }
+
+    pub fn removed(&self) -> impl Iterator<Item = &str> {
+        self.lines.iter().filter_map(|line| line.strip_prefix('-'))
+    }
+
+    pub fn text(&self) -> String {
+        let mut text = self.header.clone();
+        for line in &self.lines {
+            text.push('\n');
+            text.push_str(line);
+        }
+        text.push('\n');
+        text
+    }
+}
+
+/// Splits a record patch into per-file hunks. Hunk bodies are consumed by
+/// the line counts in their headers, so a removed `-- x` line is never
+/// mistaken for a file header. Sections without hunks (binary files,
+/// directories, mode-only changes) are left out.
+pub fn parse_patch(patch: &str) -> Vec<PatchFile> {
+    let mut files: Vec<PatchFile> = Vec::new();
+    let mut lines = patch.lines().peekable();
+    // (old, new) lines still expected in the current hunk
+    let mut remaining = (0usize, 0usize);
+    while let Some(line) = lines.next() {
+        if remaining != (0, 0) {
+            if let Some(hunk) = files.last_mut().and_then(|file| file.hunks.last_mut()) {
+                match line.as_bytes().first() {
+                    Some(b'+') => remaining.1 = remaining.1.saturating_sub(1),
+                    Some(b'-') => remaining.0 = remaining.0.saturating_sub(1),
+                    Some(b'\\') => {}
+                    _ => {
+                        remaining.0 = remaining.0.saturating_sub(1);
+                        remaining.1 = remaining.1.saturating_sub(1);
+                    }
+                }
+                hunk.lines.push(line.to_string());
+                continue;
+            }
+        }
+        if line.starts_with('\\') {
+            if let Some(hunk) = files.last_mut().and_then(|file| file.hunks.last_mut()) {
+                hunk.lines.push(line.to_string());
+            }
+        } else if let Some(old) = line.strip_prefix("--- ") {
+            let Some(new) = lines.peek().and_then(|next| next.strip_prefix("+++ ")) else {
+                continue;
+            };
+            let path = match new.strip_prefix("b/") {
+                Some(path) => path,
+                None => old.strip_prefix("a/").unwrap_or(old),
+            };
+            files.push(PatchFile {
+                path: path.to_string(),
+                hunks: Vec::new(),
+            });
+            lines.next();
+        } else if line.starts_with("@@ ") {
+            let Some(file) = files.last_mut() else {
+                continue;
+            };
+            remaining = hunk_lengths(line);
+            file.hunks.push(Hunk {
+                header: line.to_string(),
+                lines: Vec::new(),
+            });
+        }
+    }
+    files
+}
+
+/// Old and new line counts from `@@ -a[,b] +c[,d] @@`.
+fn hunk_lengths(header: &str) -> (usize, usize) {
+    let mut ranges = header.split(' ').skip(1);
+    let length = |range: Option<&str>| {
+        range
+            .and_then(|range| range.get(1..))
+            .map(|range| match range.split_once(',') {
+                Some((_, len)) => len.parse().unwrap_or(0),
+                None => 1,
+            })
+            .unwrap_or(0)
+    };
+    (length(ranges.next()), length(ranges.next()))
+}
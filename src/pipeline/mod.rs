@@ -1,23 +1,40 @@
+mod hunks;
+mod notebook;
+mod semantic;
+pub use hunks::{parse_patch, Hunk, PatchFile};
+pub use notebook::{is_notebook, notebook_patch, NotebookOptions};
+pub use semantic::{render_semantic, semantic_diff, StructuredFormat};
+
 use std::io::{Read, Write};
 
 use anyhow::Result;
 use chrono::{DateTime, Utc};
 use similar::{ChangeTag, TextDiff};
 
-use crate::models::{FileOp, FileRecord, FileStats, RecordStats};
+use crate::models::{FileOp, FileRecord, FileStats, RecordStats, SemanticFileDiff};
 use crate::util;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct FileInput {
     pub path: String,
     pub before: Option<Vec<u8>>,
     pub after: Option<Vec<u8>>,
+    pub before_mode: Option<u32>,
+    pub after_mode: Option<u32>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct DiffOptions {
+    /// Emit a path-based change list for JSON, TOML and YAML files.
+    pub semantic: bool,
+    pub notebook: NotebookOptions,
 }
 
 #[derive(Debug, Clone)]
 pub struct FileArtifact {
     pub record: FileRecord,
     pub patch: String,
+    pub semantic: Option<SemanticFileDiff>,
     pub before_blob: Option<Vec<u8>>,
     pub after_blob: Option<Vec<u8>>,
 }
@@ -30,11 +47,19 @@ pub struct RecordArtifact {
     pub ended_at: DateTime<Utc>,
 }
 
-pub fn build_file_artifact(input: FileInput) -> Result<Option<FileArtifact>> {
+pub fn build_file_artifact(
+    input: FileInput,
+    options: &DiffOptions,
+) -> Result<Option<FileArtifact>> {
     let before = input.before;
     let after = input.after;
+    // snapshots taken before modes were tracked carry no mode; treat as unchanged
+    let mode_changed = matches!(
+        (input.before_mode, input.after_mode),
+        (Some(old), Some(new)) if old != new
+    );
 
-    if before.is_some() && after.is_some() && before == after {
+    if before.is_some() && after.is_some() && before == after && !mode_changed {
         return Ok(None);
     }
 
@@ -47,7 +72,8 @@ pub fn build_file_artifact(input: FileInput) -> Result<Option<FileArtifact>> {
         None => (None, None),
     };
 
-    if before_sha.is_some() && after_sha.is_some() && before_sha == after_sha {
+    let content_changed = before_sha != after_sha;
+    if before_sha.is_some() && after_sha.is_some() && !content_changed && !mode_changed {
         return Ok(None);
     }
 
@@ -58,7 +84,48 @@ pub fn build_file_artifact(input: FileInput) -> Result<Option<FileArtifact>> {
         (false, false) => return Ok(None),
     };
 
-    let (patch, stats) = build_patch(&input.path, before_blob.as_ref(), after_blob.as_ref())?;
+    let notebook = if options.notebook.enabled && is_notebook(&input.path) {
+        notebook_patch(
+            &input.path,
+            before_blob.as_deref(),
+            after_blob.as_deref(),
+            &options.notebook,
+        )
+    } else {
+        None
+    };
+    let (patch, stats) = match (notebook, input.before_mode, input.after_mode) {
+        (Some(result), _, _) => result,
+        (None, Some(old), Some(new))
+            if util::is_symlink_mode(old) != util::is_symlink_mode(new) =>
+        {
+            type_change_patch(&input.path, &before_blob, &after_blob, old, new)?
+        }
+        (None, _, _) => {
+            let (mut patch, stats) =
+                build_patch(&input.path, before_blob.as_ref(), after_blob.as_ref())?;
+            let header = mode_header(&input.path, &op, input.before_mode, input.after_mode);
+            patch.insert_str(0, &header);
+            (patch, stats)
+        }
+    };
+    let semantic = match (
+        options.semantic && content_changed,
+        &before_blob,
+        &after_blob,
+    ) {
+        (true, Some(old_bytes), Some(new_bytes)) => {
+            semantic_diff(&input.path, old_bytes, new_bytes)
+        }
+        _ => None,
+    };
+
+    let symlink_target = match (input.after_mode, &after_blob) {
+        (Some(mode), Some(bytes)) if util::is_symlink_mode(mode) => {
+            Some(String::from_utf8_lossy(bytes).to_string())
+        }
+        _ => None,
+    };
 
     let record = FileRecord {
         path: input.path,
@@ -66,16 +133,85 @@ pub fn build_file_artifact(input: FileInput) -> Result<Option<FileArtifact>> {
         before_sha,
         after_sha,
         stats,
+        before_mode: input.before_mode,
+        after_mode: input.after_mode,
+        symlink_target,
     };
 
     Ok(Some(FileArtifact {
         record,
         patch,
+        semantic,
         before_blob,
         after_blob,
     }))
 }
 
+pub fn directory_artifact(path: &str, op: FileOp) -> FileArtifact {
+    let patch = match op {
+        FileOp::DirRemoved => format!("Directory removed: {path}/\n"),
+        _ => format!("Directory created: {path}/\n"),
+    };
+    FileArtifact {
+        record: FileRecord {
+            path: path.to_string(),
+            op,
+            before_sha: None,
+            after_sha: None,
+            stats: FileStats::default(),
+            before_mode: None,
+            after_mode: None,
+            symlink_target: None,
+        },
+        patch,
+        semantic: None,
+        before_blob: None,
+        after_blob: None,
+    }
+}
+
+fn mode_header(path: &str, op: &FileOp, before: Option<u32>, after: Option<u32>) -> String {
+    let line = match (op, before, after) {
+        (FileOp::Modified, Some(old), Some(new)) if old != new => {
+            format!("old mode {old:o}\nnew mode {new:o}\n")
+        }
+        (FileOp::Added, _, Some(new)) if util::is_symlink_mode(new) => {
+            format!("new file mode {new:o}\n")
+        }
+        (FileOp::Deleted, Some(old), _) if util::is_symlink_mode(old) => {
+            format!("deleted file mode {old:o}\n")
+        }
+        _ => return String::new(),
+    };
+    format!("diff --git a/{path} b/{path}\n{line}")
+}
+
+/// A file turned into a symlink or back: git spells this as a delete of the
+/// old entry followed by a create of the new one.
+fn type_change_patch(
+    path: &str,
+    before: &Option<Vec<u8>>,
+    after: &Option<Vec<u8>>,
+    before_mode: u32,
+    after_mode: u32,
+) -> Result<(String, FileStats)> {
+    let (removed_patch, removed) = build_patch(path, before.as_ref(), None)?;
+    let (added_patch, added) = build_patch(path, None, after.as_ref())?;
+    let patch = format!(
+        "diff --git a/{path} b/{path}\ndeleted file mode {before_mode:o}\n{removed_patch}\
+         diff --git a/{path} b/{path}\nnew file mode {after_mode:o}\n{added_patch}"
+    );
+    Ok((
+        patch,
+        FileStats {
+            added: added.added,
+            removed: removed.removed,
+            chunks: added.chunks + removed.chunks,
+            cells: None,
+        },
+    ))
+}
+
 fn build_patch(
     path: &str,
     before: Option<&Vec<u8>>,
@@ -104,6 +240,7 @@ fn build_patch(
                     added,
                     removed,
                     chunks,
+                    cells: None,
                 },
             ))
         }
@@ -124,6 +261,7 @@ fn build_patch(
                     added,
                     removed: 0,
                     chunks: diff.ops().len(),
+                    cells: None,
                 },
             ))
         }
@@ -144,6 +282,7 @@ fn build_patch(
                     added: 0,
                     removed,
                     chunks: diff.ops().len(),
+                    cells: None,
                 },
             ))
         }
@@ -195,6 +334,7 @@ fn binary_patch(path: &str) -> (String, FileStats) {
             added: 0,
             removed: 0,
             chunks: 1,
+            cells: None,
         },
     )
 }
@@ -0,0 +1,222 @@
+use serde_json::Value;
+use similar::{capture_diff_slices, Algorithm, ChangeTag, DiffOp, TextDiff};
+
+use crate::models::{CellStats, FileStats};
+
+#[derive(Debug, Clone, Default)]
+pub struct NotebookOptions {
+    /// Diff `.ipynb` files cell by cell instead of as raw JSON lines.
+    pub enabled: bool,
+    pub ignore_outputs: bool,
+    pub ignore_execution_count: bool,
+    pub ignore_metadata: bool,
+}
+
+struct Cell {
+    cell_type: String,
+    source: String,
+    outputs: Vec<Value>,
+    execution_count: Option<Value>,
+    metadata: Value,
+}
+
+pub fn is_notebook(path: &str) -> bool {
+    path.to_ascii_lowercase().ends_with(".ipynb")
+}
+
+/// Builds a cell-level patch for a notebook. Returns `None` when either side
+/// is not a parseable notebook so callers can fall back to the line diff.
+pub fn notebook_patch(
+    path: &str,
+    before: Option<&[u8]>,
+    after: Option<&[u8]>,
+    options: &NotebookOptions,
+) -> Option<(String, FileStats)> {
+    let old_nb = match before {
+        Some(bytes) => Some(parse_notebook(bytes)?),
+        None => None,
+    };
+    let new_nb = match after {
+        Some(bytes) => Some(parse_notebook(bytes)?),
+        None => None,
+    };
+    let empty = (Value::Null, Vec::new());
+    let (old_meta, old_cells) = old_nb.as_ref().unwrap_or(&empty);
+    let (new_meta, new_cells) = new_nb.as_ref().unwrap_or(&empty);
+
+    let old_text = render_notebook(old_meta, old_cells, options);
+    let new_text = render_notebook(new_meta, new_cells, options);
+    let mut cells = cell_stats(old_cells, new_cells, options);
+
+    let diff = TextDiff::from_lines(old_text.as_str(), new_text.as_str());
+    let mut added = 0usize;
+    let mut removed = 0usize;
+    for change in diff.iter_all_changes() {
+        match change.tag() {
+            ChangeTag::Insert => added += 1,
+            ChangeTag::Delete => removed += 1,
+            ChangeTag::Equal => {}
+        }
+    }
+    let old_header = match before {
+        Some(_) => format!("a/{path}"),
+        None => "/dev/null".to_string(),
+    };
+    let new_header = match after {
+        Some(_) => format!("b/{path}"),
+        None => "/dev/null".to_string(),
+    };
+    let patch = if old_text == new_text {
+        cells.ignored_only = true;
+        String::new()
+    } else {
+        diff.unified_diff()
+            .header(&old_header, &new_header)
+            .to_string()
+    };
+    Some((
+        patch,
+        FileStats {
+            added,
+            removed,
+            chunks: diff.ops().len(),
+            cells: Some(cells),
+        },
+    ))
+}
+
+fn parse_notebook(bytes: &[u8]) -> Option<(Value, Vec<Cell>)> {
+    let root: Value = serde_json::from_slice(bytes).ok()?;
+    let cells = root
+        .get("cells")?
+        .as_array()?
+        .iter()
+        .map(|cell| Cell {
+            cell_type: cell
+                .get("cell_type")
+                .and_then(Value::as_str)
+                .unwrap_or("code")
+                .to_string(),
+            source: join_text(cell.get("source")),
+            outputs: cell
+                .get("outputs")
+                .and_then(Value::as_array)
+                .cloned()
+                .unwrap_or_default(),
+            execution_count: cell
+                .get("execution_count")
+                .filter(|v| !v.is_null())
+                .cloned(),
+            metadata: cell.get("metadata").cloned().unwrap_or(Value::Null),
+        })
+        .collect();
+    let metadata = root.get("metadata").cloned().unwrap_or(Value::Null);
+    Some((metadata, cells))
+}
+
+fn join_text(value: Option<&Value>) -> String {
+    match value {
+        Some(Value::String(text)) => text.clone(),
+        Some(Value::Array(parts)) => parts.iter().filter_map(Value::as_str).collect(),
+        _ => String::new(),
+    }
+}
+
+fn render_notebook(metadata: &Value, cells: &[Cell], options: &NotebookOptions) -> String {
+    let mut out = String::new();
+    if !options.ignore_metadata && !is_empty(metadata) {
+        out.push_str(&format!("# notebook metadata: {metadata}\n"));
+    }
+    for cell in cells {
+        out.push_str(&render_cell(cell, options));
+    }
+    out
+}
+
+fn render_cell(cell: &Cell, options: &NotebookOptions) -> String {
+    let mut out = format!("# %% [{}]\n", cell.cell_type);
+    out.push_str(&cell.source);
+    if !cell.source.is_empty() && !cell.source.ends_with('\n') {
+        out.push('\n');
+    }
+    if !options.ignore_execution_count {
+        if let Some(ref count) = cell.execution_count {
+            out.push_str(&format!("# execution_count: {count}\n"));
+        }
+    }
+    if !options.ignore_metadata && !is_empty(&cell.metadata) {
+        out.push_str(&format!("# metadata: {}\n", cell.metadata));
+    }
+    if !options.ignore_outputs {
+        for output in &cell.outputs {
+            out.push_str(&render_output(output));
+        }
+    }
+    out
+}
+
+fn render_output(output: &Value) -> String {
+    let kind = output
+        .get("output_type")
+        .and_then(Value::as_str)
+        .unwrap_or("output");
+    let text = match output.get("text") {
+        Some(text) => join_text(Some(text)),
+        None => join_text(output.get("data").and_then(|data| data.get("text/plain"))),
+    };
+    let mut out = format!("# >> {kind}\n");
+    if text.is_empty() {
+        if let Some(Value::Object(data)) = output.get("data") {
+            let mimes: Vec<&str> = data.keys().map(String::as_str).collect();
+            out.push_str(&format!("# << {}\n", mimes.join(", ")));
+        }
+    } else {
+        for line in text.lines() {
+            out.push_str(&format!("# << {line}\n"));
+        }
+    }
+    out
+}
+
+fn is_empty(value: &Value) -> bool {
+    match value {
+        Value::Null => true,
+        Value::Object(map) => map.is_empty(),
+        _ => false,
+    }
+}
+
+fn cell_stats(old_cells: &[Cell], new_cells: &[Cell], options: &NotebookOptions) -> CellStats {
+    let old_keys: Vec<&str> = old_cells.iter().map(|c| c.source.as_str()).collect();
+    let new_keys: Vec<&str> = new_cells.iter().map(|c| c.source.as_str()).collect();
+    let mut stats = CellStats::default();
+    for op in capture_diff_slices(Algorithm::Myers, &old_keys, &new_keys) {
+        match op {
+            DiffOp::Equal {
+                old_index,
+                new_index,
+                len,
+            } => {
+                // same source, but outputs or metadata may still differ
+                for offset in 0..len {
+                    let old = render_cell(&old_cells[old_index + offset], options);
+                    let new = render_cell(&new_cells[new_index + offset], options);
+                    if old != new {
+                        stats.modified += 1;
+                    }
+                }
+            }
+            DiffOp::Delete { old_len, .. } => stats.removed += old_len,
+            DiffOp::Insert { new_len, .. } => stats.added += new_len,
+            DiffOp::Replace {
+                old_len, new_len, ..
+            } => {
+                let paired = old_len.min(new_len);
+                stats.modified += paired;
+                stats.removed += old_len - paired;
+                stats.added += new_len - paired;
+            }
+        }
+    }
+    stats
+}
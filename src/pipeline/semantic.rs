@@ -0,0 +1,173 @@
+use std::collections::BTreeSet;
+
+use anyhow::Result;
+use serde_json::Value;
+
+use crate::models::{SemanticChange, SemanticFileDiff, SemanticOp};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StructuredFormat {
+    Json,
+    Toml,
+    Yaml,
+}
+
+impl StructuredFormat {
+    pub fn detect(path: &str) -> Option<Self> {
+        let ext = path.rsplit_once('.')?.1.to_ascii_lowercase();
+        match ext.as_str() {
+            "json" => Some(Self::Json),
+            "toml" => Some(Self::Toml),
+            "yaml" | "yml" => Some(Self::Yaml),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Json => "json",
+            Self::Toml => "toml",
+            Self::Yaml => "yaml",
+        }
+    }
+
+    pub fn parse(&self, text: &str) -> Result<Value> {
+        let value = match self {
+            Self::Json => serde_json::from_str(text)?,
+            Self::Toml => {
+                let parsed: toml::Value = toml::from_str(text)?;
+                serde_json::to_value(parsed)?
+            }
+            Self::Yaml => {
+                let parsed: serde_yaml::Value = serde_yaml::from_str(text)?;
+                serde_json::to_value(parsed)?
+            }
+        };
+        Ok(value)
+    }
+}
+
+/// Computes a path-based change list for known structured formats.
+///
+/// Returns `None` when the path is not a supported format or either side
+/// fails to parse, in which case callers fall back to the line diff.
+pub fn semantic_diff(path: &str, before: &[u8], after: &[u8]) -> Option<SemanticFileDiff> {
+    let format = StructuredFormat::detect(path)?;
+    let old_value = format.parse(std::str::from_utf8(before).ok()?).ok()?;
+    let new_value = format.parse(std::str::from_utf8(after).ok()?).ok()?;
+    let mut changes = Vec::new();
+    diff_values("", &old_value, &new_value, &mut changes);
+    Some(SemanticFileDiff {
+        path: path.to_string(),
+        format: format.name().to_string(),
+        changes,
+    })
+}
+
+pub fn render_semantic(diff: &SemanticFileDiff) -> String {
+    let mut out = format!("--- a/{}\n+++ b/{}\n", diff.path, diff.path);
+    if diff.changes.is_empty() {
+        out.push_str("  (no structural changes)\n");
+    }
+    for change in &diff.changes {
+        let line = match change.op {
+            SemanticOp::Added => format!("+ {}: {}", change.path, render_value(&change.after)),
+            SemanticOp::Removed => format!("- {}: {}", change.path, render_value(&change.before)),
+            SemanticOp::Changed => format!(
+                "~ {}: {} -> {}",
+                change.path,
+                render_value(&change.before),
+                render_value(&change.after)
+            ),
+        };
+        out.push_str(&line);
+        out.push('\n');
+    }
+    out
+}
+
+fn render_value(value: &Option<Value>) -> String {
+    match value {
+        Some(v) => v.to_string(),
+        None => "null".to_string(),
+    }
+}
+
+fn diff_values(path: &str, before: &Value, after: &Value, out: &mut Vec<SemanticChange>) {
+    match (before, after) {
+        (Value::Object(old_map), Value::Object(new_map)) => {
+            let keys: BTreeSet<&String> = old_map.keys().chain(new_map.keys()).collect();
+            for key in keys {
+                let child = join_key(path, key);
+                match (old_map.get(key), new_map.get(key)) {
+                    (Some(old), Some(new)) => diff_values(&child, old, new, out),
+                    (Some(old), None) => out.push(removed(child, old)),
+                    (None, Some(new)) => out.push(added(child, new)),
+                    (None, None) => {}
+                }
+            }
+        }
+        (Value::Array(old_items), Value::Array(new_items)) => {
+            let len = old_items.len().max(new_items.len());
+            for idx in 0..len {
+                let child = format!("{path}[{idx}]");
+                match (old_items.get(idx), new_items.get(idx)) {
+                    (Some(old), Some(new)) => diff_values(&child, old, new, out),
+                    (Some(old), None) => out.push(removed(child, old)),
+                    (None, Some(new)) => out.push(added(child, new)),
+                    (None, None) => {}
+                }
+            }
+        }
+        _ => {
+            if before != after {
+                out.push(SemanticChange {
+                    path: display_path(path),
+                    op: SemanticOp::Changed,
+                    before: Some(before.clone()),
+                    after: Some(after.clone()),
+                });
+            }
+        }
+    }
+}
+
+fn added(path: String, value: &Value) -> SemanticChange {
+    SemanticChange {
+        path: display_path(&path),
+        op: SemanticOp::Added,
+        before: None,
+        after: Some(value.clone()),
+    }
+}
+
+fn removed(path: String, value: &Value) -> SemanticChange {
+    SemanticChange {
+        path: display_path(&path),
+        op: SemanticOp::Removed,
+        before: Some(value.clone()),
+        after: None,
+    }
+}
+
+fn join_key(path: &str, key: &str) -> String {
+    let simple = !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    if !simple {
+        format!("{path}[{}]", Value::String(key.to_string()))
+    } else if path.is_empty() {
+        key.to_string()
+    } else {
+        format!("{path}.{key}")
+    }
+}
+
+fn display_path(path: &str) -> String {
+    if path.is_empty() {
+        "(root)".to_string()
+    } else {
+        path.to_string()
+    }
+}
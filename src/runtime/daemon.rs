@@ -0,0 +1,151 @@
+use std::fs::File;
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+
+/// Environment variable carrying the write end of the readiness pipe from
+/// `watch --daemon` to the detached watcher.
+pub const READY_FD_ENV: &str = "MEOWDIFF_READY_FD";
+
+/// Tells a parent waiting in `watch --daemon` whether startup succeeded.
+/// Only the first message is sent; a no-op when not started as a daemon.
+#[derive(Clone, Default)]
+pub struct ReadySignal {
+    pipe: Arc<Mutex<Option<File>>>,
+}
+
+impl ReadySignal {
+    /// Picks up the pipe handed down by the parent, if any.
+    pub fn from_env() -> Self {
+        #[cfg(unix)]
+        {
+            use std::os::unix::io::{FromRawFd, RawFd};
+            let fd = std::env::var(READY_FD_ENV)
+                .ok()
+                .and_then(|value| value.parse::<RawFd>().ok());
+            if let Some(fd) = fd {
+                std::env::remove_var(READY_FD_ENV);
+                // SAFETY: the parent opened this descriptor for us and nothing
+                // else in the process owns it
+                let file = unsafe { File::from_raw_fd(fd) };
+                return Self {
+                    pipe: Arc::new(Mutex::new(Some(file))),
+                };
+            }
+        }
+        Self::default()
+    }
+
+    pub fn ready(&self) {
+        self.send("ready");
+    }
+
+    pub fn failed(&self, err: &anyhow::Error) {
+        self.send(&format!("error: {err:#}"));
+    }
+
+    fn send(&self, message: &str) {
+        if let Some(mut pipe) = self.pipe.lock().unwrap().take() {
+            let _ = writeln!(pipe, "{}", message.replace('\n', " "));
+        }
+    }
+}
+
+#[cfg(unix)]
+pub use unix::spawn_detached;
+
+#[cfg(unix)]
+mod unix {
+    use std::fs::{File, OpenOptions};
+    use std::io::{BufRead, BufReader};
+    use std::os::unix::io::FromRawFd;
+    use std::os::unix::process::CommandExt;
+    use std::path::Path;
+    use std::process::{Command, Stdio};
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    use anyhow::{bail, Context, Result};
+
+    use super::READY_FD_ENV;
+    use crate::runtime::log::{CURRENT_LOG, STDERR_LOG};
+    use crate::util;
+
+    /// Starts `cmd` in its own session and waits until it reports readiness
+    /// over a pipe. Returns the child's pid, or the error it reported.
+    pub fn spawn_detached(mut cmd: Command, log_dir: &Path, timeout: Duration) -> Result<u32> {
+        util::ensure_dir(log_dir)?;
+        let log = log_dir.join(CURRENT_LOG);
+        let stderr_path = log_dir.join(STDERR_LOG);
+        let stderr = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&stderr_path)
+            .with_context(|| format!("failed to open {}", stderr_path.display()))?;
+
+        let mut fds = [0; 2];
+        // SAFETY: fds is a valid two-element buffer
+        if unsafe { libc::pipe(fds.as_mut_ptr()) } != 0 {
+            return Err(std::io::Error::last_os_error()).context("failed to create readiness pipe");
+        }
+        let [read_fd, write_fd] = fds;
+        // only the write end may survive exec in the child
+        // SAFETY: read_fd is an open descriptor we own; F_SETFD touches no memory
+        unsafe { libc::fcntl(read_fd, libc::F_SETFD, libc::FD_CLOEXEC) };
+        // SAFETY: read_fd was just created and is owned here
+        let reader = unsafe { File::from_raw_fd(read_fd) };
+
+        cmd.env(READY_FD_ENV, write_fd.to_string())
+            .current_dir("/")
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(stderr);
+        // SAFETY: the closure runs between fork and exec, where only
+        // async-signal-safe calls are allowed; setsid is one and the closure
+        // neither allocates nor takes locks
+        unsafe {
+            cmd.pre_exec(|| {
+                if libc::setsid() == -1 {
+                    return Err(std::io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+        let spawned = cmd.spawn();
+        // the parent must drop its copy or EOF never arrives
+        // SAFETY: write_fd came from pipe() above, is not wrapped in any owner
+        // and is closed exactly once here
+        unsafe { libc::close(write_fd) };
+        let mut child = spawned.context("failed to spawn watcher daemon")?;
+
+        let (tx, rx) = mpsc::channel();
+        std::thread::spawn(move || {
+            let mut line = String::new();
+            let _ = BufReader::new(reader).read_line(&mut line);
+            let _ = tx.send(line);
+        });
+        let line = match rx.recv_timeout(timeout) {
+            Ok(line) => line,
+            Err(_) => bail!(
+                "watcher daemon (pid {}) did not report readiness within {}s; see {}",
+                child.id(),
+                timeout.as_secs(),
+                log.display()
+            ),
+        };
+        let line = line.trim();
+        if line == "ready" {
+            return Ok(child.id());
+        }
+        if let Some(message) = line.strip_prefix("error: ") {
+            let _ = child.wait();
+            bail!("watcher daemon failed to start: {message}");
+        }
+        // pipe closed without a word: the child died before it could report
+        let status = child.wait()?;
+        bail!(
+            "watcher daemon exited during startup ({status}); see {} and {}",
+            log.display(),
+            stderr_path.display()
+        )
+    }
+}
@@ -0,0 +1,117 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, NaiveDate, Utc};
+
+pub const CURRENT_LOG: &str = "current.log";
+/// Raw stderr of a daemonized watcher (panics, early failures); not rotated.
+pub const STDERR_LOG: &str = "stderr.log";
+const DEFAULT_MAX_BYTES: u64 = 16 * 1024 * 1024;
+const DEFAULT_KEEP: usize = 7;
+
+/// Log file that rolls over daily or once it grows past a size cap.
+///
+/// The live file is always `current.log`; rolled files are renamed to
+/// `<date>.log` (`<date>.<n>.log` on repeat) and only the newest are kept.
+pub struct RotatingLog {
+    dir: PathBuf,
+    file: File,
+    opened_on: NaiveDate,
+    bytes: u64,
+    max_bytes: u64,
+    keep: usize,
+}
+
+impl RotatingLog {
+    pub fn open(dir: &Path) -> Result<Self> {
+        Self::with_limits(dir, DEFAULT_MAX_BYTES, DEFAULT_KEEP)
+    }
+
+    pub fn with_limits(dir: &Path, max_bytes: u64, keep: usize) -> Result<Self> {
+        fs::create_dir_all(dir).with_context(|| format!("failed to create {}", dir.display()))?;
+        let path = dir.join(CURRENT_LOG);
+        let file =
+            open_append(&path).with_context(|| format!("failed to open {}", path.display()))?;
+        let meta = file.metadata()?;
+        // an existing file belongs to the day it was last written
+        let opened_on = meta
+            .modified()
+            .map(|time| DateTime::<Utc>::from(time).date_naive())
+            .unwrap_or_else(|_| Utc::now().date_naive());
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            file,
+            opened_on,
+            bytes: meta.len(),
+            max_bytes,
+            keep,
+        })
+    }
+
+    pub fn path(&self) -> PathBuf {
+        self.dir.join(CURRENT_LOG)
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        let current = self.path();
+        let stem = self.opened_on.format("%Y-%m-%d").to_string();
+        let mut archive = self.dir.join(format!("{stem}.log"));
+        let mut n = 1;
+        while archive.exists() {
+            archive = self.dir.join(format!("{stem}.{n}.log"));
+            n += 1;
+        }
+        fs::rename(&current, &archive)?;
+        self.file = open_append(&current)?;
+        self.opened_on = Utc::now().date_naive();
+        self.bytes = 0;
+        self.prune()
+    }
+
+    fn prune(&self) -> io::Result<()> {
+        let mut archives: Vec<(std::time::SystemTime, PathBuf)> = fs::read_dir(&self.dir)?
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| {
+                path.extension().is_some_and(|ext| ext == "log")
+                    && path
+                        .file_name()
+                        .is_some_and(|name| name != CURRENT_LOG && name != STDERR_LOG)
+            })
+            .filter_map(|path| {
+                let modified = path.metadata().and_then(|meta| meta.modified()).ok()?;
+                Some((modified, path))
+            })
+            .collect();
+        archives.sort();
+        let excess = archives.len().saturating_sub(self.keep);
+        for (_, path) in archives.into_iter().take(excess) {
+            fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+}
+
+impl Write for RotatingLog {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let new_day = Utc::now().date_naive() != self.opened_on;
+        let full = self.bytes > 0 && self.bytes + buf.len() as u64 > self.max_bytes;
+        if new_day || full {
+            self.rotate()?;
+        }
+        let written = self.file.write(buf)?;
+        self.bytes += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+fn open_append(path: &Path) -> io::Result<File> {
+    OpenOptions::new().create(true).append(true).open(path)
+}
@@ -1,14 +1,60 @@
+mod daemon;
+mod log;
+mod service;
+#[cfg(unix)]
+pub use daemon::spawn_detached;
+pub use daemon::{ReadySignal, READY_FD_ENV};
+pub use log::{RotatingLog, CURRENT_LOG, STDERR_LOG};
+pub use service::{current_service, render_unit, unit_dir, ServiceTarget, SERVICE_ENV};
+
+use std::path::Path;
+use std::sync::Mutex;
+
 use anyhow::Result;
 use tracing_subscriber::{fmt, EnvFilter};
 
-pub fn init_tracing(verbose: u8) -> Result<()> {
+/// Where [`init_tracing`] sends log lines.
+pub enum LogOutput<'a> {
+    Terminal,
+    /// A rotating `current.log` in this directory, for detached processes.
+    File(&'a Path),
+    /// Plain lines on stderr for a supervisor such as systemd, whose journal
+    /// adds its own timestamps.
+    Stderr,
+}
+
+pub fn init_tracing(verbose: u8, output: LogOutput<'_>) -> Result<()> {
     let level = match verbose {
         0 => "info",
         1 => "debug",
         _ => "trace",
     };
     let filter = EnvFilter::try_from_default_env().or_else(|_| EnvFilter::try_new(level))?;
-    let subscriber = fmt().with_env_filter(filter).with_target(false).compact();
-    let _ = subscriber.try_init();
+    match output {
+        LogOutput::File(dir) => {
+            let writer = Mutex::new(RotatingLog::open(dir)?);
+            let subscriber = fmt()
+                .with_env_filter(filter)
+                .with_target(false)
+                .with_ansi(false)
+                .with_writer(writer)
+                .compact();
+            let _ = subscriber.try_init();
+        }
+        LogOutput::Stderr => {
+            let subscriber = fmt()
+                .with_env_filter(filter)
+                .with_target(false)
+                .with_ansi(false)
+                .without_time()
+                .with_writer(std::io::stderr)
+                .compact();
+            let _ = subscriber.try_init();
+        }
+        LogOutput::Terminal => {
+            let subscriber = fmt().with_env_filter(filter).with_target(false).compact();
+            let _ = subscriber.try_init();
+        }
+    }
     Ok(())
 }
@@ -0,0 +1,129 @@
+//! systemd `--user` units that keep a watcher (or the multi-project daemon)
+//! running across logins and crashes.
+
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use directories::BaseDirs;
+
+/// Set in every generated unit so the watcher can record which unit runs it.
+pub const SERVICE_ENV: &str = "MEOWDIFF_SERVICE";
+
+/// What a unit runs.
+#[derive(Debug, Clone)]
+pub enum ServiceTarget {
+    Project {
+        project_id: String,
+        root: PathBuf,
+    },
+    /// `meowdiff daemon`, watching every enabled project.
+    Daemon,
+}
+
+impl ServiceTarget {
+    pub fn unit_name(&self) -> String {
+        match self {
+            Self::Project { project_id, .. } => format!("meowdiff-{project_id}.service"),
+            Self::Daemon => "meowdiff-daemon.service".to_string(),
+        }
+    }
+
+    fn description(&self) -> String {
+        match self {
+            Self::Project { root, .. } => format!("MeowDiff watcher for {}", root.display()),
+            Self::Daemon => "MeowDiff daemon for enabled projects".to_string(),
+        }
+    }
+
+    fn args(&self) -> Vec<String> {
+        match self {
+            Self::Project { root, .. } => vec![
+                "watch".into(),
+                "--foreground".into(),
+                // systemd collects stderr into the journal
+                "--log-stderr".into(),
+                "--path".into(),
+                root.to_string_lossy().into_owned(),
+            ],
+            Self::Daemon => vec![
+                "daemon".into(),
+                "--foreground".into(),
+                "--log-stderr".into(),
+            ],
+        }
+    }
+}
+
+/// Directory systemd searches for user units, honouring `XDG_CONFIG_HOME`.
+pub fn unit_dir() -> Result<PathBuf> {
+    let base = BaseDirs::new().context("failed to locate home directory")?;
+    Ok(base.config_dir().join("systemd").join("user"))
+}
+
+/// Renders the unit file that runs `target` with the binary at `exe`.
+pub fn render_unit(exe: &Path, target: &ServiceTarget) -> String {
+    let mut exec = quote_exec_arg(&exe.to_string_lossy());
+    for arg in target.args() {
+        exec.push(' ');
+        exec.push_str(&quote_exec_arg(&arg));
+    }
+    let mut unit = String::new();
+    let _ = writeln!(unit, "[Unit]");
+    let _ = writeln!(
+        unit,
+        "Description={}",
+        escape_specifiers(&target.description())
+    );
+    let _ = writeln!(unit);
+    let _ = writeln!(unit, "[Service]");
+    let _ = writeln!(unit, "Type=simple");
+    let _ = writeln!(unit, "ExecStart={exec}");
+    let _ = writeln!(unit, "Environment={SERVICE_ENV}={}", target.unit_name());
+    let _ = writeln!(unit, "Restart=on-failure");
+    let _ = writeln!(unit, "RestartSec=5");
+    let _ = writeln!(unit);
+    let _ = writeln!(unit, "[Install]");
+    let _ = writeln!(unit, "WantedBy=default.target");
+    unit
+}
+
+/// Unit running the current process, when started from a generated unit.
+pub fn current_service() -> Option<String> {
+    std::env::var(SERVICE_ENV)
+        .ok()
+        .filter(|unit| !unit.is_empty())
+}
+
+/// `%` starts a specifier anywhere in a unit file.
+fn escape_specifiers(value: &str) -> String {
+    value.replace('%', "%%")
+}
+
+/// Quotes one `ExecStart=` word so spaces, quotes and backslashes survive
+/// systemd's command-line splitting.
+fn quote_exec_arg(arg: &str) -> String {
+    let arg = escape_specifiers(arg);
+    let plain = !arg.is_empty()
+        && !arg
+            .chars()
+            .any(|c| c.is_whitespace() || matches!(c, '"' | '\'' | '\\' | ';' | '$'));
+    if plain {
+        return arg;
+    }
+    let mut quoted = String::with_capacity(arg.len() + 2);
+    quoted.push('"');
+    for c in arg.chars() {
+        match c {
+            '"' | '\\' => {
+                quoted.push('\\');
+                quoted.push(c);
+            }
+            // `$` would otherwise expand an environment variable
+            '$' => quoted.push_str("$$"),
+            _ => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
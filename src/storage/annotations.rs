@@ -0,0 +1,305 @@
+//! Notes, tags and bookmarks users attach to records.
+//!
+//! All three point at a record id. Tags are unique names that resolve back
+//! to their record wherever a record id is accepted; bookmarks are unnamed
+//! markers on records worth coming back to.
+
+use std::collections::{HashMap, HashSet};
+
+use anyhow::{anyhow, bail, Result};
+use chrono::{DateTime, Utc};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::Serialize;
+
+use super::StorageEngine;
+use crate::models::TimelineEntry;
+
+/// Stands for the newest record wherever a record id is accepted.
+pub const LATEST_REF: &str = "now";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Note {
+    pub record_id: String,
+    pub text: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Tag {
+    pub name: String,
+    pub record_id: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Bookmark {
+    pub record_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl StorageEngine {
+    /// Turns a record id, a tag name or `now` into a record id.
+    pub fn resolve_record(&self, reference: &str) -> Result<String> {
+        if reference == LATEST_REF {
+            return self
+                .latest_record_id()?
+                .ok_or_else(|| anyhow!("no records yet"));
+        }
+        let conn = self.conn.lock().unwrap();
+        let record = conn
+            .query_row(
+                "SELECT record_id FROM records WHERE record_id = ?1",
+                [reference],
+                |row| row.get(0),
+            )
+            .optional()?;
+        let record = match record {
+            Some(record) => Some(record),
+            None => conn
+                .query_row(
+                    "SELECT record_id FROM tags WHERE name = ?1",
+                    [reference],
+                    |row| row.get(0),
+                )
+                .optional()?,
+        };
+        record.ok_or_else(|| anyhow!("unknown record or tag {reference}"))
+    }
+
+    pub fn add_note(&self, record_id: &str, text: &str) -> Result<Note> {
+        let note = Note {
+            record_id: self.resolve_record(record_id)?,
+            text: text.to_string(),
+            created_at: Utc::now(),
+        };
+        self.conn.lock().unwrap().execute(
+            "INSERT INTO notes (record_id, text, created_at) VALUES (?1, ?2, ?3)",
+            params![
+                note.record_id,
+                note.text,
+                note.created_at.timestamp_millis()
+            ],
+        )?;
+        Ok(note)
+    }
+
+    /// Notes on `record_id`, oldest first.
+    pub fn notes(&self, record_id: &str) -> Result<Vec<Note>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT record_id, text, created_at FROM notes WHERE record_id = ?1 ORDER BY id",
+        )?;
+        let rows = stmt.query_map([record_id], |row| {
+            Ok(Note {
+                record_id: row.get(0)?,
+                text: row.get(1)?,
+                created_at: timestamp(row.get(2)?),
+            })
+        })?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    /// Points tag `name` at `target`. Moving an existing tag needs `force`.
+    /// Returns the record the tag pointed at before, if any.
+    pub fn set_tag(&self, name: &str, target: &str, force: bool) -> Result<(Tag, Option<String>)> {
+        validate_tag_name(name)?;
+        let tag = Tag {
+            name: name.to_string(),
+            record_id: self.resolve_record(target)?,
+            created_at: Utc::now(),
+        };
+        let conn = self.conn.lock().unwrap();
+        let shadows_record: bool = conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM records WHERE record_id = ?1)",
+            [name],
+            |row| row.get(0),
+        )?;
+        if shadows_record {
+            bail!("{name} is a record id; pick another tag name");
+        }
+        let previous: Option<String> = conn
+            .query_row(
+                "SELECT record_id FROM tags WHERE name = ?1",
+                [name],
+                |row| row.get(0),
+            )
+            .optional()?;
+        if let Some(previous) = &previous {
+            if !force && previous != &tag.record_id {
+                bail!("tag {name} already points at {previous}; use --force to move it");
+            }
+        }
+        conn.execute(
+            "INSERT INTO tags (name, record_id, created_at) VALUES (?1, ?2, ?3) ON CONFLICT(name) DO UPDATE SET record_id=excluded.record_id, created_at=excluded.created_at",
+            params![tag.name, tag.record_id, tag.created_at.timestamp_millis()],
+        )?;
+        Ok((tag, previous))
+    }
+
+    /// Removes tag `name`; false when there was no such tag.
+    pub fn delete_tag(&self, name: &str) -> Result<bool> {
+        let conn = self.conn.lock().unwrap();
+        Ok(conn.execute("DELETE FROM tags WHERE name = ?1", [name])? > 0)
+    }
+
+    /// All tags, by name.
+    pub fn tags(&self) -> Result<Vec<Tag>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt =
+            conn.prepare("SELECT name, record_id, created_at FROM tags ORDER BY name")?;
+        let rows = stmt.query_map([], |row| {
+            Ok(Tag {
+                name: row.get(0)?,
+                record_id: row.get(1)?,
+                created_at: timestamp(row.get(2)?),
+            })
+        })?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    pub fn add_bookmark(&self, record_id: &str, message: Option<&str>) -> Result<Bookmark> {
+        let bookmark = Bookmark {
+            record_id: self.resolve_record(record_id)?,
+            message: message.map(str::to_string),
+            created_at: Utc::now(),
+        };
+        self.conn.lock().unwrap().execute(
+            "INSERT INTO bookmarks (record_id, message, created_at) VALUES (?1, ?2, ?3)",
+            params![
+                bookmark.record_id,
+                bookmark.message,
+                bookmark.created_at.timestamp_millis()
+            ],
+        )?;
+        Ok(bookmark)
+    }
+
+    /// All bookmarks, newest first.
+    pub fn bookmarks(&self) -> Result<Vec<Bookmark>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT record_id, message, created_at FROM bookmarks ORDER BY created_at DESC, id DESC",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok(Bookmark {
+                record_id: row.get(0)?,
+                message: row.get(1)?,
+                created_at: timestamp(row.get(2)?),
+            })
+        })?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    /// Copies what `other` attached to `record_id`, given all of its `tags`
+    /// and `bookmarks`. Tags whose name is already taken here keep pointing
+    /// where they do.
+    pub(super) fn copy_annotations(
+        &self,
+        other: &StorageEngine,
+        record_id: &str,
+        tags: &[Tag],
+        bookmarks: &[Bookmark],
+    ) -> Result<()> {
+        let notes = other.notes(record_id)?;
+        let tags = tags.iter().filter(|tag| tag.record_id == record_id);
+        // oldest first, so they keep their order here
+        let bookmarks = bookmarks
+            .iter()
+            .rev()
+            .filter(|bookmark| bookmark.record_id == record_id);
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        for note in notes {
+            tx.execute(
+                "INSERT INTO notes (record_id, text, created_at) VALUES (?1, ?2, ?3)",
+                params![
+                    note.record_id,
+                    note.text,
+                    note.created_at.timestamp_millis()
+                ],
+            )?;
+        }
+        for tag in tags {
+            tx.execute(
+                "INSERT OR IGNORE INTO tags (name, record_id, created_at) VALUES (?1, ?2, ?3)",
+                params![tag.name, tag.record_id, tag.created_at.timestamp_millis()],
+            )?;
+        }
+        for bookmark in bookmarks {
+            tx.execute(
+                "INSERT INTO bookmarks (record_id, message, created_at) VALUES (?1, ?2, ?3)",
+                params![
+                    bookmark.record_id,
+                    bookmark.message,
+                    bookmark.created_at.timestamp_millis()
+                ],
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+}
+
+/// Fills in the notes, tags and bookmark flag of timeline entries, reading
+/// only the annotations of those records.
+pub(super) fn annotate_entries(conn: &Connection, entries: &mut [TimelineEntry]) -> Result<()> {
+    if entries.is_empty() {
+        return Ok(());
+    }
+    // one JSON array parameter, however many entries there are
+    let ids = serde_json::to_string(
+        &entries
+            .iter()
+            .map(|entry| entry.record_id.as_str())
+            .collect::<Vec<_>>(),
+    )?;
+    let mut notes: HashMap<String, Vec<String>> = HashMap::new();
+    let mut stmt = conn.prepare(
+        "SELECT record_id, text FROM notes WHERE record_id IN (SELECT value FROM json_each(?1)) ORDER BY id",
+    )?;
+    let rows = stmt.query_map([&ids], |row| Ok((row.get::<_, String>(0)?, row.get(1)?)))?;
+    for row in rows {
+        let (record_id, text) = row?;
+        notes.entry(record_id).or_default().push(text);
+    }
+    let mut tags: HashMap<String, Vec<String>> = HashMap::new();
+    let mut stmt = conn.prepare(
+        "SELECT record_id, name FROM tags WHERE record_id IN (SELECT value FROM json_each(?1)) ORDER BY name",
+    )?;
+    let rows = stmt.query_map([&ids], |row| Ok((row.get::<_, String>(0)?, row.get(1)?)))?;
+    for row in rows {
+        let (record_id, name) = row?;
+        tags.entry(record_id).or_default().push(name);
+    }
+    let mut stmt = conn.prepare(
+        "SELECT DISTINCT record_id FROM bookmarks WHERE record_id IN (SELECT value FROM json_each(?1))",
+    )?;
+    let bookmarked = stmt
+        .query_map([&ids], |row| row.get::<_, String>(0))?
+        .collect::<rusqlite::Result<HashSet<_>>>()?;
+
+    for entry in entries {
+        entry.notes = notes.remove(&entry.record_id).map(|texts| texts.join("; "));
+        entry.tags = tags.remove(&entry.record_id).unwrap_or_default();
+        entry.bookmarked = bookmarked.contains(&entry.record_id);
+    }
+    Ok(())
+}
+
+/// Tag names share the namespace of record ids and `A..B` ranges.
+fn validate_tag_name(name: &str) -> Result<()> {
+    if name.is_empty()
+        || name == LATEST_REF
+        || name.contains("..")
+        || name.chars().any(|c| c.is_whitespace() || c.is_control())
+    {
+        bail!("invalid tag name {name:?}");
+    }
+    Ok(())
+}
+
+fn timestamp(millis: i64) -> DateTime<Utc> {
+    DateTime::<Utc>::from_timestamp_millis(millis).unwrap_or_else(Utc::now)
+}
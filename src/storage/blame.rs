@@ -0,0 +1,180 @@
+//! Per-line attribution from the record chain of one file.
+//!
+//! Starting from the current snapshot, each record that touched the file is
+//! undone in turn: lines its diff left unchanged move back to their place in
+//! the older content, the rest are charged to that record. Only records on
+//! the `prev_record_id` chain count, and each must have left the content the
+//! newer one started from.
+
+use std::collections::HashMap;
+
+use anyhow::{anyhow, bail, Result};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use similar::{DiffOp, TextDiff};
+
+use super::StorageEngine;
+use crate::models::{FileOp, FileRecord};
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BlameLine {
+    /// 1-based line number in the current content.
+    pub line: usize,
+    pub content: String,
+    /// The record that last changed the line; `None` when it has been there
+    /// since the baseline.
+    pub record_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+}
+
+/// A line still waiting for its record: its index in the current content
+/// and in the older version being looked at.
+struct Pending {
+    line: usize,
+    at: usize,
+}
+
+/// One record's change to the file being blamed.
+struct FileChange {
+    record_id: String,
+    ended_at: DateTime<Utc>,
+    label: Option<String>,
+    file: FileRecord,
+}
+
+impl StorageEngine {
+    /// Attributes every line of the tracked file `path` to the record that
+    /// last changed it.
+    pub fn blame(&self, path: &str) -> Result<Vec<BlameLine>> {
+        let snapshot = self
+            .fetch_snapshot(path)?
+            .ok_or_else(|| anyhow!("{path} is not tracked"))?;
+        let Ok(text) = String::from_utf8(self.read_blob(&snapshot.sha)?) else {
+            bail!("{path} is a binary file");
+        };
+        let mut blame: Vec<BlameLine> = text
+            .split_inclusive('\n')
+            .enumerate()
+            .map(|(idx, line)| BlameLine {
+                line: idx + 1,
+                content: line.trim_end_matches(['\n', '\r']).to_string(),
+                record_id: None,
+                timestamp: None,
+                label: None,
+            })
+            .collect();
+        let mut pending: Vec<Pending> = (0..blame.len())
+            .map(|idx| Pending { line: idx, at: idx })
+            .collect();
+
+        let mut newer = text;
+        let mut newer_sha = snapshot.sha;
+        for change in self.file_history(path)? {
+            if pending.is_empty() {
+                break;
+            }
+            if change.file.after_sha.as_ref() != Some(&newer_sha) {
+                bail!(
+                    "history of {path} is broken at record {}: it did not leave the content that followed",
+                    change.record_id
+                );
+            }
+            if change.file.before_sha == change.file.after_sha {
+                // mode-only change
+                continue;
+            }
+            let older = match &change.file.before_sha {
+                Some(sha) => String::from_utf8(self.read_blob(sha)?).ok(),
+                None => None,
+            };
+            let mut attribute = |entry: &Pending| {
+                let line = &mut blame[entry.line];
+                line.record_id = Some(change.record_id.clone());
+                line.timestamp = Some(change.ended_at);
+                line.label = change.label.clone();
+            };
+            let Some(older) = older else {
+                // created here, or nothing to trace through a binary version
+                pending.iter().for_each(&mut attribute);
+                pending.clear();
+                break;
+            };
+            let kept = unchanged_lines(&older, &newer);
+            pending.retain_mut(|entry| match kept.get(entry.at).copied().flatten() {
+                Some(at) => {
+                    entry.at = at;
+                    true
+                }
+                None => {
+                    attribute(entry);
+                    false
+                }
+            });
+            newer = older;
+            // a text version was read, so there was a before_sha
+            newer_sha = change.file.before_sha.unwrap_or_default();
+        }
+        Ok(blame)
+    }
+
+    /// Records on the chain that changed `path`, newest first, with that
+    /// file's entry.
+    fn file_history(&self, path: &str) -> Result<Vec<FileChange>> {
+        let chain = self.record_chain()?;
+        let conn = self.conn.lock().unwrap();
+        // the path as it appears inside files_json narrows the scan
+        let needle = serde_json::to_string(path)?;
+        let mut stmt = conn.prepare(
+            "SELECT record_id, label, files_json FROM records WHERE instr(files_json, ?1) > 0",
+        )?;
+        let rows = stmt.query_map([needle], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                (row.get::<_, Option<String>>(1)?, row.get::<_, String>(2)?),
+            ))
+        })?;
+        let mut touching: HashMap<String, (Option<String>, String)> =
+            rows.collect::<rusqlite::Result<_>>()?;
+        let mut history = Vec::new();
+        for (record_id, ts_end) in chain {
+            let Some((label, files_json)) = touching.remove(&record_id) else {
+                continue;
+            };
+            let files: Vec<FileRecord> = serde_json::from_str(&files_json)?;
+            let Some(file) = files.into_iter().find(|file| {
+                file.path == path && !matches!(file.op, FileOp::DirCreated | FileOp::DirRemoved)
+            }) else {
+                continue;
+            };
+            history.push(FileChange {
+                record_id,
+                ended_at: DateTime::<Utc>::from_timestamp_millis(ts_end).unwrap_or_else(Utc::now),
+                label,
+                file,
+            });
+        }
+        Ok(history)
+    }
+}
+
+/// For each line of `newer`, its index in `older` when the diff kept it.
+fn unchanged_lines(older: &str, newer: &str) -> Vec<Option<usize>> {
+    let diff = TextDiff::from_lines(older, newer);
+    let mut kept = vec![None; diff.new_slices().len()];
+    for op in diff.ops() {
+        if let DiffOp::Equal {
+            old_index,
+            new_index,
+            len,
+        } = *op
+        {
+            for offset in 0..len {
+                kept[new_index + offset] = Some(old_index + offset);
+            }
+        }
+    }
+    kept
+}
@@ -0,0 +1,210 @@
+//! Portable `.mdiff` bundles: a tar archive compressed with zstd holding a
+//! store subset in the usual on-disk layout plus a manifest.
+//!
+//! ```text
+//! manifest.json
+//! timeline.db                 only the exported records
+//! records/<id>/meta.json      and diff.patch.zst, semantic.json
+//! blobs/<xx>/<sha>.zst        every blob those records reference
+//! ```
+
+use std::collections::{BTreeSet, HashSet};
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::relocate::is_tree_path;
+use super::{StorageEngine, TimelineFilter};
+use crate::util;
+
+pub const BUNDLE_FORMAT: u32 = 1;
+const MANIFEST: &str = "manifest.json";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BundleManifest {
+    pub format: u32,
+    pub project_id: String,
+    /// Project root on the exporting machine, for reference only.
+    pub source_path: String,
+    pub tool_version: String,
+    pub exported_at: DateTime<Utc>,
+    pub records: Vec<String>,
+    pub blobs: usize,
+}
+
+/// Scratch directory removed on drop, whatever happened in between.
+struct Staging(PathBuf);
+
+impl Staging {
+    fn create(meta_dir: &Path, kind: &str) -> Result<Self> {
+        let dir = meta_dir.join(format!("{kind}.{}", std::process::id()));
+        if dir.exists() {
+            fs::remove_dir_all(&dir)?;
+        }
+        util::ensure_dir(&dir)?;
+        Ok(Self(dir))
+    }
+}
+
+impl Drop for Staging {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+impl StorageEngine {
+    /// Writes the records matching `filter`, with everything needed to read
+    /// and restore them, to `output`.
+    pub fn export_bundle(&self, filter: &TimelineFilter, output: &Path) -> Result<BundleManifest> {
+        let mut records: Vec<String> = self
+            .timeline(filter)?
+            .into_iter()
+            .map(|entry| entry.record_id)
+            .collect();
+        if records.is_empty() {
+            bail!("no records in the selected range");
+        }
+        records.reverse();
+
+        let staging = Staging::create(&self.paths.meta_dir, "export")?;
+        let staged = StorageEngine::open_dir(
+            &self.paths.root,
+            &self.project_root,
+            &self.project_id,
+            &staging.0,
+        )?;
+        let selected: HashSet<String> = records.iter().cloned().collect();
+        staged.copy_history(self, Some(&selected))?;
+        let blobs = walkdir::WalkDir::new(&staged.paths.blobs_dir)
+            .into_iter()
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_type().is_file())
+            .count();
+        // fold the WAL back in so the archive carries a single db file
+        staged
+            .conn
+            .lock()
+            .unwrap()
+            .pragma_update(None, "journal_mode", "DELETE")?;
+        drop(staged);
+
+        let manifest = BundleManifest {
+            format: BUNDLE_FORMAT,
+            project_id: self.project_id.clone(),
+            source_path: self.project_root.to_string_lossy().into_owned(),
+            tool_version: util::tool_version(),
+            exported_at: Utc::now(),
+            records,
+            blobs,
+        };
+        util::write_atomic(
+            &staging.0.join(MANIFEST),
+            &serde_json::to_vec_pretty(&manifest)?,
+        )?;
+
+        util::write_atomic_with(output, |file| {
+            let encoder = zstd::Encoder::new(file, 0)?;
+            let mut archive = tar::Builder::new(encoder);
+            archive.follow_symlinks(false);
+            for name in [MANIFEST, "timeline.db"] {
+                archive.append_path_with_name(staging.0.join(name), name)?;
+            }
+            for dir in ["records", "blobs"] {
+                archive.append_dir_all(dir, staging.0.join(dir))?;
+            }
+            archive.into_inner()?.finish()?;
+            Ok(())
+        })
+        .with_context(|| format!("failed to write bundle {}", output.display()))?;
+        Ok(manifest)
+    }
+
+    /// Verifies every blob and patch in `bundle` against its hash, then adds
+    /// the records this store does not have yet. Returns the manifest and
+    /// the number of records added.
+    pub fn import_bundle(&self, bundle: &Path) -> Result<(BundleManifest, usize)> {
+        let staging = Staging::create(&self.paths.meta_dir, "import")?;
+        let file =
+            File::open(bundle).with_context(|| format!("failed to open {}", bundle.display()))?;
+        let decoder = zstd::Decoder::new(file)?;
+        // unpack refuses entries that would land outside the staging dir
+        tar::Archive::new(decoder)
+            .unpack(&staging.0)
+            .with_context(|| format!("failed to unpack {}", bundle.display()))?;
+
+        let manifest_path = staging.0.join(MANIFEST);
+        let manifest: BundleManifest =
+            serde_json::from_slice(&fs::read(&manifest_path).context("bundle has no manifest")?)
+                .context("failed to parse bundle manifest")?;
+        if manifest.format != BUNDLE_FORMAT {
+            bail!(
+                "unsupported bundle format {} (this build reads {BUNDLE_FORMAT})",
+                manifest.format
+            );
+        }
+
+        let staged = StorageEngine::open_dir(
+            &self.paths.root,
+            &self.project_root,
+            &manifest.project_id,
+            &staging.0,
+        )?;
+        staged.verify_bundle(&manifest)?;
+        let listed: HashSet<String> = manifest.records.iter().cloned().collect();
+        let added = self.copy_history(&staged, Some(&listed))?;
+        Ok((manifest, added))
+    }
+
+    fn verify_bundle(&self, manifest: &BundleManifest) -> Result<()> {
+        let indexed: HashSet<(String, String)> = {
+            let conn = self.conn.lock().unwrap();
+            let mut stmt = conn.prepare("SELECT record_id, diff_hash FROM records")?;
+            let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+            rows.collect::<rusqlite::Result<_>>()?
+        };
+        let mut referenced = BTreeSet::new();
+        for record_id in &manifest.records {
+            if !is_plain_id(record_id) {
+                bail!("bundle lists an invalid record id {record_id:?}");
+            }
+            let meta = self
+                .read_record_meta(record_id)
+                .with_context(|| format!("bundle is missing record {record_id}"))?;
+            let diff_hash = util::hash_bytes(&self.read_patch(record_id)?);
+            if !indexed.contains(&(record_id.clone(), diff_hash)) {
+                bail!("patch of record {record_id} does not match its hash");
+            }
+            for file in &meta.files {
+                if !is_tree_path(&file.path) {
+                    bail!("record {record_id} names an invalid path {:?}", file.path);
+                }
+                referenced.extend(file.before_sha.iter().chain(&file.after_sha).cloned());
+            }
+        }
+        for sha in referenced {
+            if sha.len() != 64 || !is_plain_id(&sha) {
+                bail!("bundle references an invalid blob id {sha:?}");
+            }
+            let path = self.blob_path(&sha);
+            let mut data = Vec::new();
+            File::open(&path)
+                .map(zstd::Decoder::new)
+                .with_context(|| format!("bundle is missing blob {sha}"))??
+                .read_to_end(&mut data)
+                .with_context(|| format!("blob {sha} is corrupt"))?;
+            if util::hash_bytes(&data) != sha {
+                bail!("blob {sha} does not match its hash");
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Ids become path components, so only accept what this tool generates.
+fn is_plain_id(id: &str) -> bool {
+    !id.is_empty() && id.chars().all(|c| c.is_ascii_alphanumeric())
+}
@@ -0,0 +1,215 @@
+//! Rebuilding the project tree as it was at an earlier record.
+//!
+//! Only `latest_snapshots` describes a full tree, so older states are
+//! reached by undoing newer records one by one, using the `before_sha` each
+//! of them kept. Records are followed through `prev_record_id` from the
+//! head of this checkout's own chain, and imported records are never part
+//! of it, so history from another checkout is never undone against this
+//! tree.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+use anyhow::{anyhow, bail, Result};
+use chrono::{DateTime, Utc};
+use rusqlite::OptionalExtension;
+
+use super::StorageEngine;
+use crate::models::{FileOp, RecordMeta};
+use crate::pipeline::{build_file_artifact, DiffOptions, FileArtifact, FileInput};
+
+/// Content of one file at some point in history.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TreeFile {
+    pub sha: String,
+    pub mode: Option<u32>,
+}
+
+/// Every tracked file by relative path.
+pub type Tree = BTreeMap<String, TreeFile>;
+
+impl StorageEngine {
+    /// The tree as the watcher sees it now.
+    pub fn current_tree(&self) -> Result<Tree> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare("SELECT path, sha, mode FROM latest_snapshots")?;
+        let rows = stmt.query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                TreeFile {
+                    sha: row.get(1)?,
+                    mode: row.get(2)?,
+                },
+            ))
+        })?;
+        Ok(rows.collect::<rusqlite::Result<Tree>>()?)
+    }
+
+    /// The tree right after `record_id` was recorded.
+    pub fn tree_at(&self, record_id: &str) -> Result<Tree> {
+        self.rewind_to(record_id, false)
+    }
+
+    /// The tree just before `record_id`, i.e. what its changes apply to.
+    pub fn tree_before(&self, record_id: &str) -> Result<Tree> {
+        self.rewind_to(record_id, true)
+    }
+
+    /// The tree as it stood at `at`: every record that ended later is undone.
+    pub fn tree_at_time(&self, at: DateTime<Utc>) -> Result<Tree> {
+        let mut tree = self.current_tree()?;
+        for (id, ts_end) in self.record_chain()? {
+            if ts_end <= at.timestamp_millis() {
+                break;
+            }
+            undo_record(&mut tree, &self.read_record_meta(&id)?)?;
+        }
+        Ok(tree)
+    }
+
+    fn rewind_to(&self, record_id: &str, undo_target: bool) -> Result<Tree> {
+        let chain = self.record_chain()?;
+        if !chain.iter().any(|(id, _)| id == record_id) {
+            if self.has_record(record_id)? {
+                bail!("record {record_id} is not in the history of this tree");
+            }
+            return Err(anyhow!("record {record_id} not found"));
+        }
+        let mut tree = self.current_tree()?;
+        for (id, _) in chain {
+            let target = id == record_id;
+            if target && !undo_target {
+                break;
+            }
+            undo_record(&mut tree, &self.read_record_meta(&id)?)?;
+            if target {
+                break;
+            }
+        }
+        Ok(tree)
+    }
+
+    /// Changes between the trees right after records `from` and `to`, one
+    /// artifact per file that differs, by path.
+    pub fn diff_records(
+        &self,
+        from: &str,
+        to: &str,
+        options: &DiffOptions,
+    ) -> Result<Vec<FileArtifact>> {
+        let before = self.tree_at(from)?;
+        let after = self.tree_at(to)?;
+        let paths: BTreeSet<&String> = before.keys().chain(after.keys()).collect();
+        let mut artifacts = Vec::new();
+        for path in paths {
+            let (old, new) = (before.get(path), after.get(path));
+            if old == new {
+                continue;
+            }
+            let input = FileInput {
+                path: path.clone(),
+                before: old.map(|file| self.read_blob(&file.sha)).transpose()?,
+                after: new.map(|file| self.read_blob(&file.sha)).transpose()?,
+                before_mode: old.and_then(|file| file.mode),
+                after_mode: new.and_then(|file| file.mode),
+            };
+            artifacts.extend(build_file_artifact(input, options)?);
+        }
+        Ok(artifacts)
+    }
+
+    /// Record ids and end times from the chain head back through
+    /// `prev_record_id`, stopping at the first record that is missing or
+    /// was imported.
+    pub(super) fn record_chain(&self) -> Result<Vec<(String, i64)>> {
+        let links: HashMap<String, (Option<String>, i64)> = {
+            let conn = self.conn.lock().unwrap();
+            let mut stmt = conn.prepare(
+                "SELECT record_id, prev_record_id, ts_end FROM records WHERE imported = 0",
+            )?;
+            let rows = stmt.query_map([], |row| Ok((row.get(0)?, (row.get(1)?, row.get(2)?))))?;
+            rows.collect::<rusqlite::Result<_>>()?
+        };
+        let mut chain = Vec::new();
+        let mut seen = HashSet::new();
+        let mut next = self.latest_record_id()?;
+        while let Some(id) = next {
+            let Some((prev, ts_end)) = links.get(&id) else {
+                break;
+            };
+            if !seen.insert(id.clone()) {
+                break;
+            }
+            next = prev.clone();
+            chain.push((id, *ts_end));
+        }
+        Ok(chain)
+    }
+
+    fn has_record(&self, record_id: &str) -> Result<bool> {
+        let conn = self.conn.lock().unwrap();
+        Ok(conn
+            .query_row(
+                "SELECT 1 FROM records WHERE record_id = ?1",
+                [record_id],
+                |_| Ok(()),
+            )
+            .optional()?
+            .is_some())
+    }
+}
+
+/// Applies a record's changes to `tree`.
+pub fn apply_record(tree: &mut Tree, meta: &RecordMeta) {
+    for file in &meta.files {
+        if matches!(file.op, FileOp::DirCreated | FileOp::DirRemoved) {
+            // files below a directory are recorded on their own
+            continue;
+        }
+        match &file.after_sha {
+            Some(sha) => {
+                tree.insert(
+                    file.path.clone(),
+                    TreeFile {
+                        sha: sha.clone(),
+                        mode: file.after_mode,
+                    },
+                );
+            }
+            None => {
+                tree.remove(&file.path);
+            }
+        }
+    }
+}
+
+/// Reverts a record's changes in `tree`, which must hold what the record
+/// left behind.
+fn undo_record(tree: &mut Tree, meta: &RecordMeta) -> Result<()> {
+    for file in meta.files.iter().rev() {
+        if matches!(file.op, FileOp::DirCreated | FileOp::DirRemoved) {
+            continue;
+        }
+        if tree.get(&file.path).map(|entry| &entry.sha) != file.after_sha.as_ref() {
+            bail!(
+                "history is broken at record {}: {} does not match what it recorded",
+                meta.record_id,
+                file.path
+            );
+        }
+        match &file.before_sha {
+            Some(sha) => {
+                tree.insert(
+                    file.path.clone(),
+                    TreeFile {
+                        sha: sha.clone(),
+                        mode: file.before_mode,
+                    },
+                );
+            }
+            None => {
+                tree.remove(&file.path);
+            }
+        }
+    }
+    Ok(())
+}
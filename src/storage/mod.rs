@@ -1,85 +1,155 @@
-use std::fs::File;
+use std::collections::HashMap;
+use std::fs::{self, File};
 use std::io::{Read, Write};
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Mutex;
 
 use anyhow::{Context, Result};
 use chrono::{DateTime, Utc};
+use rusqlite::types::Value as SqlValue;
 use rusqlite::{params, Connection, OptionalExtension};
-use serde::{Deserialize, Serialize};
 
-use crate::models::{FileOp, RecordMeta, RecordStats, TimelineEntry};
+use crate::models::{
+    FileOp, RecordMeta, RecordStats, SemanticFileDiff, SnapshotInfo, SnapshotStamp, TimelineEntry,
+};
 use crate::pipeline::FileArtifact;
 use crate::util;
 
+mod annotations;
+mod blame;
+mod bundle;
+mod history;
+mod registry;
+mod relocate;
+mod search;
+pub use annotations::{Bookmark, Note, Tag, LATEST_REF};
+pub use blame::BlameLine;
+pub use bundle::{BundleManifest, BUNDLE_FORMAT};
+pub use history::{apply_record, Tree, TreeFile};
+pub use registry::{
+    find_project_entry, read_registry_global, set_project_enabled, set_project_stopped,
+    ProjectEntry, Registry, WatcherStatus, REGISTRY_DB,
+};
+pub use relocate::{relocate, RelocateOptions, Relocation, TreeCheck};
+pub use search::{LineSide, SearchHit, SearchQuery};
+
 const META_VERSION: &str = "1";
+/// Marks a store whose blobs were checked once against their hashes.
+const BLOBS_VERIFIED: &str = "blobs-verified";
+/// `user_version` of a timeline db whose directories have been primed.
+const DIRECTORIES_PRIMED: i64 = 1;
 
 pub struct StorageEngine {
     project_id: String,
     project_root: PathBuf,
     paths: StoragePaths,
     conn: Mutex<Connection>,
+    registry: Mutex<Registry>,
+    /// Bytes written since the registry's size was last brought up to date,
+    /// so new records never need a walk of the whole store.
+    unsized_bytes: AtomicU64,
+    /// Whether this SQLite build gave us the full-text patch index.
+    search_index: bool,
 }
 
 #[derive(Clone)]
 pub struct StoragePaths {
+    /// The meowdiff root holding the registry and every project's store.
+    pub root: PathBuf,
     pub project_dir: PathBuf,
     pub records_dir: PathBuf,
     pub blobs_dir: PathBuf,
     pub meta_dir: PathBuf,
     pub timeline_db: PathBuf,
-    pub registry_file: PathBuf,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct ProjectEntry {
-    pub project_id: String,
-    pub path: String,
-    pub last_seen: i64,
+    pub registry_db: PathBuf,
 }
 
-#[derive(Debug, Default, Clone, Serialize, Deserialize)]
-struct RegistryFile {
-    projects: Vec<ProjectEntry>,
+#[derive(Debug, Clone, Default)]
+pub struct TimelineFilter {
+    pub limit: Option<usize>,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    pub branch: Option<String>,
 }
 
 impl StorageEngine {
     pub fn open(project_root: &Path) -> Result<Self> {
+        Self::open_in(&util::meowdiff_root()?, project_root)
+    }
+
+    /// Opens the store for `project_root` kept under `root` rather than
+    /// `~/.meowdiff`.
+    pub fn open_in(root: &Path, project_root: &Path) -> Result<Self> {
+        let project_id = util::compute_project_id(project_root)?;
+        let engine = Self::open_dir(root, project_root, &project_id, &root.join(&project_id))?;
+        engine.verify_legacy_blobs()?;
+        engine.update_registry()?;
+        Ok(engine)
+    }
+
+    /// Opens the store kept under `project_id` for a tree at `project_root`,
+    /// whether or not the id matches the path. The registry is left alone.
+    pub fn open_as(project_root: &Path, project_id: &str) -> Result<Self> {
+        let root = util::meowdiff_root()?;
+        let engine = Self::open_dir(&root, project_root, project_id, &root.join(project_id))?;
+        engine.verify_legacy_blobs()?;
+        Ok(engine)
+    }
+
+    /// Opens a store laid out in `project_dir`, which need not live under
+    /// `root` (bundles are staged elsewhere).
+    fn open_dir(
+        root: &Path,
+        project_root: &Path,
+        project_id: &str,
+        project_dir: &Path,
+    ) -> Result<Self> {
         let project_root = project_root
             .canonicalize()
             .with_context(|| format!("failed to canonicalize {}", project_root.display()))?;
-        let project_id = util::compute_project_id(&project_root)?;
-        let meowdiff_root = util::meowdiff_root()?;
-        let project_dir = meowdiff_root.join(&project_id);
+        let project_id = project_id.to_string();
+        let project_dir = project_dir.to_path_buf();
         let records_dir = project_dir.join("records");
         let blobs_dir = project_dir.join("blobs");
         let meta_dir = project_dir.join("meta");
         let timeline_db = project_dir.join("timeline.db");
-        let registry_file = meowdiff_root.join("registry.json");
+        let registry_db = root.join(REGISTRY_DB);
         util::ensure_dir(&project_dir)?;
         util::ensure_dir(&records_dir)?;
         util::ensure_dir(&blobs_dir)?;
         util::ensure_dir(&meta_dir)?;
+        let swept = util::sweep_temp_files(&project_dir)?;
+        if swept > 0 {
+            tracing::info!(
+                count = swept,
+                "removed temp files left by an interrupted write"
+            );
+        }
 
         let mut conn = Connection::open(&timeline_db)
             .with_context(|| format!("failed to open {}", timeline_db.display()))?;
         init_db(&mut conn)?;
+        let search_index = search::init_search_index(&conn);
 
         let engine = Self {
             project_id,
             project_root,
             paths: StoragePaths {
+                root: root.to_path_buf(),
                 project_dir,
                 records_dir,
                 blobs_dir,
                 meta_dir,
                 timeline_db,
-                registry_file,
+                registry_db,
             },
             conn: Mutex::new(conn),
+            registry: Mutex::new(Registry::open_in(root)?),
+            unsized_bytes: AtomicU64::new(0),
+            search_index,
         };
         engine.persist_meta_version()?;
-        engine.update_registry()?;
         Ok(engine)
     }
 
@@ -95,18 +165,72 @@ impl StorageEngine {
         &self.paths
     }
 
+    /// Head of this checkout's own record chain. Records imported from
+    /// another store never become the head, however late they ended.
     pub fn latest_record_id(&self) -> Result<Option<String>> {
         let conn = self.conn.lock().unwrap();
-        let mut stmt =
-            conn.prepare("SELECT record_id FROM records ORDER BY ts_end DESC LIMIT 1")?;
+        let mut stmt = conn.prepare(
+            "SELECT record_id FROM records WHERE imported = 0 ORDER BY ts_end DESC, rowid DESC LIMIT 1",
+        )?;
         let result = stmt
             .query_row([], |row| row.get::<_, String>(0))
             .optional()?;
         Ok(result)
     }
 
-    pub fn register_touch(&self) -> Result<()> {
-        self.update_registry()
+    /// Notes a freshly committed record in the registry and adds what it
+    /// wrote to the stored size.
+    pub fn register_record(&self, meta: &RecordMeta) -> Result<()> {
+        let registry = self.registry.lock().unwrap();
+        registry.set_last_record(&self.project_id, meta.ended_at.timestamp_millis())?;
+        let written = self.unsized_bytes.swap(0, Ordering::Relaxed);
+        registry.add_storage_bytes(&self.project_id, written)?;
+        Ok(())
+    }
+
+    /// Reports this project's watcher as started or stopped.
+    pub fn register_watcher(&self, state: WatcherStatus) -> Result<()> {
+        let pid = (state != WatcherStatus::Stopped).then(|| std::process::id() as i32);
+        self.registry
+            .lock()
+            .unwrap()
+            .set_watcher(&self.project_id, state, pid)?;
+        self.refresh_storage_size()
+    }
+
+    /// Measures the whole store; too slow for the commit path.
+    pub fn refresh_storage_size(&self) -> Result<()> {
+        self.unsized_bytes.store(0, Ordering::Relaxed);
+        let bytes = self.storage_bytes();
+        self.registry
+            .lock()
+            .unwrap()
+            .set_storage_bytes(&self.project_id, bytes)?;
+        Ok(())
+    }
+
+    fn note_written(&self, path: &Path) {
+        if let Ok(meta) = std::fs::metadata(path) {
+            self.unsized_bytes.fetch_add(meta.len(), Ordering::Relaxed);
+        }
+    }
+
+    /// Total size of everything stored for this project.
+    pub fn storage_bytes(&self) -> u64 {
+        walkdir::WalkDir::new(&self.paths.project_dir)
+            .into_iter()
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_type().is_file())
+            .filter_map(|entry| entry.metadata().ok())
+            .map(|meta| meta.len())
+            .sum()
+    }
+
+    /// Whether any record is stored, imported ones included.
+    pub fn has_records(&self) -> Result<bool> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare("SELECT 1 FROM records LIMIT 1")?;
+        Ok(stmt.exists([])?)
     }
 
     pub fn has_snapshots(&self) -> Result<bool> {
@@ -115,18 +239,102 @@ impl StorageEngine {
         Ok(stmt.exists([])?)
     }
 
-    pub fn seed_snapshot(&self, path: &str, data: &[u8]) -> Result<()> {
+    pub fn seed_snapshot(&self, path: &str, data: &[u8], mode: Option<u32>) -> Result<()> {
         let sha = util::hash_bytes(data);
         self.ensure_blob(&sha, Some(data))?;
         let conn = self.conn.lock().unwrap();
         conn.execute(
-            "INSERT INTO latest_snapshots (path, sha, record_id, updated_at) VALUES (?1, ?2, ?3, ?4) \
-             ON CONFLICT(path) DO UPDATE SET sha=excluded.sha, record_id=excluded.record_id, updated_at=excluded.updated_at",
-            params![path, sha, "baseline", Utc::now().timestamp_millis()],
+            "INSERT INTO latest_snapshots (path, sha, record_id, updated_at, mode, size) VALUES (?1, ?2, ?3, ?4, ?5, ?6) \
+             ON CONFLICT(path) DO UPDATE SET sha=excluded.sha, record_id=excluded.record_id, updated_at=excluded.updated_at, mode=excluded.mode, size=excluded.size",
+            params![path, sha, "baseline", Utc::now().timestamp_millis(), mode, data.len() as i64],
         )?;
         Ok(())
     }
 
+    /// Whether directories on disk have been seeded, which stores created
+    /// before directory tracking never did. A project without any
+    /// subdirectory has no rows to tell, hence the marker.
+    pub fn directories_primed(&self) -> Result<bool> {
+        let conn = self.conn.lock().unwrap();
+        let version: i64 = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+        Ok(version >= DIRECTORIES_PRIMED)
+    }
+
+    pub fn mark_directories_primed(&self) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.pragma_update(None, "user_version", DIRECTORIES_PRIMED)?;
+        Ok(())
+    }
+
+    pub fn has_directory(&self, path: &str) -> Result<bool> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare("SELECT 1 FROM directories WHERE path = ?1")?;
+        Ok(stmt.exists([path])?)
+    }
+
+    pub fn seed_directory(&self, path: &str) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT OR IGNORE INTO directories (path, record_id, updated_at) VALUES (?1, ?2, ?3)",
+            params![path, "baseline", Utc::now().timestamp_millis()],
+        )?;
+        Ok(())
+    }
+
+    /// Tracked directories strictly below `dir`, deepest first.
+    pub fn directories_under(&self, dir: &str) -> Result<Vec<String>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT path FROM directories WHERE substr(path, 1, length(?1)) = ?1 ORDER BY length(path) DESC, path",
+        )?;
+        let rows = stmt.query_map([format!("{dir}/")], |row| row.get::<_, String>(0))?;
+        Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+    }
+
+    /// Every path with a latest snapshot, for whole-tree rescans.
+    pub fn snapshot_paths(&self) -> Result<Vec<String>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare("SELECT path FROM latest_snapshots ORDER BY path")?;
+        let rows = stmt.query_map([], |row| row.get::<_, String>(0))?;
+        Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+    }
+
+    /// Size, mode and time of every latest snapshot, keyed by path.
+    pub fn snapshot_stamps(&self) -> Result<HashMap<String, SnapshotStamp>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare("SELECT path, size, mode, updated_at FROM latest_snapshots")?;
+        let rows = stmt.query_map([], |row| {
+            let size: Option<i64> = row.get(1)?;
+            Ok((
+                row.get::<_, String>(0)?,
+                SnapshotStamp {
+                    size: size.map(|size| size as u64),
+                    mode: row.get(2)?,
+                    updated_at: DateTime::<Utc>::from_timestamp_millis(row.get(3)?)
+                        .unwrap_or(DateTime::<Utc>::MIN_UTC),
+                },
+            ))
+        })?;
+        Ok(rows.collect::<rusqlite::Result<HashMap<_, _>>>()?)
+    }
+
+    pub fn directory_paths(&self) -> Result<Vec<String>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare("SELECT path FROM directories ORDER BY path")?;
+        let rows = stmt.query_map([], |row| row.get::<_, String>(0))?;
+        Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+    }
+
+    /// Snapshotted file paths strictly below `dir`.
+    pub fn snapshot_paths_under(&self, dir: &str) -> Result<Vec<String>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT path FROM latest_snapshots WHERE substr(path, 1, length(?1)) = ?1 ORDER BY path",
+        )?;
+        let rows = stmt.query_map([format!("{dir}/")], |row| row.get::<_, String>(0))?;
+        Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+    }
+
     pub fn commit_record(
         &self,
         meta: &RecordMeta,
@@ -138,17 +346,21 @@ impl StorageEngine {
         let meta_path = record_dir.join("meta.json");
         let patch_path = record_dir.join("diff.patch.zst");
 
-        // write meta json
-        {
-            let mut file = File::create(&meta_path)
-                .with_context(|| format!("failed to create {}", meta_path.display()))?;
-            serde_json::to_writer_pretty(&mut file, meta)?
-        }
-
-        {
-            let mut file = File::create(&patch_path)
-                .with_context(|| format!("failed to create {}", patch_path.display()))?;
-            file.write_all(patch_bytes)?;
+        // every file must be durable before the transaction below makes the
+        // record visible; a crash in between only leaves an unreferenced dir
+        util::write_atomic(&meta_path, &serde_json::to_vec_pretty(meta)?)?;
+        util::write_atomic(&patch_path, patch_bytes)?;
+        self.note_written(&meta_path);
+        self.note_written(&patch_path);
+
+        let semantic: Vec<&SemanticFileDiff> = artifacts
+            .iter()
+            .filter_map(|artifact| artifact.semantic.as_ref())
+            .collect();
+        if !semantic.is_empty() {
+            let semantic_path = record_dir.join("semantic.json");
+            util::write_atomic(&semantic_path, &serde_json::to_vec_pretty(&semantic)?)?;
+            self.note_written(&semantic_path);
         }
 
         // ensure blobs
@@ -165,6 +377,14 @@ impl StorageEngine {
             }
         }
 
+        let sizes: HashMap<&str, usize> = artifacts
+            .iter()
+            .filter_map(|artifact| {
+                let blob = artifact.after_blob.as_ref()?;
+                Some((artifact.record.path.as_str(), blob.len()))
+            })
+            .collect();
+
         let files_json = serde_json::to_string(&meta.files)?;
         let stats_json = serde_json::to_string(&meta.stats)?;
         let diff_hash = util::hash_bytes(patch_bytes);
@@ -172,7 +392,7 @@ impl StorageEngine {
         let mut conn = self.conn.lock().unwrap();
         let tx = conn.transaction()?;
         tx.execute(
-            "INSERT INTO records (record_id, project_id, ts_start, ts_end, files_json, stats_json, prev_record_id, diff_hash, duration_ms) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            "INSERT INTO records (record_id, project_id, ts_start, ts_end, files_json, stats_json, prev_record_id, diff_hash, duration_ms, git_branch, git_head, label) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
             params![
                 meta.record_id,
                 meta.project_id,
@@ -182,7 +402,10 @@ impl StorageEngine {
                 stats_json,
                 meta.prev_record_id,
                 diff_hash,
-                (meta.ended_at - meta.started_at).num_milliseconds()
+                (meta.ended_at - meta.started_at).num_milliseconds(),
+                meta.git.as_ref().and_then(|git| git.branch.clone()),
+                meta.git.as_ref().and_then(|git| git.head.clone()),
+                meta.label
             ],
         )?;
 
@@ -191,12 +414,14 @@ impl StorageEngine {
                 FileOp::Added | FileOp::Modified => {
                     if let Some(ref sha) = file.after_sha {
                         tx.execute(
-                            "INSERT INTO latest_snapshots (path, sha, record_id, updated_at) VALUES (?1, ?2, ?3, ?4) ON CONFLICT(path) DO UPDATE SET sha=excluded.sha, record_id=excluded.record_id, updated_at=excluded.updated_at",
+                            "INSERT INTO latest_snapshots (path, sha, record_id, updated_at, mode, size) VALUES (?1, ?2, ?3, ?4, ?5, ?6) ON CONFLICT(path) DO UPDATE SET sha=excluded.sha, record_id=excluded.record_id, updated_at=excluded.updated_at, mode=excluded.mode, size=excluded.size",
                             params![
                                 file.path,
                                 sha,
                                 meta.record_id,
-                                meta.ended_at.timestamp_millis()
+                                meta.ended_at.timestamp_millis(),
+                                file.after_mode,
+                                sizes.get(file.path.as_str()).map(|&size| size as i64)
                             ],
                         )?;
                     }
@@ -207,8 +432,28 @@ impl StorageEngine {
                         params![file.path],
                     )?;
                 }
+                FileOp::DirCreated => {
+                    tx.execute(
+                        "INSERT INTO directories (path, record_id, updated_at) VALUES (?1, ?2, ?3) ON CONFLICT(path) DO UPDATE SET record_id=excluded.record_id, updated_at=excluded.updated_at",
+                        params![file.path, meta.record_id, meta.ended_at.timestamp_millis()],
+                    )?;
+                }
+                FileOp::DirRemoved => {
+                    let prefix = format!("{}/", file.path);
+                    tx.execute(
+                        "DELETE FROM directories WHERE path = ?1 OR substr(path, 1, length(?2)) = ?2",
+                        params![file.path, prefix],
+                    )?;
+                    tx.execute(
+                        "DELETE FROM latest_snapshots WHERE substr(path, 1, length(?1)) = ?1",
+                        params![prefix],
+                    )?;
+                }
             }
         }
+        if self.search_index {
+            search::index_record(&tx, &meta.record_id, artifacts)?;
+        }
         tx.commit()?;
         Ok(())
     }
@@ -222,6 +467,21 @@ impl StorageEngine {
         Ok(meta)
     }
 
+    /// Sets or clears the label on an existing record, in both the index and
+    /// its `meta.json`.
+    pub fn set_label(&self, record_id: &str, label: Option<&str>) -> Result<()> {
+        let mut meta = self.read_record_meta(record_id)?;
+        meta.label = label.map(str::to_string);
+        let meta_path = self.paths.records_dir.join(record_id).join("meta.json");
+        util::write_atomic(&meta_path, &serde_json::to_vec_pretty(&meta)?)?;
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "UPDATE records SET label = ?1 WHERE record_id = ?2",
+            params![label, record_id],
+        )?;
+        Ok(())
+    }
+
     pub fn read_patch(&self, record_id: &str) -> Result<Vec<u8>> {
         let path = self
             .paths
@@ -235,31 +495,43 @@ impl StorageEngine {
         Ok(buf)
     }
 
-    pub fn timeline(
-        &self,
-        limit: Option<usize>,
-        from: Option<DateTime<Utc>>,
-        to: Option<DateTime<Utc>>,
-    ) -> Result<Vec<TimelineEntry>> {
+    pub fn read_semantic(&self, record_id: &str) -> Result<Vec<SemanticFileDiff>> {
+        let path = self.paths.records_dir.join(record_id).join("semantic.json");
+        if !path.exists() {
+            return Ok(Vec::new());
+        }
+        let file =
+            File::open(&path).with_context(|| format!("failed to open {}", path.display()))?;
+        let diffs = serde_json::from_reader(file)
+            .with_context(|| format!("failed to parse semantic diff for {record_id}"))?;
+        Ok(diffs)
+    }
+
+    pub fn timeline(&self, filter: &TimelineFilter) -> Result<Vec<TimelineEntry>> {
         let conn = self.conn.lock().unwrap();
-        let mut sql =
-            String::from("SELECT record_id, ts_end, stats_json, duration_ms FROM records");
+        let mut sql = String::from(
+            "SELECT record_id, ts_end, stats_json, duration_ms, git_branch, git_head, label FROM records",
+        );
         let mut clauses: Vec<String> = Vec::new();
-        let mut args: Vec<i64> = Vec::new();
-        if let Some(from_ts) = from {
+        let mut args: Vec<SqlValue> = Vec::new();
+        if let Some(from_ts) = filter.from {
             clauses.push("ts_end >= ?".into());
-            args.push(from_ts.timestamp_millis());
+            args.push(SqlValue::Integer(from_ts.timestamp_millis()));
         }
-        if let Some(to_ts) = to {
+        if let Some(to_ts) = filter.to {
             clauses.push("ts_end <= ?".into());
-            args.push(to_ts.timestamp_millis());
+            args.push(SqlValue::Integer(to_ts.timestamp_millis()));
+        }
+        if let Some(ref branch) = filter.branch {
+            clauses.push("git_branch = ?".into());
+            args.push(SqlValue::Text(branch.clone()));
         }
         if !clauses.is_empty() {
             sql.push_str(" WHERE ");
             sql.push_str(&clauses.join(" AND "));
         }
         sql.push_str(" ORDER BY ts_end DESC");
-        if let Some(limit) = limit {
+        if let Some(limit) = filter.limit {
             sql.push_str(&format!(" LIMIT {limit}"));
         }
         let mut stmt = conn.prepare(&sql)?;
@@ -280,16 +552,30 @@ impl StorageEngine {
                 lines_removed: stats.lines_removed,
                 duration_ms,
                 notes: None,
+                tags: Vec::new(),
+                bookmarked: false,
+                git_branch: row.get(4)?,
+                git_head: row.get(5)?,
+                label: row.get(6)?,
             });
         }
+        drop(rows);
+        annotations::annotate_entries(&conn, &mut entries)?;
         Ok(entries)
     }
 
-    pub fn fetch_snapshot(&self, path: &str) -> Result<Option<String>> {
+    pub fn fetch_snapshot(&self, path: &str) -> Result<Option<SnapshotInfo>> {
         let conn = self.conn.lock().unwrap();
-        let mut stmt = conn.prepare("SELECT sha FROM latest_snapshots WHERE path = ?1")?;
+        let mut stmt =
+            conn.prepare("SELECT sha, record_id, mode FROM latest_snapshots WHERE path = ?1")?;
         let result = stmt
-            .query_row([path], |row| row.get::<_, String>(0))
+            .query_row([path], |row| {
+                Ok(SnapshotInfo {
+                    sha: row.get(0)?,
+                    record_id: row.get(1)?,
+                    mode: row.get(2)?,
+                })
+            })
             .optional()?;
         Ok(result)
     }
@@ -304,48 +590,34 @@ impl StorageEngine {
         Ok(buf)
     }
 
+    /// Stores `content` as blob `sha` unless it exists already.
     pub fn ensure_blob(&self, sha: &str, content: Option<&[u8]>) -> Result<()> {
         let path = self.blob_path(sha);
         if path.exists() {
             return Ok(());
         }
         let data = content.context("blob content missing while attempting to persist new blob")?;
-        if let Some(parent) = path.parent() {
-            util::ensure_dir(parent)?;
-        }
-        let mut file = File::create(&path)
-            .with_context(|| format!("failed to create blob {}", path.display()))?;
-        let mut encoder = zstd::Encoder::new(&mut file, 0)?;
-        encoder.write_all(data)?;
-        encoder.finish()?;
+        // blobs are content-addressed, so existence must imply completeness
+        util::write_atomic_with(&path, |file| {
+            let mut encoder = zstd::Encoder::new(file, 0)?;
+            encoder.write_all(data)?;
+            encoder.finish()?;
+            Ok(())
+        })
+        .with_context(|| format!("failed to write blob {sha}"))?;
+        self.note_written(&path);
         Ok(())
     }
 
     pub fn list_projects(&self) -> Result<Vec<ProjectEntry>> {
-        let registry = self.read_registry()?;
-        Ok(registry.projects)
-    }
-
-    fn read_registry(&self) -> Result<RegistryFile> {
-        load_registry_file(&self.paths.registry_file)
+        self.registry.lock().unwrap().list()
     }
 
     pub fn update_registry(&self) -> Result<()> {
-        let mut registry = self.read_registry()?;
-        let entry = ProjectEntry {
-            project_id: self.project_id.clone(),
-            path: self.project_root.to_string_lossy().to_string(),
-            last_seen: Utc::now().timestamp(),
-        };
-        registry
-            .projects
-            .retain(|p| p.project_id != self.project_id);
-        registry.projects.push(entry);
-        let path = &self.paths.registry_file;
-        let mut file =
-            File::create(path).with_context(|| format!("failed to write {}", path.display()))?;
-        serde_json::to_writer_pretty(&mut file, &registry)?;
-        Ok(())
+        self.registry
+            .lock()
+            .unwrap()
+            .touch(&self.project_id, &self.project_root)
     }
 
     fn blob_path(&self, sha: &str) -> PathBuf {
@@ -353,14 +625,49 @@ impl StorageEngine {
         self.paths.blobs_dir.join(prefix).join(format!("{sha}.zst"))
     }
 
+    /// Removes blobs that do not decode to their sha, once per store.
+    /// Stores written before blob writes were atomic may hold truncated
+    /// ones; since then a blob only gets its name once complete. A removed
+    /// blob is written again by the next `ensure_blob` with its content.
+    fn verify_legacy_blobs(&self) -> Result<()> {
+        let marker = self.paths.meta_dir.join(BLOBS_VERIFIED);
+        if marker.exists() {
+            return Ok(());
+        }
+        let mut removed = 0;
+        for entry in walkdir::WalkDir::new(&self.paths.blobs_dir)
+            .into_iter()
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_type().is_file())
+        {
+            let Some(sha) = entry
+                .file_name()
+                .to_str()
+                .and_then(|name| name.strip_suffix(".zst"))
+            else {
+                continue;
+            };
+            if !blob_matches(entry.path(), sha) {
+                fs::remove_file(entry.path())
+                    .with_context(|| format!("failed to remove {}", entry.path().display()))?;
+                removed += 1;
+            }
+        }
+        if removed > 0 {
+            tracing::warn!(
+                count = removed,
+                "removed blobs that did not match their hash"
+            );
+        }
+        util::write_atomic(&marker, b"")
+    }
+
     fn persist_meta_version(&self) -> Result<()> {
         let version_path = self.paths.meta_dir.join("version");
         if version_path.exists() {
             return Ok(());
         }
-        let mut file = File::create(&version_path)?;
-        file.write_all(META_VERSION.as_bytes())?;
-        Ok(())
+        util::write_atomic(&version_path, META_VERSION.as_bytes())
     }
 }
 
@@ -385,34 +692,64 @@ fn init_db(conn: &mut Connection) -> Result<()> {
             path TEXT PRIMARY KEY,
             sha TEXT NOT NULL,
             record_id TEXT NOT NULL,
+            updated_at INTEGER NOT NULL,
+            mode INTEGER
+        );
+
+        CREATE TABLE IF NOT EXISTS directories (
+            path TEXT PRIMARY KEY,
+            record_id TEXT NOT NULL,
             updated_at INTEGER NOT NULL
         );
+
+        CREATE TABLE IF NOT EXISTS notes (
+            id INTEGER PRIMARY KEY,
+            record_id TEXT NOT NULL,
+            text TEXT NOT NULL,
+            created_at INTEGER NOT NULL
+        );
+        CREATE INDEX IF NOT EXISTS idx_notes_record ON notes(record_id);
+
+        CREATE TABLE IF NOT EXISTS tags (
+            name TEXT PRIMARY KEY,
+            record_id TEXT NOT NULL,
+            created_at INTEGER NOT NULL
+        );
+
+        CREATE TABLE IF NOT EXISTS bookmarks (
+            id INTEGER PRIMARY KEY,
+            record_id TEXT NOT NULL,
+            message TEXT,
+            created_at INTEGER NOT NULL
+        );
         "#,
     )?;
+    ensure_column(conn, "latest_snapshots", "mode", "INTEGER")?;
+    ensure_column(conn, "latest_snapshots", "size", "INTEGER")?;
+    ensure_column(conn, "records", "git_branch", "TEXT")?;
+    ensure_column(conn, "records", "git_head", "TEXT")?;
+    ensure_column(conn, "records", "label", "TEXT")?;
+    // copied in from another store, so not part of this checkout's chain
+    ensure_column(conn, "records", "imported", "INTEGER NOT NULL DEFAULT 0")?;
     Ok(())
 }
 
-fn load_registry_file(path: &Path) -> Result<RegistryFile> {
-    if path.exists() {
-        let file = File::open(path)?;
-        let registry: RegistryFile =
-            serde_json::from_reader(file).context("failed to parse registry.json")?;
-        Ok(registry)
-    } else {
-        Ok(RegistryFile::default())
-    }
+fn blob_matches(path: &Path, sha: &str) -> bool {
+    let mut hasher = blake3::Hasher::new();
+    let decoded = File::open(path)
+        .map_err(anyhow::Error::from)
+        .and_then(|file| Ok(zstd::stream::copy_decode(file, &mut hasher)?));
+    decoded.is_ok() && hex::encode(hasher.finalize().as_bytes()) == sha
 }
 
-pub fn read_registry_global() -> Result<Vec<ProjectEntry>> {
-    let root = util::meowdiff_root()?;
-    let path = root.join("registry.json");
-    let registry = load_registry_file(&path)?;
-    Ok(registry.projects)
-}
-
-pub fn find_project_entry(project_id: &str) -> Result<Option<ProjectEntry>> {
-    let entries = read_registry_global()?;
-    Ok(entries
-        .into_iter()
-        .find(|entry| entry.project_id == project_id))
+fn ensure_column(conn: &Connection, table: &str, column: &str, decl: &str) -> Result<()> {
+    let mut stmt = conn.prepare(&format!("PRAGMA table_info({table})"))?;
+    let exists = stmt
+        .query_map([], |row| row.get::<_, String>(1))?
+        .filter_map(|name| name.ok())
+        .any(|name| name == column);
+    if !exists {
+        conn.execute_batch(&format!("ALTER TABLE {table} ADD COLUMN {column} {decl}"))?;
+    }
+    Ok(())
 }
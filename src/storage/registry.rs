@@ -0,0 +1,305 @@
+//! Index of every project MeowDiff has stored history for, shared by all
+//! watchers in `~/.meowdiff/registry.db`.
+//!
+//! Each update is a single SQLite statement, so concurrent watchers never
+//! overwrite each other's entries the way the old `registry.json`
+//! read-modify-write could.
+
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::{anyhow, Context, Result};
+use chrono::Utc;
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use serde::{Deserialize, Serialize};
+
+use crate::util;
+
+pub const REGISTRY_DB: &str = "registry.db";
+/// Registry format used before the SQLite index; imported once on open.
+const LEGACY_REGISTRY: &str = "registry.json";
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProjectEntry {
+    pub project_id: String,
+    pub path: String,
+    pub last_seen: i64,
+    /// Watched by `meowdiff daemon`.
+    #[serde(default)]
+    pub enabled: bool,
+    /// When the project was last enabled; lets the daemon tell a fresh
+    /// `enable` apart from one it has already acted on.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub enabled_at: Option<i64>,
+    /// Stopped with `meowdiff stop`; the daemon leaves it alone, across
+    /// restarts too, until it is enabled again.
+    #[serde(default)]
+    pub stopped: bool,
+    /// End of the newest record, in milliseconds.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_record_at: Option<i64>,
+    /// Size of the project's store on disk when last measured.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub storage_bytes: Option<u64>,
+    /// Last state a watcher reported; may be stale if it crashed.
+    #[serde(default)]
+    pub watcher_state: WatcherStatus,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub watcher_pid: Option<i32>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WatcherStatus {
+    #[default]
+    Stopped,
+    Running,
+    /// Running inside `meowdiff daemon`.
+    Supervised,
+}
+
+impl WatcherStatus {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Stopped => "stopped",
+            Self::Running => "running",
+            Self::Supervised => "supervised",
+        }
+    }
+
+    fn parse(value: &str) -> Self {
+        match value {
+            "running" => Self::Running,
+            "supervised" => Self::Supervised,
+            _ => Self::Stopped,
+        }
+    }
+}
+
+pub struct Registry {
+    conn: Connection,
+}
+
+impl Registry {
+    /// Opens the registry under `~/.meowdiff`.
+    pub fn open() -> Result<Self> {
+        Self::open_in(&util::meowdiff_root()?)
+    }
+
+    /// Opens the registry kept in `root`, importing a legacy
+    /// `registry.json` found there.
+    pub fn open_in(root: &Path) -> Result<Self> {
+        util::ensure_dir(root)?;
+        let path = root.join(REGISTRY_DB);
+        let conn = Connection::open(&path)
+            .with_context(|| format!("failed to open {}", path.display()))?;
+        conn.busy_timeout(BUSY_TIMEOUT)?;
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        conn.execute_batch(
+            r#"
+            CREATE TABLE IF NOT EXISTS projects (
+                project_id TEXT PRIMARY KEY,
+                path TEXT NOT NULL,
+                last_seen INTEGER NOT NULL,
+                enabled INTEGER NOT NULL DEFAULT 0,
+                enabled_at INTEGER,
+                last_record_at INTEGER,
+                storage_bytes INTEGER,
+                watcher_state TEXT NOT NULL DEFAULT 'stopped',
+                watcher_pid INTEGER
+            );
+            "#,
+        )?;
+        super::ensure_column(&conn, "projects", "stopped", "INTEGER NOT NULL DEFAULT 0")?;
+        let registry = Self { conn };
+        registry.import_legacy(&root.join(LEGACY_REGISTRY))?;
+        Ok(registry)
+    }
+
+    fn import_legacy(&self, path: &Path) -> Result<()> {
+        #[derive(Deserialize)]
+        struct LegacyFile {
+            projects: Vec<ProjectEntry>,
+        }
+
+        if !path.exists() {
+            return Ok(());
+        }
+        let contents =
+            fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+        let legacy: LegacyFile = serde_json::from_slice(&contents)
+            .with_context(|| format!("failed to parse {}", path.display()))?;
+        for entry in &legacy.projects {
+            self.conn.execute(
+                "INSERT OR IGNORE INTO projects (project_id, path, last_seen, enabled, enabled_at) VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![
+                    entry.project_id,
+                    entry.path,
+                    entry.last_seen,
+                    entry.enabled,
+                    entry.enabled_at
+                ],
+            )?;
+        }
+        // another process may have imported it first
+        match fs::rename(path, path.with_extension("json.bak")) {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => {
+                Err(err).with_context(|| format!("failed to retire {}", path.display()))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Records that the project at `path` was just used.
+    pub fn touch(&self, project_id: &str, path: &Path) -> Result<()> {
+        self.conn.execute(
+            "INSERT INTO projects (project_id, path, last_seen) VALUES (?1, ?2, ?3) ON CONFLICT(project_id) DO UPDATE SET path=excluded.path, last_seen=excluded.last_seen",
+            params![
+                project_id,
+                path.to_string_lossy(),
+                Utc::now().timestamp()
+            ],
+        )?;
+        Ok(())
+    }
+
+    pub fn set_last_record(&self, project_id: &str, ended_at_ms: i64) -> Result<()> {
+        self.conn.execute(
+            "UPDATE projects SET last_record_at = MAX(COALESCE(last_record_at, 0), ?2), last_seen = ?3 WHERE project_id = ?1",
+            params![project_id, ended_at_ms, Utc::now().timestamp()],
+        )?;
+        Ok(())
+    }
+
+    pub fn set_storage_bytes(&self, project_id: &str, bytes: u64) -> Result<()> {
+        self.conn.execute(
+            "UPDATE projects SET storage_bytes = ?2 WHERE project_id = ?1",
+            params![project_id, bytes as i64],
+        )?;
+        Ok(())
+    }
+
+    pub fn add_storage_bytes(&self, project_id: &str, bytes: u64) -> Result<()> {
+        self.conn.execute(
+            "UPDATE projects SET storage_bytes = COALESCE(storage_bytes, 0) + ?2 WHERE project_id = ?1",
+            params![project_id, bytes as i64],
+        )?;
+        Ok(())
+    }
+
+    pub fn set_watcher(
+        &self,
+        project_id: &str,
+        state: WatcherStatus,
+        pid: Option<i32>,
+    ) -> Result<()> {
+        self.conn.execute(
+            "UPDATE projects SET watcher_state = ?2, watcher_pid = ?3 WHERE project_id = ?1",
+            params![project_id, state.as_str(), pid],
+        )?;
+        Ok(())
+    }
+
+    /// Marks a registered project as watched (or not) by `meowdiff daemon`.
+    pub fn set_enabled(&self, project_id: &str, enabled: bool) -> Result<ProjectEntry> {
+        let enabled_at = enabled.then(|| Utc::now().timestamp_millis());
+        let updated = self.conn.execute(
+            "UPDATE projects SET enabled = ?2, enabled_at = ?3, stopped = 0 WHERE project_id = ?1",
+            params![project_id, enabled, enabled_at],
+        )?;
+        if updated == 0 {
+            return Err(anyhow!("project {project_id} not found in registry"));
+        }
+        self.find(project_id)?
+            .ok_or_else(|| anyhow!("project {project_id} not found in registry"))
+    }
+
+    /// Keeps the daemon from restarting a project stopped by hand.
+    pub fn set_stopped(&self, project_id: &str, stopped: bool) -> Result<()> {
+        self.conn.execute(
+            "UPDATE projects SET stopped = ?2 WHERE project_id = ?1",
+            params![project_id, stopped],
+        )?;
+        Ok(())
+    }
+
+    pub fn list(&self) -> Result<Vec<ProjectEntry>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT * FROM projects ORDER BY last_seen DESC, project_id")?;
+        let rows = stmt.query_map([], entry_from_row)?;
+        Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+    }
+
+    pub fn find(&self, project_id: &str) -> Result<Option<ProjectEntry>> {
+        let entry = self
+            .conn
+            .query_row(
+                "SELECT * FROM projects WHERE project_id = ?1",
+                [project_id],
+                entry_from_row,
+            )
+            .optional()?;
+        Ok(entry)
+    }
+
+    /// Hands `old_id`'s daemon enablement and record time to `new_id` and
+    /// drops `old_id`, once its history lives under the new id.
+    pub fn merge_into(&self, old_id: &str, new_id: &str) -> Result<()> {
+        self.conn.execute(
+            "UPDATE projects SET \
+                enabled = MAX(projects.enabled, old.enabled), \
+                enabled_at = CASE WHEN old.enabled THEN old.enabled_at ELSE projects.enabled_at END, \
+                last_record_at = MAX(COALESCE(projects.last_record_at, 0), COALESCE(old.last_record_at, 0)) \
+             FROM (SELECT enabled, enabled_at, last_record_at FROM projects WHERE project_id = ?1) AS old \
+             WHERE project_id = ?2",
+            params![old_id, new_id],
+        )?;
+        self.remove(old_id)?;
+        Ok(())
+    }
+
+    /// Forgets a project. Its stored history is left on disk.
+    pub fn remove(&self, project_id: &str) -> Result<bool> {
+        let removed = self
+            .conn
+            .execute("DELETE FROM projects WHERE project_id = ?1", [project_id])?;
+        Ok(removed > 0)
+    }
+}
+
+fn entry_from_row(row: &Row<'_>) -> rusqlite::Result<ProjectEntry> {
+    Ok(ProjectEntry {
+        project_id: row.get("project_id")?,
+        path: row.get("path")?,
+        last_seen: row.get("last_seen")?,
+        enabled: row.get("enabled")?,
+        enabled_at: row.get("enabled_at")?,
+        stopped: row.get("stopped")?,
+        last_record_at: row.get("last_record_at")?,
+        storage_bytes: row
+            .get::<_, Option<i64>>("storage_bytes")?
+            .map(|bytes| bytes.max(0) as u64),
+        watcher_state: WatcherStatus::parse(&row.get::<_, String>("watcher_state")?),
+        watcher_pid: row.get("watcher_pid")?,
+    })
+}
+
+/// Marks a registered project as watched (or not) by `meowdiff daemon`.
+pub fn set_project_enabled(project_id: &str, enabled: bool) -> Result<ProjectEntry> {
+    Registry::open()?.set_enabled(project_id, enabled)
+}
+
+pub fn set_project_stopped(project_id: &str, stopped: bool) -> Result<()> {
+    Registry::open()?.set_stopped(project_id, stopped)
+}
+
+pub fn read_registry_global() -> Result<Vec<ProjectEntry>> {
+    Registry::open()?.list()
+}
+
+pub fn find_project_entry(project_id: &str) -> Result<Option<ProjectEntry>> {
+    Registry::open()?.find(project_id)
+}
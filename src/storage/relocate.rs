@@ -0,0 +1,359 @@
+//! Moving a project's history to a new checkout path.
+
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Component, Path};
+
+use anyhow::{bail, Context, Result};
+use rusqlite::params;
+use rusqlite::types::Value;
+use serde::Serialize;
+
+use super::{Registry, StorageEngine};
+use crate::util;
+
+/// How well a tree on disk matches a store's latest snapshots.
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct TreeCheck {
+    pub tracked: usize,
+    pub matched: usize,
+    pub changed: Vec<String>,
+    pub missing: Vec<String>,
+}
+
+impl TreeCheck {
+    /// Share of tracked files found unchanged; an empty store matches fully.
+    pub fn ratio(&self) -> f64 {
+        if self.tracked == 0 {
+            1.0
+        } else {
+            self.matched as f64 / self.tracked as f64
+        }
+    }
+}
+
+impl std::fmt::Display for TreeCheck {
+    /// The match counts followed by the first few changed and missing paths.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} of {} tracked files match ({:.0}%)",
+            self.matched,
+            self.tracked,
+            self.ratio() * 100.0
+        )?;
+        for path in self.changed.iter().take(5) {
+            write!(f, "\n  changed: {path}")?;
+        }
+        for path in self.missing.iter().take(5) {
+            write!(f, "\n  missing: {path}")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct RelocateOptions {
+    /// Combine with history already recorded for the new path.
+    pub merge: bool,
+    /// Percentage of tracked files that must match the new tree.
+    pub min_match: u8,
+    /// Relocate even if the new tree does not match.
+    pub force: bool,
+}
+
+/// Outcome of [`relocate`].
+#[derive(Debug)]
+pub struct Relocation {
+    pub new_id: String,
+    pub check: TreeCheck,
+    /// Whether the records were merged into history the new path already
+    /// had, rather than the whole store being moved.
+    pub merged: bool,
+    pub records: usize,
+}
+
+/// Moves the history of `old_id` under `root` to the checkout now at
+/// `new_root`: the store directory is renamed and re-keyed, or with
+/// `merge` its records are copied into the new path's own history. The
+/// registry entry follows. Callers make sure no watcher holds either store.
+pub fn relocate(
+    root: &Path,
+    old_id: &str,
+    new_root: &Path,
+    options: RelocateOptions,
+) -> Result<Relocation> {
+    let old_dir = root.join(old_id);
+    if !old_dir.join("timeline.db").exists() {
+        bail!("no history stored for project {old_id}");
+    }
+    let new_id = util::compute_project_id(new_root)?;
+    if new_id == old_id {
+        bail!("project {old_id} already lives at {}", new_root.display());
+    }
+    let new_dir = root.join(&new_id);
+
+    let old = StorageEngine::open_dir(root, new_root, old_id, &old_dir)?;
+    let check = old.check_tree(new_root)?;
+    if check.ratio() * 100.0 < f64::from(options.min_match) && !options.force {
+        bail!(
+            "{} does not look like project {old_id}; pass --force to relocate anyway\n{check}",
+            new_root.display()
+        );
+    }
+
+    let new_has_history = new_dir.join("timeline.db").exists()
+        && StorageEngine::open_dir(root, new_root, &new_id, &new_dir)?.has_records()?;
+    let (storage, records) = if new_has_history {
+        if !options.merge {
+            bail!(
+                "{} already has its own history; pass --merge to combine both",
+                new_root.display()
+            );
+        }
+        let storage = StorageEngine::open_in(root, new_root)?;
+        let records = storage.import_history(&old)?;
+        storage.copy_directories(&old)?;
+        drop(old);
+        fs::remove_dir_all(&old_dir)
+            .with_context(|| format!("failed to remove {}", old_dir.display()))?;
+        (storage, records)
+    } else {
+        drop(old);
+        if new_dir.exists() {
+            // a store without records only holds a baseline for the new path
+            fs::remove_dir_all(&new_dir)
+                .with_context(|| format!("failed to remove {}", new_dir.display()))?;
+        }
+        fs::rename(&old_dir, &new_dir).with_context(|| {
+            format!(
+                "failed to move {} to {}",
+                old_dir.display(),
+                new_dir.display()
+            )
+        })?;
+        let storage = StorageEngine::open_in(root, new_root)?;
+        let records = storage.rekey_records(old_id)?;
+        (storage, records)
+    };
+    Registry::open_in(root)?.merge_into(old_id, &new_id)?;
+    storage.refresh_storage_size()?;
+    Ok(Relocation {
+        new_id,
+        check,
+        merged: new_has_history,
+        records,
+    })
+}
+
+/// Files of a record directory copied besides `meta.json`, which is
+/// rewritten for the destination store.
+const RECORD_FILES: [&str; 2] = ["diff.patch.zst", "semantic.json"];
+
+/// Columns copied verbatim when records move between stores.
+const RECORD_COLUMNS: &str = "record_id, ts_start, ts_end, files_json, stats_json, prev_record_id, diff_hash, duration_ms, git_branch, git_head, label";
+
+impl StorageEngine {
+    /// Compares every snapshotted file with its counterpart under `root`.
+    pub fn check_tree(&self, root: &Path) -> Result<TreeCheck> {
+        let snapshots: Vec<(String, String)> = {
+            let conn = self.conn.lock().unwrap();
+            let mut stmt = conn.prepare("SELECT path, sha FROM latest_snapshots ORDER BY path")?;
+            let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+            rows.collect::<rusqlite::Result<_>>()?
+        };
+        let mut check = TreeCheck {
+            tracked: snapshots.len(),
+            ..Default::default()
+        };
+        for (path, sha) in snapshots {
+            match util::read_worktree_entry(&root.join(&path))? {
+                Some((data, _)) if util::hash_bytes(&data) == sha => check.matched += 1,
+                Some(_) => check.changed.push(path),
+                None => check.missing.push(path),
+            }
+        }
+        Ok(check)
+    }
+
+    /// Points every record at this store's project id after the directory
+    /// was moved over from `old_id`.
+    pub fn rekey_records(&self, old_id: &str) -> Result<usize> {
+        let ids: Vec<String> = {
+            let conn = self.conn.lock().unwrap();
+            conn.execute(
+                "UPDATE records SET project_id = ?1 WHERE project_id = ?2",
+                params![self.project_id, old_id],
+            )?;
+            let mut stmt = conn.prepare("SELECT record_id FROM records")?;
+            let rows = stmt.query_map([], |row| row.get(0))?;
+            rows.collect::<rusqlite::Result<_>>()?
+        };
+        for record_id in &ids {
+            let mut meta = self.read_record_meta(record_id)?;
+            if meta.project_id != self.project_id {
+                meta.project_id = self.project_id.clone();
+                let path = self.paths.records_dir.join(record_id).join("meta.json");
+                util::write_atomic(&path, &serde_json::to_vec_pretty(&meta)?)?;
+            }
+        }
+        Ok(ids.len())
+    }
+
+    /// Copies all records of `other`, with the blobs they reference, into
+    /// this store, keeping this store's snapshots as the current state.
+    /// Returns the records added.
+    pub fn import_history(&self, other: &StorageEngine) -> Result<usize> {
+        self.copy_history(other, None)
+    }
+
+    /// Copies records of `other` (all, or those in `only`) that this store
+    /// does not have yet. Files land before the index row, as in
+    /// `commit_record`.
+    pub(super) fn copy_history(
+        &self,
+        other: &StorageEngine,
+        only: Option<&HashSet<String>>,
+    ) -> Result<usize> {
+        let rows = {
+            let conn = other.conn.lock().unwrap();
+            let mut stmt = conn.prepare(&format!(
+                "SELECT {RECORD_COLUMNS} FROM records ORDER BY ts_end"
+            ))?;
+            let columns = RECORD_COLUMNS.split(", ").count();
+            let rows = stmt.query_map([], |row| {
+                (0..columns)
+                    .map(|i| row.get::<_, Value>(i))
+                    .collect::<rusqlite::Result<Vec<_>>>()
+            })?;
+            rows.collect::<rusqlite::Result<Vec<_>>>()?
+        };
+
+        let tags = other.tags()?;
+        let bookmarks = other.bookmarks()?;
+        let mut copied = 0;
+        for mut values in rows {
+            let Value::Text(record_id) = values[0].clone() else {
+                continue;
+            };
+            if only.is_some_and(|only| !only.contains(&record_id)) {
+                continue;
+            }
+            let dest = self.paths.records_dir.join(&record_id);
+            if dest.exists() {
+                continue;
+            }
+            let source = other.paths.records_dir.join(&record_id);
+            if !is_regular_file(&source.join("meta.json"))? {
+                bail!("record {record_id} has no meta.json to copy");
+            }
+            let mut meta = other.read_record_meta(&record_id)?;
+            if let Some(file) = meta.files.iter().find(|file| !is_tree_path(&file.path)) {
+                bail!("record {record_id} names an invalid path {:?}", file.path);
+            }
+            // read everything before writing, so a refused record leaves no
+            // partial directory behind
+            let mut files = Vec::new();
+            for name in RECORD_FILES {
+                let path = source.join(name);
+                if is_regular_file(&path)? {
+                    let data = fs::read(&path)
+                        .with_context(|| format!("failed to read {}", path.display()))?;
+                    files.push((name, data));
+                }
+            }
+            for file in &meta.files {
+                for sha in [&file.before_sha, &file.after_sha].into_iter().flatten() {
+                    self.copy_blob(other, sha)?;
+                }
+            }
+            for (name, data) in files {
+                util::write_atomic(&dest.join(name), &data)?;
+            }
+            meta.project_id = self.project_id.clone();
+            util::write_atomic(&dest.join("meta.json"), &serde_json::to_vec_pretty(&meta)?)?;
+
+            values.insert(1, self.project_id.clone().into());
+            let placeholders = (1..=values.len())
+                .map(|i| format!("?{i}"))
+                .collect::<Vec<_>>()
+                .join(", ");
+            let inserted = self.conn.lock().unwrap().execute(
+                &format!(
+                    "INSERT OR IGNORE INTO records (record_id, project_id, {}, imported) VALUES ({placeholders}, 1)",
+                    RECORD_COLUMNS.trim_start_matches("record_id, "),
+                ),
+                rusqlite::params_from_iter(values),
+            )?;
+            if inserted > 0 {
+                self.copy_annotations(other, &record_id, &tags, &bookmarks)?;
+            }
+            copied += inserted;
+        }
+        Ok(copied)
+    }
+
+    /// Tracks the directories `other` knew about that also exist in this
+    /// store's tree, so merged history keeps seeing them as directories.
+    fn copy_directories(&self, other: &StorageEngine) -> Result<()> {
+        let rows = {
+            let conn = other.conn.lock().unwrap();
+            let mut stmt = conn.prepare("SELECT path, record_id, updated_at FROM directories")?;
+            let rows = stmt.query_map([], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, i64>(2)?,
+                ))
+            })?;
+            rows.collect::<rusqlite::Result<Vec<_>>>()?
+        };
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "INSERT OR IGNORE INTO directories (path, record_id, updated_at) VALUES (?1, ?2, ?3)",
+        )?;
+        for (path, record_id, updated_at) in rows {
+            if self.project_root.join(&path).is_dir() {
+                stmt.execute(params![path, record_id, updated_at])?;
+            }
+        }
+        Ok(())
+    }
+
+    fn copy_blob(&self, other: &StorageEngine, sha: &str) -> Result<()> {
+        let dest = self.blob_path(sha);
+        if dest.exists() {
+            return Ok(());
+        }
+        let source = other.blob_path(sha);
+        if !is_regular_file(&source)? {
+            bail!(
+                "blob {sha} missing from {}",
+                other.paths.blobs_dir.display()
+            );
+        }
+        let data =
+            fs::read(&source).with_context(|| format!("failed to read {}", source.display()))?;
+        util::write_atomic(&dest, &data)
+    }
+}
+
+/// Whether `path` stays inside the project tree: relative, non-empty and
+/// made only of plain components.
+pub(super) fn is_tree_path(path: &str) -> bool {
+    let mut components = Path::new(path).components().peekable();
+    components.peek().is_some()
+        && components.all(|component| matches!(component, Component::Normal(_)))
+}
+
+/// Whether `path` is a regular file; missing is `false` and anything else,
+/// symlinks included, is refused so a copied store cannot reach outside
+/// itself.
+fn is_regular_file(path: &Path) -> Result<bool> {
+    match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.is_file() => Ok(true),
+        Ok(_) => bail!("{} is not a regular file", path.display()),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(false),
+        Err(err) => Err(err).with_context(|| format!("failed to read {}", path.display())),
+    }
+}
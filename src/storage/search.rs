@@ -0,0 +1,227 @@
+//! Finding records whose patches added or removed matching lines.
+//!
+//! Matching always runs the regex over parsed hunks. The optional FTS5
+//! index (`patch_search`, trigram tokens over added and removed lines) only
+//! narrows down which patches are worth reading, using literals every match
+//! has to contain.
+
+use std::collections::HashSet;
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use regex::Regex;
+use regex_syntax::hir::literal::{ExtractKind, Extractor};
+use rusqlite::{params, Connection};
+use serde::Serialize;
+
+use super::{StorageEngine, TimelineFilter};
+use crate::ignore::PathGlobs;
+use crate::pipeline::{decompress_patch, parse_patch, FileArtifact, Hunk};
+
+/// The trigram tokenizer cannot look up anything shorter.
+const MIN_LITERAL_CHARS: usize = 3;
+
+/// Which lines of a hunk the pattern is matched against.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LineSide {
+    #[default]
+    Either,
+    Added,
+    Removed,
+}
+
+pub struct SearchQuery<'a> {
+    pub pattern: &'a Regex,
+    pub side: LineSide,
+    pub files: Option<&'a PathGlobs>,
+    /// Time range; `limit` counts records searched, newest first.
+    pub filter: TimelineFilter,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SearchHit {
+    pub record_id: String,
+    pub timestamp: DateTime<Utc>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    pub path: String,
+    /// The whole hunk, header included.
+    pub hunk: String,
+    /// Matching lines with their `+` / `-` prefix.
+    pub lines: Vec<String>,
+}
+
+/// Creates the index when this SQLite build has FTS5; search falls back to
+/// reading every patch in range otherwise.
+pub(super) fn init_search_index(conn: &Connection) -> bool {
+    let created = conn.execute_batch(
+        "CREATE VIRTUAL TABLE IF NOT EXISTS patch_search USING fts5(record_id UNINDEXED, added, removed, tokenize = 'trigram');",
+    );
+    if let Err(err) = &created {
+        tracing::debug!(error = %err, "full-text search index unavailable");
+    }
+    created.is_ok()
+}
+
+/// Adds a record's changed lines to the index.
+pub(super) fn index_record(
+    conn: &Connection,
+    record_id: &str,
+    artifacts: &[FileArtifact],
+) -> Result<()> {
+    let patch: String = artifacts
+        .iter()
+        .map(|artifact| artifact.patch.as_str())
+        .collect::<Vec<_>>()
+        .join("\n");
+    index_patch(conn, record_id, &patch)
+}
+
+fn index_patch(conn: &Connection, record_id: &str, patch: &str) -> Result<()> {
+    let (mut added, mut removed) = (String::new(), String::new());
+    for hunk in parse_patch(patch).iter().flat_map(|file| &file.hunks) {
+        for line in hunk.added() {
+            added.push_str(line);
+            added.push('\n');
+        }
+        for line in hunk.removed() {
+            removed.push_str(line);
+            removed.push('\n');
+        }
+    }
+    conn.execute(
+        "INSERT INTO patch_search (record_id, added, removed) VALUES (?1, ?2, ?3)",
+        params![record_id, added, removed],
+    )?;
+    Ok(())
+}
+
+impl StorageEngine {
+    /// Hunks whose added and/or removed lines match `query.pattern`, newest
+    /// record first.
+    pub fn search(&self, query: &SearchQuery) -> Result<Vec<SearchHit>> {
+        let candidates = match self.search_index {
+            true => {
+                self.backfill_search_index()?;
+                self.search_candidates(query)?
+            }
+            false => None,
+        };
+        let mut hits = Vec::new();
+        for entry in self.timeline(&query.filter)? {
+            if candidates
+                .as_ref()
+                .is_some_and(|ids| !ids.contains(&entry.record_id))
+            {
+                continue;
+            }
+            let patch = decompress_patch(&self.read_patch(&entry.record_id)?)?;
+            for file in parse_patch(&patch) {
+                if query
+                    .files
+                    .is_some_and(|globs| !globs.matches_relative(&file.path))
+                {
+                    continue;
+                }
+                for hunk in &file.hunks {
+                    let lines = matching_lines(hunk, query.pattern, query.side);
+                    if lines.is_empty() {
+                        continue;
+                    }
+                    hits.push(SearchHit {
+                        record_id: entry.record_id.clone(),
+                        timestamp: entry.timestamp,
+                        label: entry.label.clone(),
+                        path: file.path.clone(),
+                        hunk: hunk.text(),
+                        lines,
+                    });
+                }
+            }
+        }
+        Ok(hits)
+    }
+
+    /// Indexes records committed before the index existed or copied in by
+    /// `relocate` / `import`.
+    fn backfill_search_index(&self) -> Result<usize> {
+        let missing: Vec<String> = {
+            let conn = self.conn.lock().unwrap();
+            let mut stmt = conn.prepare(
+                "SELECT record_id FROM records WHERE record_id NOT IN (SELECT record_id FROM patch_search)",
+            )?;
+            let rows = stmt.query_map([], |row| row.get(0))?;
+            rows.collect::<rusqlite::Result<_>>()?
+        };
+        for record_id in &missing {
+            let patch = decompress_patch(&self.read_patch(record_id)?)?;
+            index_patch(&self.conn.lock().unwrap(), record_id, &patch)?;
+        }
+        if !missing.is_empty() {
+            tracing::debug!(records = missing.len(), "indexed records for search");
+        }
+        Ok(missing.len())
+    }
+
+    /// Records whose indexed lines contain a literal every match needs, or
+    /// `None` when the pattern has no usable literal.
+    fn search_candidates(&self, query: &SearchQuery) -> Result<Option<HashSet<String>>> {
+        let Some(literals) = required_literals(query.pattern.as_str()) else {
+            return Ok(None);
+        };
+        let columns = match query.side {
+            LineSide::Either => "{added removed}",
+            LineSide::Added => "added",
+            LineSide::Removed => "removed",
+        };
+        let terms: Vec<String> = literals
+            .iter()
+            .map(|literal| format!("\"{}\"", literal.replace('"', "\"\"")))
+            .collect();
+        let expression = format!("{columns} : ({})", terms.join(" OR "));
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn
+            .prepare("SELECT DISTINCT record_id FROM patch_search WHERE patch_search MATCH ?1")?;
+        let rows = stmt.query_map([expression], |row| row.get(0))?;
+        Ok(Some(rows.collect::<rusqlite::Result<_>>()?))
+    }
+}
+
+fn matching_lines(hunk: &Hunk, pattern: &Regex, side: LineSide) -> Vec<String> {
+    hunk.lines
+        .iter()
+        .filter(|line| match line.as_bytes().first() {
+            Some(b'+') => side != LineSide::Removed,
+            Some(b'-') => side != LineSide::Added,
+            _ => false,
+        })
+        .filter(|line| pattern.is_match(&line[1..]))
+        .cloned()
+        .collect()
+}
+
+/// Literals one of which every match of `pattern` starts (or else ends)
+/// with, when all of them are long enough to look up.
+fn required_literals(pattern: &str) -> Option<Vec<String>> {
+    let hir = regex_syntax::parse(pattern).ok()?;
+    for kind in [ExtractKind::Prefix, ExtractKind::Suffix] {
+        let seq = Extractor::new().kind(kind).extract(&hir);
+        let Some(literals) = seq.literals() else {
+            continue;
+        };
+        let literals: Option<Vec<String>> = literals
+            .iter()
+            .map(|literal| String::from_utf8(literal.as_bytes().to_vec()).ok())
+            .collect();
+        if let Some(literals) = literals.filter(|literals| {
+            !literals.is_empty()
+                && literals
+                    .iter()
+                    .all(|literal| literal.chars().count() >= MIN_LITERAL_CHARS)
+        }) {
+            return Some(literals);
+        }
+    }
+    None
+}
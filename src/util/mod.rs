@@ -1,4 +1,7 @@
+use std::fs::File;
+use std::io::Write;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
 
 use anyhow::{Context, Result};
 use blake3::Hasher;
@@ -53,6 +56,114 @@ pub fn ensure_dir(path: &Path) -> Result<()> {
     Ok(())
 }
 
+/// Replaces `path` with `data` so that readers, and a crash at any point,
+/// only ever see the old or the new content in full.
+pub fn write_atomic(path: &Path, data: &[u8]) -> Result<()> {
+    write_atomic_with(path, |file| Ok(file.write_all(data)?))
+}
+
+/// Like [`write_atomic`], streaming the content through `write`.
+///
+/// The data goes to a temp file in the same directory, which is fsynced and
+/// renamed over `path`; the directory is fsynced last so the rename itself
+/// survives a power loss.
+pub fn write_atomic_with(path: &Path, write: impl FnOnce(&mut File) -> Result<()>) -> Result<()> {
+    let parent = path
+        .parent()
+        .with_context(|| format!("{} has no parent directory", path.display()))?;
+    let created = !parent.exists();
+    ensure_dir(parent)?;
+    let tmp = temp_sibling(path);
+    let written = File::create(&tmp)
+        .with_context(|| format!("failed to create {}", tmp.display()))
+        .and_then(|mut file| {
+            write(&mut file)?;
+            file.sync_all()
+                .with_context(|| format!("failed to sync {}", tmp.display()))
+        })
+        .and_then(|_| {
+            std::fs::rename(&tmp, path)
+                .with_context(|| format!("failed to write {}", path.display()))
+        });
+    if let Err(err) = written {
+        let _ = std::fs::remove_file(&tmp);
+        return Err(err);
+    }
+    sync_dir(parent)?;
+    // a freshly created directory is only durable once its parent is synced
+    match parent.parent() {
+        Some(grandparent) if created => sync_dir(grandparent),
+        _ => Ok(()),
+    }
+}
+
+/// Removes temp files under `dir` left by [`write_atomic`] in a process that
+/// died mid-write. Returns how many were removed.
+pub fn sweep_temp_files(dir: &Path) -> Result<usize> {
+    let mut removed = 0;
+    for entry in walkdir::WalkDir::new(dir)
+        .into_iter()
+        .filter_map(|e| e.ok())
+    {
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let name = entry.file_name().to_string_lossy();
+        let Some(writer) = temp_writer_pid(&name) else {
+            continue;
+        };
+        // a live writer may be about to rename it into place
+        if is_process_alive(writer) {
+            continue;
+        }
+        std::fs::remove_file(entry.path())
+            .with_context(|| format!("failed to remove {}", entry.path().display()))?;
+        removed += 1;
+    }
+    Ok(removed)
+}
+
+/// Pid of the writer that named a temp file `.<name>.<pid>.<n>.tmp`.
+fn temp_writer_pid(file_name: &str) -> Option<i32> {
+    let stem = file_name.strip_prefix('.')?.strip_suffix(".tmp")?;
+    let mut parts = stem.rsplitn(3, '.');
+    parts.next()?.parse::<u64>().ok()?;
+    let pid = parts.next()?.parse().ok()?;
+    parts.next().filter(|name| !name.is_empty())?;
+    Some(pid)
+}
+
+pub fn is_process_alive(pid: i32) -> bool {
+    if pid <= 0 {
+        return false;
+    }
+    unsafe { libc::kill(pid, 0) == 0 }
+}
+
+/// Hidden, per-writer temp name so concurrent writers never share one.
+fn temp_sibling(path: &Path) -> PathBuf {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let n = COUNTER.fetch_add(1, Ordering::Relaxed);
+    path.with_file_name(format!(".{name}.{}.{n}.tmp", std::process::id()))
+}
+
+#[cfg(unix)]
+fn sync_dir(dir: &Path) -> Result<()> {
+    File::open(dir)
+        .and_then(|dir| dir.sync_all())
+        .with_context(|| format!("failed to sync directory {}", dir.display()))
+}
+
+#[cfg(not(unix))]
+fn sync_dir(_dir: &Path) -> Result<()> {
+    // directory handles cannot be fsynced here; the rename is still atomic
+    Ok(())
+}
+
 pub fn now_utc() -> DateTime<Utc> {
     Utc::now()
 }
@@ -63,6 +174,123 @@ pub fn relative_path(project_root: &Path, path: &Path) -> Option<String> {
         .map(|p| p.to_string_lossy().to_string())
 }
 
+pub const MODE_SYMLINK: u32 = 0o120000;
+const MODE_REGULAR: u32 = 0o100000;
+
+pub fn is_symlink_mode(mode: u32) -> bool {
+    mode & 0o170000 == MODE_SYMLINK
+}
+
+/// Reads a working-tree entry without following symlinks.
+///
+/// Returns the content (the link target for symlinks) together with git-style
+/// mode bits, or `None` when the path is missing or a directory.
+pub fn read_worktree_entry(path: &Path) -> Result<Option<(Vec<u8>, u32)>> {
+    let meta = match std::fs::symlink_metadata(path) {
+        Ok(meta) => meta,
+        Err(_) => return Ok(None),
+    };
+    if meta.file_type().is_symlink() {
+        let target = std::fs::read_link(path)
+            .with_context(|| format!("failed to read link {}", path.display()))?;
+        let bytes = target.to_string_lossy().as_bytes().to_vec();
+        return Ok(Some((bytes, MODE_SYMLINK | 0o777)));
+    }
+    if meta.is_dir() {
+        return Ok(None);
+    }
+    let data = std::fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+    Ok(Some((data, MODE_REGULAR | permission_bits(&meta))))
+}
+
+/// Git-style mode of a working-tree entry, as `read_worktree_entry` would
+/// report it, without reading the content.
+pub fn worktree_mode(path: &Path) -> Option<u32> {
+    let meta = std::fs::symlink_metadata(path).ok()?;
+    if meta.file_type().is_symlink() {
+        Some(MODE_SYMLINK | 0o777)
+    } else if meta.is_dir() {
+        None
+    } else {
+        Some(MODE_REGULAR | permission_bits(&meta))
+    }
+}
+
+/// Writes a file or symlink described by `mode`, replacing whatever is there.
+/// An empty directory in the way is removed; a non-empty one is an error.
+pub fn write_worktree_entry(path: &Path, data: &[u8], mode: Option<u32>) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        ensure_dir(parent)?;
+    }
+    let existing = std::fs::symlink_metadata(path).ok();
+    if existing.as_ref().is_some_and(|meta| meta.is_dir()) {
+        std::fs::remove_dir(path)
+            .with_context(|| format!("cannot replace directory {} with a file", path.display()))?;
+    }
+    let is_link = existing.is_some_and(|meta| meta.file_type().is_symlink());
+    match mode {
+        Some(mode) if is_symlink_mode(mode) => {
+            if is_link || path.exists() {
+                std::fs::remove_file(path)
+                    .with_context(|| format!("failed to replace {}", path.display()))?;
+            }
+            let target = String::from_utf8_lossy(data).to_string();
+            create_symlink(&target, path)
+        }
+        _ => {
+            if is_link {
+                std::fs::remove_file(path)
+                    .with_context(|| format!("failed to replace {}", path.display()))?;
+            }
+            std::fs::write(path, data)
+                .with_context(|| format!("failed to write {}", path.display()))?;
+            if let Some(mode) = mode {
+                set_permission_bits(path, mode)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+#[cfg(unix)]
+fn permission_bits(meta: &std::fs::Metadata) -> u32 {
+    use std::os::unix::fs::PermissionsExt;
+    meta.permissions().mode() & 0o7777
+}
+
+#[cfg(not(unix))]
+fn permission_bits(meta: &std::fs::Metadata) -> u32 {
+    if meta.permissions().readonly() {
+        0o444
+    } else {
+        0o644
+    }
+}
+
+#[cfg(unix)]
+fn set_permission_bits(path: &Path, mode: u32) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    let perms = std::fs::Permissions::from_mode(mode & 0o7777);
+    std::fs::set_permissions(path, perms)
+        .with_context(|| format!("failed to set mode on {}", path.display()))
+}
+
+#[cfg(not(unix))]
+fn set_permission_bits(_path: &Path, _mode: u32) -> Result<()> {
+    Ok(())
+}
+
+#[cfg(unix)]
+fn create_symlink(target: &str, path: &Path) -> Result<()> {
+    std::os::unix::fs::symlink(target, path)
+        .with_context(|| format!("failed to create symlink {}", path.display()))
+}
+
+#[cfg(not(unix))]
+fn create_symlink(target: &str, path: &Path) -> Result<()> {
+    std::fs::write(path, target).with_context(|| format!("failed to write {}", path.display()))
+}
+
 pub fn tool_version() -> String {
     env!("CARGO_PKG_VERSION").to_string()
 }
@@ -0,0 +1,237 @@
+//! The snapshots a store starts from, and deliberate resets of them.
+
+use std::collections::HashSet;
+use std::fmt;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use chrono::Utc;
+use walkdir::WalkDir;
+
+use super::{assemble_record, WatchLock};
+use crate::git;
+use crate::ignore::IgnoreMatcher;
+use crate::models::{FileOp, RecordMeta};
+use crate::pipeline::{
+    build_file_artifact, compress_patch, directory_artifact, DiffOptions, FileInput,
+};
+use crate::storage::StorageEngine;
+use crate::util;
+
+/// Where baseline contents come from.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum BaselineSource {
+    /// The files on disk.
+    #[default]
+    WorkingTree,
+    /// A git revision, so the first records show changes since that commit.
+    Git(String),
+}
+
+impl fmt::Display for BaselineSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::WorkingTree => write!(f, "working tree"),
+            Self::Git(rev) => write!(f, "git {rev}"),
+        }
+    }
+}
+
+/// Seeds an empty store's snapshots from `source`. Directories always come
+/// from disk; git does not track them.
+pub(super) fn prime_baseline(
+    project_root: &Path,
+    storage: &StorageEngine,
+    ignore: &IgnoreMatcher,
+    source: &BaselineSource,
+) -> Result<()> {
+    let mut count = 0usize;
+    visit_source(project_root, ignore, source, |path, data, mode| {
+        storage.seed_snapshot(path, &data, Some(mode))?;
+        count += 1;
+        Ok(())
+    })?;
+    prime_directories(project_root, storage, ignore)?;
+    tracing::info!(files = count, %source, "baseline snapshots prepared");
+    Ok(())
+}
+
+pub(super) fn prime_directories(
+    project_root: &Path,
+    storage: &StorageEngine,
+    ignore: &IgnoreMatcher,
+) -> Result<()> {
+    for rel in disk_directories(project_root, ignore) {
+        storage.seed_directory(&rel)?;
+    }
+    storage.mark_directories_primed()
+}
+
+/// Replaces the snapshots of the project at `project_root` with `source`.
+///
+/// The difference is committed as one labelled record, so replaying the
+/// timeline still ends at the current snapshots. Returns `None` when they
+/// already match. Refuses to run while a watcher holds the project.
+pub fn rebaseline(
+    project_root: &Path,
+    source: &BaselineSource,
+    diff: &DiffOptions,
+) -> Result<Option<RecordMeta>> {
+    let storage = StorageEngine::open(project_root)?;
+    let lock = WatchLock::acquire(&storage.paths().meta_dir, storage.project_id(), false)
+        .context("stop the watcher before rebaselining")?;
+    let ignore = IgnoreMatcher::new(project_root)?;
+    let label = match source {
+        BaselineSource::WorkingTree => "rebaseline from working tree".to_string(),
+        BaselineSource::Git(rev) => {
+            let commit = git::resolve_commit(project_root, rev)?;
+            format!("rebaseline from {rev} ({})", git::short_sha(&commit))
+        }
+    };
+    let started_at = Utc::now();
+
+    // directories first, like a batch: created ones, then removals deepest first
+    let tracked: HashSet<String> = storage.directory_paths()?.into_iter().collect();
+    let on_disk: HashSet<String> = disk_directories(project_root, &ignore)
+        .into_iter()
+        .collect();
+    let mut created: Vec<&String> = on_disk.difference(&tracked).collect();
+    created.sort();
+    let mut removed: Vec<&String> = tracked.difference(&on_disk).collect();
+    removed.sort_by_key(|path| (std::cmp::Reverse(path.matches('/').count()), *path));
+    let mut artifacts: Vec<_> = created
+        .into_iter()
+        .map(|path| directory_artifact(path, FileOp::DirCreated))
+        .chain(
+            removed
+                .into_iter()
+                .map(|path| directory_artifact(path, FileOp::DirRemoved)),
+        )
+        .collect();
+
+    let mut current = storage.current_tree()?;
+    let mut files = Vec::new();
+    visit_source(project_root, &ignore, source, |path, data, mode| {
+        let before = current.remove(path);
+        if let Some(before) = &before {
+            let same_mode = before.mode.is_none_or(|old| old == mode);
+            if same_mode && before.sha == util::hash_bytes(&data) {
+                return Ok(());
+            }
+        }
+        let input = FileInput {
+            path: path.to_string(),
+            before: before
+                .as_ref()
+                .map(|file| storage.read_blob(&file.sha))
+                .transpose()?,
+            after: Some(data),
+            before_mode: before.and_then(|file| file.mode),
+            after_mode: Some(mode),
+        };
+        files.extend(build_file_artifact(input, diff)?);
+        Ok(())
+    })?;
+    // whatever the source did not list is gone from the baseline
+    for (path, file) in current {
+        let input = FileInput {
+            before: Some(storage.read_blob(&file.sha)?),
+            before_mode: file.mode,
+            path,
+            ..Default::default()
+        };
+        files.extend(build_file_artifact(input, diff)?);
+    }
+    files.sort_by(|a, b| a.record.path.cmp(&b.record.path));
+    artifacts.extend(files);
+    if artifacts.is_empty() {
+        lock.release();
+        return Ok(None);
+    }
+
+    let (meta, patch) = assemble_record(
+        &storage,
+        started_at,
+        Utc::now(),
+        &artifacts,
+        git::read_context(project_root),
+        Some(label),
+    )?;
+    storage.commit_record(&meta, &compress_patch(&patch)?, &artifacts)?;
+    storage.register_record(&meta)?;
+    tracing::debug!(record_id = %meta.record_id, files = meta.files.len(), %source, "rebaselined");
+    lock.release();
+    Ok(Some(meta))
+}
+
+/// Calls `f` with the relative path, content and mode of every file in
+/// `source` that the ignore rules let through.
+fn visit_source(
+    project_root: &Path,
+    ignore: &IgnoreMatcher,
+    source: &BaselineSource,
+    mut f: impl FnMut(&str, Vec<u8>, u32) -> Result<()>,
+) -> Result<()> {
+    match source {
+        BaselineSource::WorkingTree => {
+            let walker = WalkDir::new(project_root)
+                .min_depth(1)
+                .into_iter()
+                .filter_entry(|e| !ignore.is_ignored(e.path(), e.file_type().is_dir()));
+            for entry in walker.filter_map(|e| e.ok()) {
+                if entry.file_type().is_dir() {
+                    continue;
+                }
+                let Some(rel) = util::relative_path(project_root, entry.path()) else {
+                    continue;
+                };
+                if let Some((data, mode)) = util::read_worktree_entry(entry.path())? {
+                    f(&rel, data, mode)?;
+                }
+            }
+            Ok(())
+        }
+        BaselineSource::Git(rev) => {
+            git::for_each_blob(project_root, rev, |path, git_mode, data| {
+                let absolute = project_root.join(path);
+                if ignore.is_ignored(&absolute, false) {
+                    return Ok(());
+                }
+                let on_disk = util::worktree_mode(&absolute);
+                f(path, data, checkout_mode(git_mode, on_disk))
+            })
+        }
+    }
+}
+
+/// Maps a git mode to the mode the watcher would read from disk, keeping
+/// the file's actual permission bits when git agrees on its kind and
+/// executable bit. Otherwise a checkout of the file would show up as a
+/// mode change.
+fn checkout_mode(git_mode: u32, on_disk: Option<u32>) -> u32 {
+    let fallback = if util::is_symlink_mode(git_mode) {
+        util::MODE_SYMLINK | 0o777
+    } else {
+        git_mode
+    };
+    match on_disk {
+        Some(mode)
+            if mode & 0o170000 == fallback & 0o170000
+                && (mode & 0o111 != 0) == (fallback & 0o111 != 0) =>
+        {
+            mode
+        }
+        _ => fallback,
+    }
+}
+
+fn disk_directories(project_root: &Path, ignore: &IgnoreMatcher) -> Vec<String> {
+    WalkDir::new(project_root)
+        .min_depth(1)
+        .into_iter()
+        .filter_entry(|e| !ignore.is_ignored(e.path(), e.file_type().is_dir()))
+        .filter_map(|e| e.ok())
+        .filter(|entry| entry.file_type().is_dir())
+        .filter_map(|entry| util::relative_path(project_root, entry.path()))
+        .collect()
+}
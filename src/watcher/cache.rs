@@ -0,0 +1,143 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use lru::LruCache;
+use serde::Serialize;
+
+use crate::models::{FileOp, FileRecord};
+
+/// Latest committed content per path, so rapid saves of the same file do not
+/// re-read and decompress the previous blob every time.
+pub struct SnapshotCache {
+    inner: Mutex<CacheState>,
+    capacity_bytes: usize,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+#[derive(Clone)]
+pub struct CachedSnapshot {
+    pub sha: String,
+    pub mode: Option<u32>,
+    pub content: Arc<Vec<u8>>,
+}
+
+struct CacheState {
+    entries: LruCache<String, CachedSnapshot>,
+    bytes: usize,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub entries: usize,
+    pub bytes: usize,
+}
+
+impl SnapshotCache {
+    pub fn new(capacity_bytes: usize) -> Self {
+        Self {
+            inner: Mutex::new(CacheState {
+                entries: LruCache::unbounded(),
+                bytes: 0,
+            }),
+            capacity_bytes,
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    pub fn get(&self, path: &str) -> Option<CachedSnapshot> {
+        let mut state = self.inner.lock().unwrap();
+        match state.entries.get(path) {
+            Some(entry) => {
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                Some(entry.clone())
+            }
+            None => {
+                self.misses.fetch_add(1, Ordering::Relaxed);
+                None
+            }
+        }
+    }
+
+    pub fn insert(&self, path: &str, snapshot: CachedSnapshot) {
+        let size = snapshot.content.len();
+        let mut state = self.inner.lock().unwrap();
+        if let Some(old) = state.entries.pop(path) {
+            state.bytes -= old.content.len();
+        }
+        if size > self.capacity_bytes {
+            return;
+        }
+        state.bytes += size;
+        state.entries.put(path.to_string(), snapshot);
+        while state.bytes > self.capacity_bytes {
+            match state.entries.pop_lru() {
+                Some((_, evicted)) => state.bytes -= evicted.content.len(),
+                None => break,
+            }
+        }
+    }
+
+    pub fn remove(&self, path: &str) {
+        let mut state = self.inner.lock().unwrap();
+        if let Some(old) = state.entries.pop(path) {
+            state.bytes -= old.content.len();
+        }
+    }
+
+    pub fn remove_prefix(&self, dir: &str) {
+        let prefix = format!("{dir}/");
+        let mut state = self.inner.lock().unwrap();
+        let doomed: Vec<String> = state
+            .entries
+            .iter()
+            .filter(|(path, _)| path.starts_with(&prefix))
+            .map(|(path, _)| path.clone())
+            .collect();
+        for path in doomed {
+            if let Some(old) = state.entries.pop(&path) {
+                state.bytes -= old.content.len();
+            }
+        }
+    }
+
+    pub fn clear(&self) {
+        let mut state = self.inner.lock().unwrap();
+        state.entries.clear();
+        state.bytes = 0;
+    }
+
+    /// Mirrors a committed record so the cache never disagrees with
+    /// `latest_snapshots`. Must only be called after the commit succeeded.
+    pub fn apply_committed(&self, file: &FileRecord, after_blob: Option<Vec<u8>>) {
+        match file.op {
+            FileOp::Added | FileOp::Modified => match (&file.after_sha, after_blob) {
+                (Some(sha), Some(content)) => self.insert(
+                    &file.path,
+                    CachedSnapshot {
+                        sha: sha.clone(),
+                        mode: file.after_mode,
+                        content: Arc::new(content),
+                    },
+                ),
+                _ => self.remove(&file.path),
+            },
+            FileOp::Deleted => self.remove(&file.path),
+            FileOp::DirRemoved => self.remove_prefix(&file.path),
+            FileOp::DirCreated => {}
+        }
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        let state = self.inner.lock().unwrap();
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            entries: state.entries.len(),
+            bytes: state.bytes,
+        }
+    }
+}
@@ -0,0 +1,160 @@
+use std::fs::{self, File};
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::util;
+
+const CONTROL_FILENAME: &str = "control.json";
+const CONTROL_LOCK_FILENAME: &str = "control.lock";
+
+/// Runtime switches the CLI hands to a running watcher: pause/resume and
+/// muted globs. Lives next to `watch.lock` and is re-read by the watcher.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ControlState {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub paused_at: Option<DateTime<Utc>>,
+    /// Pause expires on its own at this time; `None` pauses until `resume`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub paused_until: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub muted: Vec<String>,
+}
+
+impl ControlState {
+    pub fn path(meta_dir: &Path) -> PathBuf {
+        meta_dir.join(CONTROL_FILENAME)
+    }
+
+    pub fn load(meta_dir: &Path) -> Result<Self> {
+        let path = Self::path(meta_dir);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let contents = fs::read_to_string(&path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        serde_json::from_str(&contents)
+            .with_context(|| format!("failed to parse {}", path.display()))
+    }
+
+    pub fn save(&self, meta_dir: &Path) -> Result<()> {
+        util::ensure_dir(meta_dir)?;
+        let path = Self::path(meta_dir);
+        // the watcher must never read a half-written file
+        util::write_atomic(&path, &serde_json::to_vec_pretty(self)?)
+    }
+
+    /// Loads, changes and saves the state while holding an advisory lock,
+    /// so the CLI and the watcher never overwrite each other's changes.
+    pub fn update<T>(
+        meta_dir: &Path,
+        change: impl FnOnce(&mut Self) -> Result<T>,
+    ) -> Result<(Self, T)> {
+        util::ensure_dir(meta_dir)?;
+        let lock_path = meta_dir.join(CONTROL_LOCK_FILENAME);
+        let lock = File::create(&lock_path)
+            .with_context(|| format!("failed to open {}", lock_path.display()))?;
+        // released when `lock` is closed
+        if unsafe { libc::flock(lock.as_raw_fd(), libc::LOCK_EX) } != 0 {
+            return Err(std::io::Error::last_os_error())
+                .with_context(|| format!("failed to lock {}", lock_path.display()));
+        }
+        let mut state = Self::load(meta_dir)?;
+        let result = change(&mut state)?;
+        state.save(meta_dir)?;
+        Ok((state, result))
+    }
+
+    pub fn is_paused(&self, now: DateTime<Utc>) -> bool {
+        match (self.paused_at, self.paused_until) {
+            (Some(_), Some(until)) => now < until,
+            (Some(_), None) => true,
+            (None, _) => false,
+        }
+    }
+
+    pub fn pause(&mut self, now: DateTime<Utc>, until: Option<DateTime<Utc>>) {
+        if !self.is_paused(now) {
+            self.paused_at = Some(now);
+        }
+        self.paused_until = until;
+    }
+
+    pub fn resume(&mut self) {
+        self.paused_at = None;
+        self.paused_until = None;
+    }
+}
+
+/// The watcher's view of [`ControlState`], turning file changes into
+/// pause/resume transitions.
+pub struct ControlWatch {
+    meta_dir: PathBuf,
+    state: ControlState,
+    paused_since: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Default)]
+pub struct ControlChange {
+    pub paused: bool,
+    /// Set when recording resumes; changes since then need a catch-up diff.
+    pub resumed_from: Option<DateTime<Utc>>,
+    pub muted: Option<Vec<String>>,
+}
+
+impl ControlWatch {
+    pub fn new(meta_dir: &Path) -> Result<Self> {
+        let state = ControlState::load(meta_dir)?;
+        let now = util::now_utc();
+        let paused_since = state.is_paused(now).then(|| state.paused_at.unwrap_or(now));
+        Ok(Self {
+            meta_dir: meta_dir.to_path_buf(),
+            state,
+            paused_since,
+        })
+    }
+
+    pub fn state(&self) -> &ControlState {
+        &self.state
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused_since.is_some()
+    }
+
+    pub fn refresh(&mut self) -> Result<ControlChange> {
+        let mut next = ControlState::load(&self.meta_dir)?;
+        let now = util::now_utc();
+        if next.paused_at.is_some() && !next.is_paused(now) {
+            // a timed pause ran out; clear it so `status` agrees, unless
+            // someone paused again meanwhile
+            next = ControlState::update(&self.meta_dir, |state| {
+                if !state.is_paused(now) {
+                    state.resume();
+                }
+                Ok(())
+            })?
+            .0;
+        }
+        let mut change = ControlChange::default();
+        match (self.paused_since, next.is_paused(now)) {
+            (None, true) => {
+                self.paused_since = Some(next.paused_at.unwrap_or(now));
+                change.paused = true;
+            }
+            (Some(since), false) => {
+                self.paused_since = None;
+                change.resumed_from = Some(since);
+            }
+            _ => {}
+        }
+        if next.muted != self.state.muted {
+            change.muted = Some(next.muted.clone());
+        }
+        self.state = next;
+        Ok(change)
+    }
+}
@@ -6,9 +6,11 @@ use anyhow::{bail, Context, Result};
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
-use crate::util;
+use super::rpc::SOCKET_FILENAME;
+use crate::{runtime, util};
 
 const LOCK_FILENAME: &str = "watch.lock";
+const SUPERVISOR_ID: &str = "daemon";
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LockInfo {
@@ -17,6 +19,14 @@ pub struct LockInfo {
     #[serde(with = "chrono::serde::ts_seconds")]
     pub started_at: DateTime<Utc>,
     pub tool_version: String,
+    /// Control socket the watcher serves JSON-RPC on.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub socket: Option<PathBuf>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub supervised: bool,
+    /// systemd user unit the process was started from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub service: Option<String>,
 }
 
 pub struct WatchLock {
@@ -25,12 +35,44 @@ pub struct WatchLock {
 }
 
 impl WatchLock {
-    pub fn acquire(meta_dir: &Path, project_id: &str) -> Result<Self> {
-        util::ensure_dir(meta_dir)?;
-        let path = meta_dir.join(LOCK_FILENAME);
+    /// `supervised` marks a watcher run inside `meowdiff daemon`, whose pid
+    /// is shared with every other project in that process.
+    pub fn acquire(meta_dir: &Path, project_id: &str, supervised: bool) -> Result<Self> {
+        let info = LockInfo {
+            project_id: project_id.to_string(),
+            pid: std::process::id() as i32,
+            started_at: Utc::now(),
+            tool_version: util::tool_version(),
+            socket: cfg!(unix).then(|| meta_dir.join(SOCKET_FILENAME)),
+            supervised,
+            service: runtime::current_service(),
+        };
+        Self::acquire_with(meta_dir, info)
+    }
+
+    /// Lock held by the `meowdiff daemon` process itself.
+    pub fn acquire_supervisor(dir: &Path) -> Result<Self> {
+        let info = LockInfo {
+            project_id: SUPERVISOR_ID.to_string(),
+            pid: std::process::id() as i32,
+            started_at: Utc::now(),
+            tool_version: util::tool_version(),
+            socket: None,
+            supervised: false,
+            service: runtime::current_service(),
+        };
+        Self::acquire_with(dir, info)
+    }
+
+    fn acquire_with(dir: &Path, info: LockInfo) -> Result<Self> {
+        util::ensure_dir(dir)?;
+        let path = dir.join(LOCK_FILENAME);
         if path.exists() {
             if let Some(existing) = read_lock_file(&path)? {
                 if is_process_alive(existing.pid) {
+                    if info.project_id == SUPERVISOR_ID {
+                        bail!("meowdiff daemon already running (pid {})", existing.pid);
+                    }
                     bail!(
                         "watch already running for project {} (pid {})",
                         existing.project_id,
@@ -44,12 +86,6 @@ impl WatchLock {
                 fs::remove_file(&path).ok();
             }
         }
-        let info = LockInfo {
-            project_id: project_id.to_string(),
-            pid: std::process::id() as i32,
-            started_at: Utc::now(),
-            tool_version: util::tool_version(),
-        };
         write_lock_file(&path, &info)?;
         Ok(Self { path, active: true })
     }
@@ -97,12 +133,7 @@ pub fn send_terminate(pid: i32) -> Result<()> {
     Ok(())
 }
 
-pub fn is_process_alive(pid: i32) -> bool {
-    if pid <= 0 {
-        return false;
-    }
-    unsafe { libc::kill(pid, 0) == 0 }
-}
+pub use crate::util::is_process_alive;
 
 fn read_lock_file(path: &Path) -> Result<Option<LockInfo>> {
     if !path.exists() {
@@ -1,46 +1,300 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
 use std::time::Duration;
 
 use chrono::{DateTime, Utc};
-use notify::Event;
+use notify::{Event, EventKind};
+use serde::Serialize;
 use tokio::sync::mpsc::Receiver;
-use tokio::time::{sleep, Instant};
+use tokio::time::{sleep_until, Instant};
 
+use crate::git;
+use crate::ignore::is_vim_probe;
+use crate::models::GitContext;
 use crate::util;
 
 pub struct Batch {
     pub events: Vec<Event>,
     pub started_at: DateTime<Utc>,
     pub ended_at: DateTime<Utc>,
+    pub closed_by: CloseReason,
+    /// Git branch and HEAD as they were when the batch opened.
+    pub git: Option<GitContext>,
 }
 
-pub async fn next_batch(rx: &mut Receiver<Event>, window: Duration) -> Option<Batch> {
-    let first_event = rx.recv().await?;
-    let mut events = vec![first_event];
-    let started_at = util::now_utc();
-    let deadline = sleep(window);
-    tokio::pin!(deadline);
-    loop {
-        tokio::select! {
-            _ = &mut deadline => {
-                break;
+impl Batch {
+    /// Folds a later batch into this one, keeping the earlier start time
+    /// and git context.
+    pub fn merge(&mut self, later: Batch) {
+        self.git = self.git.take().or(later.git);
+        self.events.extend(later.events);
+        self.ended_at = later.ended_at;
+        self.closed_by = later.closed_by;
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BatchPolicy {
+    /// Idle time after the last event before a batch closes.
+    pub window: Duration,
+    /// Hard cap on how long a batch may stay open, regardless of activity.
+    pub max_duration: Option<Duration>,
+    pub max_events: Option<usize>,
+    /// Distinct paths per batch.
+    pub max_files: Option<usize>,
+    /// Close the batch after every write, so repeated saves of one file
+    /// become separate records.
+    pub per_write: bool,
+}
+
+impl Default for BatchPolicy {
+    fn default() -> Self {
+        Self {
+            window: Duration::from_millis(50),
+            max_duration: Some(Duration::from_secs(5)),
+            max_events: Some(10_000),
+            max_files: None,
+            per_write: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CloseReason {
+    Idle,
+    MaxDuration,
+    MaxEvents,
+    MaxFiles,
+    /// Closed after a single write under a per-write policy.
+    Write,
+    ChannelClosed,
+    /// Synthetic batch rescanning the tree after a pause.
+    CatchUp,
+    /// Closed early on request over the control socket.
+    Flush,
+}
+
+#[derive(Debug, Default)]
+pub struct BatchCounters {
+    idle: AtomicU64,
+    max_duration: AtomicU64,
+    max_events: AtomicU64,
+    max_files: AtomicU64,
+    write: AtomicU64,
+    channel_closed: AtomicU64,
+    catch_up: AtomicU64,
+    flush: AtomicU64,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct BatchCounterSnapshot {
+    pub idle: u64,
+    pub max_duration: u64,
+    pub max_events: u64,
+    pub max_files: u64,
+    pub write: u64,
+    pub channel_closed: u64,
+    pub catch_up: u64,
+    pub flush: u64,
+}
+
+impl BatchCounters {
+    pub fn record(&self, reason: CloseReason) {
+        let counter = match reason {
+            CloseReason::Idle => &self.idle,
+            CloseReason::MaxDuration => &self.max_duration,
+            CloseReason::MaxEvents => &self.max_events,
+            CloseReason::MaxFiles => &self.max_files,
+            CloseReason::Write => &self.write,
+            CloseReason::ChannelClosed => &self.channel_closed,
+            CloseReason::CatchUp => &self.catch_up,
+            CloseReason::Flush => &self.flush,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> BatchCounterSnapshot {
+        BatchCounterSnapshot {
+            idle: self.idle.load(Ordering::Relaxed),
+            max_duration: self.max_duration.load(Ordering::Relaxed),
+            max_events: self.max_events.load(Ordering::Relaxed),
+            max_files: self.max_files.load(Ordering::Relaxed),
+            write: self.write.load(Ordering::Relaxed),
+            channel_closed: self.channel_closed.load(Ordering::Relaxed),
+            catch_up: self.catch_up.load(Ordering::Relaxed),
+            flush: self.flush.load(Ordering::Relaxed),
+        }
+    }
+}
+
+struct OpenBatch {
+    events: Vec<Event>,
+    paths: HashSet<PathBuf>,
+    started_at: DateTime<Utc>,
+    git: Option<GitContext>,
+    opened: Instant,
+    last_event: Instant,
+}
+
+/// Groups raw events into batches according to a [`BatchPolicy`].
+///
+/// The open batch lives on the struct rather than in the future, so
+/// `next_batch` can be cancelled by a `select!` without losing events.
+pub struct Batcher {
+    rx: Receiver<Event>,
+    policy: BatchPolicy,
+    counters: Arc<BatchCounters>,
+    current: Option<OpenBatch>,
+    carry: Option<Event>,
+    /// Checkout whose git context is read as each batch opens.
+    git_root: Option<PathBuf>,
+}
+
+impl Batcher {
+    pub fn new(rx: Receiver<Event>, policy: BatchPolicy, counters: Arc<BatchCounters>) -> Self {
+        Self {
+            rx,
+            policy,
+            counters,
+            current: None,
+            carry: None,
+            git_root: None,
+        }
+    }
+
+    /// Tags each batch with the git context of `project_root` at the time
+    /// the batch opens, before a later checkout can change it.
+    pub fn with_git_context(mut self, project_root: &Path) -> Self {
+        self.git_root = Some(project_root.to_path_buf());
+        self
+    }
+
+    pub async fn next_batch(&mut self) -> Option<Batch> {
+        loop {
+            if self.current.is_none() {
+                let event = match self.carry.take() {
+                    Some(event) => event,
+                    None => self.rx.recv().await?,
+                };
+                self.open();
+                if let Some(reason) = self.push(event) {
+                    return Some(self.close(reason));
+                }
             }
-            maybe_event = rx.recv() => {
-                match maybe_event {
-                    Some(event) => {
-                        events.push(event);
-                        let next = Instant::now() + window;
-                        deadline.as_mut().reset(next);
-                    }
-                    None => {
-                        break;
+
+            let (idle_deadline, hard_deadline) = {
+                let open = self.current.as_ref().expect("batch is open");
+                (
+                    open.last_event + self.policy.window,
+                    self.policy.max_duration.map(|max| open.opened + max),
+                )
+            };
+            let (deadline, reason) = match hard_deadline {
+                Some(hard) if hard <= idle_deadline => (hard, CloseReason::MaxDuration),
+                _ => (idle_deadline, CloseReason::Idle),
+            };
+
+            tokio::select! {
+                _ = sleep_until(deadline) => {
+                    return Some(self.close(reason));
+                }
+                maybe_event = self.rx.recv() => {
+                    match maybe_event {
+                        Some(event) => {
+                            if let Some(reason) = self.push(event) {
+                                return Some(self.close(reason));
+                            }
+                        }
+                        None => return Some(self.close(CloseReason::ChannelClosed)),
                     }
                 }
             }
         }
     }
-    Some(Batch {
-        events,
-        started_at,
-        ended_at: util::now_utc(),
-    })
+
+    /// Closes the open batch now, including events already queued on the
+    /// channel, regardless of the policy's limits.
+    pub fn flush(&mut self) -> Option<Batch> {
+        let pending: Vec<Event> = self
+            .carry
+            .take()
+            .into_iter()
+            .chain(std::iter::from_fn(|| self.rx.try_recv().ok()))
+            .collect();
+        if self.current.is_none() && pending.is_empty() {
+            return None;
+        }
+        if self.current.is_none() {
+            self.open();
+        }
+        let open = self.current.as_mut().expect("batch is open");
+        for event in pending {
+            open.paths.extend(event.paths.iter().cloned());
+            open.events.push(event);
+        }
+        Some(self.close(CloseReason::Flush))
+    }
+
+    fn open(&mut self) {
+        let now = Instant::now();
+        self.current = Some(OpenBatch {
+            events: Vec::new(),
+            paths: HashSet::new(),
+            started_at: util::now_utc(),
+            git: self.git_root.as_deref().and_then(git::read_context),
+            opened: now,
+            last_event: now,
+        });
+    }
+
+    /// Adds an event to the open batch, returning a reason when the batch
+    /// must close. An event that would exceed the file limit is carried over
+    /// to start the next batch.
+    fn push(&mut self, event: Event) -> Option<CloseReason> {
+        let open = self.current.as_mut().expect("batch is open");
+        if let Some(max_files) = self.policy.max_files {
+            let new_paths = event
+                .paths
+                .iter()
+                .filter(|p| !open.paths.contains(*p))
+                .count();
+            if !open.events.is_empty() && open.paths.len() + new_paths > max_files {
+                self.carry = Some(event);
+                return Some(CloseReason::MaxFiles);
+            }
+        }
+        // a vim probe is deleted right after it is created; keep the two
+        // together so the pair can be dropped
+        let is_write = match event.kind {
+            EventKind::Access(_) => false,
+            EventKind::Create(_) => !event.paths.iter().any(|path| is_vim_probe(path)),
+            _ => true,
+        };
+        open.paths.extend(event.paths.iter().cloned());
+        open.events.push(event);
+        open.last_event = Instant::now();
+        if self.policy.per_write && is_write {
+            return Some(CloseReason::Write);
+        }
+        match self.policy.max_events {
+            Some(max) if open.events.len() >= max => Some(CloseReason::MaxEvents),
+            _ => None,
+        }
+    }
+
+    fn close(&mut self, reason: CloseReason) -> Batch {
+        let open = self.current.take().expect("batch is open");
+        self.counters.record(reason);
+        tracing::debug!(events = open.events.len(), reason = ?reason, "batch closed");
+        Batch {
+            events: open.events,
+            started_at: open.started_at,
+            ended_at: util::now_utc(),
+            closed_by: reason,
+            git: open.git,
+        }
+    }
 }
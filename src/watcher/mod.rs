@@ -1,43 +1,117 @@
+mod baseline;
+mod cache;
+mod control;
 mod lock;
 mod microbatch;
+mod rpc;
+mod settings;
+mod supervisor;
+pub use baseline::{rebaseline, BaselineSource};
+pub use cache::{CacheStats, CachedSnapshot, SnapshotCache};
+pub use control::{ControlChange, ControlState, ControlWatch};
 pub use lock::{is_process_alive, send_terminate, LockInfo, WatchLock};
-pub use microbatch::Batch;
+pub use microbatch::{
+    Batch, BatchCounterSnapshot, BatchCounters, BatchPolicy, Batcher, CloseReason,
+};
+#[cfg(unix)]
+pub use rpc::{call as rpc_call, subscribe as rpc_subscribe, Subscription};
+pub use rpc::{RecordEvent, RpcError, RpcRequest, SOCKET_FILENAME};
+pub use settings::WatchSettings;
+pub use supervisor::{supervise, supervisor_dir, SupervisorOptions};
 
-use std::collections::BTreeSet;
+use std::collections::{BTreeSet, HashSet};
 use std::fs;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use anyhow::{Context, Result};
 use blake3::Hasher;
 use chrono::{DateTime, Utc};
-use notify::{recommended_watcher, Event, RecommendedWatcher, RecursiveMode, Watcher};
+use notify::{recommended_watcher, Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use rayon::prelude::*;
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use serde_json::{json, Value};
 #[cfg(unix)]
 use tokio::signal::unix::{signal, SignalKind};
-use tokio::sync::mpsc;
+use tokio::sync::{broadcast, mpsc, oneshot, Notify};
+use tokio::task::JoinHandle;
 use walkdir::WalkDir;
 
-use crate::ignore::IgnoreMatcher;
-use crate::models::{FileRecord, RecordMeta};
+use crate::git;
+use crate::ignore::{editor_save_target, is_vim_probe, IgnoreMatcher};
+use crate::models::{FileOp, FileRecord, GitContext, RecordMeta, SnapshotStamp};
 use crate::pipeline::{
-    aggregate_stats, build_file_artifact, compress_patch, FileArtifact, FileInput,
+    aggregate_stats, build_file_artifact, compress_patch, directory_artifact, DiffOptions,
+    FileArtifact, FileInput,
 };
-use crate::storage::StorageEngine;
+use crate::runtime::ReadySignal;
+use crate::storage::{StorageEngine, WatcherStatus};
 use crate::util::{self, colorize_patch};
 
-const DEFAULT_WINDOW_MS: u64 = 50;
+const DEFAULT_CACHE_BYTES: usize = 64 * 1024 * 1024;
+const ATOMIC_SAVE_SETTLE: Duration = Duration::from_millis(200);
+/// Records a slow subscriber may fall behind before it is told it lagged.
+const RECORD_BROADCAST_CAPACITY: usize = 256;
+/// How often the loop re-reads control state and checks for git rewrites.
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
 
 pub struct WatchOptions {
     pub project_root: PathBuf,
-    pub window: Duration,
+    pub batch: BatchPolicy,
+    pub diff: DiffOptions,
+    /// Worker threads used to diff a batch; 0 picks one per CPU.
+    pub jobs: usize,
+    /// Upper bound for decoded snapshot contents kept in memory.
+    pub cache_bytes: usize,
+    pub git: GitOptions,
+    /// Reports successful startup to a parent waiting in `watch --daemon`.
+    pub ready: ReadySignal,
+    /// Stops the watcher when notified; process signals are used when unset.
+    pub stop: Option<Arc<Notify>>,
+    /// Set when running as one of many projects inside `meowdiff daemon`.
+    pub supervised: bool,
+    /// Worker pool shared with other watchers; one is built from `jobs` when unset.
+    pub pool: Option<Arc<ThreadPool>>,
+    /// Print each record's patch to stdout.
+    pub echo: bool,
+    /// Contents a store without snapshots starts from.
+    pub baseline: BaselineSource,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct GitOptions {
+    /// Attach branch, HEAD and in-progress operation to every record.
+    pub tag_records: bool,
+    /// Hold batches while git rewrites the tree (checkout, rebase, reset)
+    /// and record the whole rewrite as one batch once it settles.
+    pub collapse_rewrites: bool,
+}
+
+impl Default for GitOptions {
+    fn default() -> Self {
+        Self {
+            tag_records: true,
+            collapse_rewrites: false,
+        }
+    }
 }
 
 impl Default for WatchOptions {
     fn default() -> Self {
         Self {
             project_root: std::env::current_dir().unwrap_or_else(|_| PathBuf::from(".")),
-            window: Duration::from_millis(DEFAULT_WINDOW_MS),
+            batch: BatchPolicy::default(),
+            diff: DiffOptions::default(),
+            jobs: 0,
+            cache_bytes: DEFAULT_CACHE_BYTES,
+            git: GitOptions::default(),
+            ready: ReadySignal::default(),
+            stop: None,
+            supervised: false,
+            pool: None,
+            echo: true,
+            baseline: BaselineSource::default(),
         }
     }
 }
@@ -48,14 +122,34 @@ pub async fn watch(options: WatchOptions) -> Result<()> {
     let ignore = Arc::new(IgnoreMatcher::new(&project_root)?);
 
     let meta_dir = storage.paths().meta_dir.clone();
-    let lock = WatchLock::acquire(&meta_dir, storage.project_id())?;
+    let lock = WatchLock::acquire(&meta_dir, storage.project_id(), options.supervised)?;
+    let registered = RegisteredWatcher::new(
+        storage.clone(),
+        if options.supervised {
+            WatcherStatus::Supervised
+        } else {
+            WatcherStatus::Running
+        },
+    )?;
 
     if !storage.has_snapshots()? {
         tracing::info!("priming baseline snapshots");
-        prime_baseline(&project_root, &storage, &ignore)?;
+        baseline::prime_baseline(&project_root, &storage, &ignore, &options.baseline)?;
+    } else if options.baseline != BaselineSource::WorkingTree {
+        tracing::warn!(
+            "store already has a baseline; ignoring --baseline-from (use `meowdiff rebaseline --from-git` to reset it)"
+        );
+    } else if !storage.directories_primed()? {
+        // stores created before directory tracking only know about files
+        baseline::prime_directories(&project_root, &storage, &ignore)?;
     }
 
-    let (tx, mut rx) = mpsc::channel::<Event>(1024);
+    let (tx, rx) = mpsc::channel::<Event>(1024);
+    let counters = Arc::new(BatchCounters::default());
+    let mut batcher = Batcher::new(rx, options.batch, counters.clone());
+    if options.git.tag_records {
+        batcher = batcher.with_git_context(&project_root);
+    }
     let mut watcher = create_watcher(tx)?;
     watcher
         .watch(&project_root, RecursiveMode::Recursive)
@@ -67,59 +161,518 @@ pub async fn watch(options: WatchOptions) -> Result<()> {
         "watcher started"
     );
 
+    let control = ControlWatch::new(&meta_dir)?;
+    if control.is_paused() {
+        tracing::info!("recording is paused; run `meowdiff resume` to continue");
+    }
+    let (records, _) = broadcast::channel(RECORD_BROADCAST_CAPACITY);
+    let ctx = BatchContext {
+        project_root: project_root.clone(),
+        storage: storage.clone(),
+        ignore: Arc::new(ignore.with_muted(&control.state().muted)?),
+        diff_options: Arc::new(options.diff),
+        pool: match options.pool {
+            Some(pool) => pool,
+            None => Arc::new(build_worker_pool(options.jobs)?),
+        },
+        cache: Arc::new(SnapshotCache::new(options.cache_bytes)),
+        tag_git: options.git.tag_records,
+        label: None,
+        records: records.clone(),
+        echo: options.echo,
+    };
+
+    let (rpc_tx, mut rpc_rx) = mpsc::channel::<RpcRequest>(64);
     #[cfg(unix)]
-    {
-        let mut ctrl_c = Box::pin(tokio::signal::ctrl_c());
-        let mut sigterm_stream =
-            signal(SignalKind::terminate()).context("failed to listen for SIGTERM")?;
-        loop {
-            tokio::select! {
-                _ = &mut ctrl_c => {
-                    tracing::info!("SIGINT received, shutting down watcher");
+    let socket = {
+        let path = meta_dir.join(rpc::SOCKET_FILENAME);
+        let listener = rpc::bind(&path)?;
+        tokio::spawn(rpc::serve(listener, rpc_tx, records));
+        path
+    };
+    #[cfg(not(unix))]
+    drop((rpc_tx, records));
+
+    let mut state = WatchLoop {
+        project_root,
+        meta_dir,
+        base_ignore: ignore,
+        ctx,
+        control,
+        batcher,
+        counters,
+        git: options.git,
+        in_flight: None,
+        deferred: None,
+        catch_up_from: None,
+        stop_requested: false,
+    };
+
+    options.ready.ready();
+
+    // batches are processed off the runtime so signals stay responsive, but
+    // one at a time so records keep their order
+    let stop = options.stop;
+    let mut shutdown = Box::pin(async move {
+        match stop {
+            Some(stop) => {
+                stop.notified().await;
+                "stop request"
+            }
+            None => shutdown_signal().await,
+        }
+    });
+    let mut poll = tokio::time::interval(POLL_INTERVAL);
+    loop {
+        tokio::select! {
+            reason = &mut shutdown => {
+                tracing::info!("{reason} received, shutting down watcher");
+                break;
+            }
+            result = join_in_flight(&mut state.in_flight), if state.in_flight.is_some() => {
+                state.in_flight = None;
+                log_batch_result(result);
+            }
+            _ = poll.tick() => {
+                state.refresh_control();
+                state.start_pending();
+            }
+            Some(request) = rpc_rx.recv() => {
+                state.handle_request(request);
+                if state.stop_requested {
+                    tracing::info!("shutdown requested over control socket");
                     break;
                 }
-                _ = sigterm_stream.recv() => {
-                    tracing::info!("SIGTERM received, shutting down watcher");
-                    break;
+            }
+            batch = state.batcher.next_batch(), if state.in_flight.is_none() => {
+                match batch {
+                    Some(batch) => state.on_batch(batch),
+                    None => break,
                 }
-                batch = microbatch::next_batch(&mut rx, options.window) => {
-                    match batch {
-                        Some(batch) => {
-                            if let Err(err) = process_batch(batch, project_root.clone(), storage.clone(), ignore.clone()) {
-                                tracing::error!(error = %err, "failed to process batch");
-                            }
-                        }
-                        None => break,
-                    }
+            }
+        }
+    }
+    state.finish().await;
+    drop(registered);
+    #[cfg(unix)]
+    fs::remove_file(&socket).ok();
+    lock.release();
+    Ok(())
+}
+
+/// Keeps the registry's watcher state in step with this process: set on
+/// creation and reset to stopped however `watch` returns.
+struct RegisteredWatcher {
+    storage: Arc<StorageEngine>,
+}
+
+impl RegisteredWatcher {
+    fn new(storage: Arc<StorageEngine>, state: WatcherStatus) -> Result<Self> {
+        let registered = Self { storage };
+        registered.storage.register_watcher(state)?;
+        Ok(registered)
+    }
+}
+
+impl Drop for RegisteredWatcher {
+    fn drop(&mut self) {
+        if let Err(err) = self.storage.register_watcher(WatcherStatus::Stopped) {
+            tracing::warn!(error = %err, "failed to update registry");
+        }
+    }
+}
+
+/// Mutable state of a running watcher, shared by the event loop and
+/// requests arriving on the control socket.
+struct WatchLoop {
+    project_root: PathBuf,
+    meta_dir: PathBuf,
+    /// Ignore rules before mutes are applied; `ctx.ignore` adds them.
+    base_ignore: Arc<IgnoreMatcher>,
+    ctx: BatchContext,
+    control: ControlWatch,
+    batcher: Batcher,
+    counters: Arc<BatchCounters>,
+    git: GitOptions,
+    in_flight: Option<JoinHandle<Result<()>>>,
+    /// Batches held back while git rewrites the tree, see [`GitOptions`].
+    deferred: Option<Batch>,
+    catch_up_from: Option<DateTime<Utc>>,
+    stop_requested: bool,
+}
+
+impl WatchLoop {
+    fn on_batch(&mut self, batch: Batch) {
+        if self.control.is_paused() {
+            tracing::debug!("recording paused, dropping batch");
+            return;
+        }
+        if self.git.collapse_rewrites
+            && (self.deferred.is_some() || git::is_rewriting(&self.project_root))
+        {
+            match self.deferred.as_mut() {
+                Some(held) => held.merge(batch),
+                None => {
+                    tracing::info!("git is rewriting the tree, holding changes");
+                    self.deferred = Some(batch);
                 }
             }
+        } else {
+            self.in_flight = Some(spawn_batch(batch, &self.ctx));
         }
     }
 
-    #[cfg(not(unix))]
-    {
-        let mut ctrl_c = Box::pin(tokio::signal::ctrl_c());
-        loop {
-            tokio::select! {
-                _ = &mut ctrl_c => {
-                    tracing::info!("SIGINT received, shutting down watcher");
-                    break;
+    fn refresh_control(&mut self) {
+        let change = match self.control.refresh() {
+            Ok(change) => change,
+            Err(err) => {
+                tracing::warn!(error = %err, "failed to read control state");
+                return;
+            }
+        };
+        if change.paused {
+            tracing::info!("recording paused");
+        }
+        if let Some(since) = change.resumed_from {
+            tracing::info!(%since, "recording resumed, scanning for changes made while paused");
+            self.catch_up_from = Some(self.catch_up_from.map_or(since, |from| from.min(since)));
+        }
+        if let Some(muted) = change.muted {
+            match self.base_ignore.with_muted(&muted) {
+                Ok(matcher) => {
+                    tracing::info!(?muted, "updated muted paths");
+                    self.ctx.ignore = Arc::new(matcher);
                 }
-                batch = microbatch::next_batch(&mut rx, options.window) => {
-                    match batch {
-                        Some(batch) => {
-                            if let Err(err) = process_batch(batch, project_root.clone(), storage.clone(), ignore.clone()) {
-                                tracing::error!(error = %err, "failed to process batch");
-                            }
-                        }
-                        None => break,
+                Err(err) => tracing::warn!(error = %err, "ignoring invalid mute patterns"),
+            }
+        }
+    }
+
+    /// Starts a pending catch-up scan or held git batch once the worker is free.
+    fn start_pending(&mut self) {
+        if self.in_flight.is_some() || self.control.is_paused() {
+            return;
+        }
+        if let Some(since) = self.catch_up_from.take() {
+            self.counters.record(CloseReason::CatchUp);
+            self.in_flight = Some(spawn_catch_up(since, &self.ctx));
+        } else if self.deferred.is_some() && !git::is_rewriting(&self.project_root) {
+            let batch = self.deferred.take().expect("deferred batch");
+            tracing::info!(
+                events = batch.events.len(),
+                "git rewrite finished, recording held changes"
+            );
+            self.in_flight = Some(spawn_batch(batch, &self.ctx));
+        }
+    }
+
+    fn handle_request(&mut self, request: RpcRequest) {
+        if request.method == "flush" {
+            // answered by the flush task once it commits
+            self.flush(request.reply);
+            return;
+        }
+        let result = self.answer(&request.method, request.params);
+        let _ = request.reply.send(result);
+    }
+
+    fn answer(&mut self, method: &str, params: Value) -> Result<Value, RpcError> {
+        match method {
+            "status" => self.status(),
+            "shutdown" => {
+                self.stop_requested = true;
+                Ok(json!({ "stopping": true }))
+            }
+            "pause" => {
+                let seconds = match params.get("seconds") {
+                    None | Some(Value::Null) => None,
+                    Some(value) => Some(value.as_u64().ok_or_else(|| {
+                        RpcError::invalid_params("seconds must be a non-negative integer")
+                    })?),
+                };
+                let now = util::now_utc();
+                let until = seconds
+                    .map(|secs| {
+                        i64::try_from(secs)
+                            .ok()
+                            .and_then(chrono::TimeDelta::try_seconds)
+                            .and_then(|delta| now.checked_add_signed(delta))
+                            .ok_or_else(|| RpcError::invalid_params("seconds is too large"))
+                    })
+                    .transpose()?;
+                ControlState::update(&self.meta_dir, |control| {
+                    control.pause(now, until);
+                    Ok(())
+                })?;
+                self.refresh_control();
+                self.status()
+            }
+            "resume" => {
+                ControlState::update(&self.meta_dir, |control| {
+                    control.resume();
+                    Ok(())
+                })?;
+                self.refresh_control();
+                self.start_pending();
+                self.status()
+            }
+            "reload" => {
+                self.base_ignore = Arc::new(IgnoreMatcher::new(&self.project_root)?);
+                self.refresh_control();
+                self.ctx.ignore =
+                    Arc::new(self.base_ignore.with_muted(&self.control.state().muted)?);
+                tracing::info!("reloaded ignore rules and control state");
+                self.status()
+            }
+            "label" => {
+                let text = match params.get("text") {
+                    None | Some(Value::Null) => None,
+                    Some(Value::String(text)) => Some(text.clone()),
+                    Some(_) => return Err(RpcError::invalid_params("text must be a string")),
+                };
+                match params.get("record_id").and_then(Value::as_str) {
+                    Some(record_id) => {
+                        self.ctx.storage.set_label(record_id, text.as_deref())?;
+                        Ok(json!({ "record_id": record_id, "label": text }))
+                    }
+                    None => {
+                        // applies to records committed from now on
+                        self.ctx.label = text.clone();
+                        Ok(json!({ "label": text }))
                     }
                 }
             }
+            _ => Err(RpcError::new(
+                rpc::METHOD_NOT_FOUND,
+                format!("unknown method {method}"),
+            )),
         }
     }
-    lock.release();
-    Ok(())
+
+    fn status(&self) -> Result<Value, RpcError> {
+        let control = self.control.state();
+        let cache = self.ctx.cache.stats();
+        Ok(json!({
+            "project_id": self.ctx.storage.project_id(),
+            "root": self.project_root,
+            "pid": std::process::id(),
+            "paused": self.control.is_paused(),
+            "paused_at": control.paused_at,
+            "paused_until": control.paused_until,
+            "muted": control.muted,
+            "label": self.ctx.label,
+            "in_flight": self.in_flight.is_some(),
+            "held_events": self.deferred.as_ref().map_or(0, |batch| batch.events.len()),
+            "latest_record": self.ctx.storage.latest_record_id()?,
+            "batches": self.counters.snapshot(),
+            "subscribers": self.ctx.records.receiver_count(),
+            "cache": cache,
+        }))
+    }
+
+    /// Commits everything observed so far and replies with the latest
+    /// record. The work becomes the in-flight batch, so the loop keeps
+    /// serving signals and requests meanwhile.
+    fn flush(&mut self, reply: oneshot::Sender<Result<Value, RpcError>>) {
+        let previous = self.in_flight.take();
+        let mut batch = self.deferred.take();
+        if let Some(open) = self.batcher.flush() {
+            match batch.as_mut() {
+                Some(held) => held.merge(open),
+                None => batch = Some(open),
+            }
+        }
+        let mut catch_up = None;
+        if self.control.is_paused() {
+            batch = None;
+        } else if let Some(since) = self.catch_up_from.take() {
+            self.counters.record(CloseReason::CatchUp);
+            catch_up = Some(since);
+        }
+        let ctx = self.ctx.clone();
+        self.in_flight = Some(tokio::spawn(async move {
+            if let Some(handle) = previous {
+                log_batch_result(handle.await);
+            }
+            if let Some(since) = catch_up {
+                log_batch_result(spawn_catch_up(since, &ctx).await);
+            }
+            if let Some(batch) = batch {
+                log_batch_result(spawn_batch(batch, &ctx).await);
+            }
+            let result = ctx
+                .storage
+                .latest_record_id()
+                .map(|latest| json!({ "latest_record": latest }))
+                .map_err(RpcError::from);
+            let _ = reply.send(result);
+            Ok(())
+        }));
+    }
+
+    async fn finish(mut self) {
+        if let Some(handle) = self.in_flight.take() {
+            tracing::info!("waiting for in-flight batch to finish");
+            log_batch_result(handle.await);
+        }
+        if let Some(batch) = self.deferred.take() {
+            tracing::info!("recording changes held during git rewrite");
+            log_batch_result(spawn_batch(batch, &self.ctx).await);
+        }
+        if let Some(since) = self.catch_up_from.take() {
+            log_batch_result(spawn_catch_up(since, &self.ctx).await);
+        }
+        let closed = self.counters.snapshot();
+        tracing::info!(
+            idle = closed.idle,
+            max_duration = closed.max_duration,
+            max_events = closed.max_events,
+            max_files = closed.max_files,
+            write = closed.write,
+            catch_up = closed.catch_up,
+            flush = closed.flush,
+            "batch close counters"
+        );
+    }
+}
+
+async fn join_in_flight(
+    handle: &mut Option<JoinHandle<Result<()>>>,
+) -> Result<Result<()>, tokio::task::JoinError> {
+    handle.as_mut().expect("batch in flight").await
+}
+
+#[cfg(unix)]
+async fn shutdown_signal() -> &'static str {
+    let mut sigterm = match signal(SignalKind::terminate()) {
+        Ok(stream) => stream,
+        Err(err) => {
+            tracing::warn!(error = %err, "failed to listen for SIGTERM");
+            let _ = tokio::signal::ctrl_c().await;
+            return "SIGINT";
+        }
+    };
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => "SIGINT",
+        _ = sigterm.recv() => "SIGTERM",
+    }
+}
+
+#[cfg(not(unix))]
+async fn shutdown_signal() -> &'static str {
+    let _ = tokio::signal::ctrl_c().await;
+    "SIGINT"
+}
+
+/// Console rendering of a committed record, shared by foreground `watch`
+/// and `tail`.
+pub fn render_record(meta: &RecordMeta, patch: Option<&str>) -> String {
+    let mut out = format!(
+        "record {} (files: {}, +{}, -{})",
+        meta.record_id, meta.stats.files, meta.stats.lines_added, meta.stats.lines_removed
+    );
+    if let Some(ref label) = meta.label {
+        out.push_str(&format!(" [{label}]"));
+    }
+    out.push('\n');
+    if let Some(patch) = patch {
+        out.push_str(&colorize_patch(patch));
+        out.push_str("\n\n");
+    }
+    out
+}
+
+fn spawn_batch(batch: Batch, ctx: &BatchContext) -> JoinHandle<Result<()>> {
+    let ctx = ctx.clone();
+    tokio::task::spawn_blocking(move || process_batch(batch, &ctx))
+}
+
+fn spawn_catch_up(since: DateTime<Utc>, ctx: &BatchContext) -> JoinHandle<Result<()>> {
+    let ctx = ctx.clone();
+    tokio::task::spawn_blocking(move || {
+        let batch = catch_up_batch(since, &ctx)?;
+        process_batch(batch, &ctx)
+    })
+}
+
+/// Synthetic batch covering the whole tree plus everything previously
+/// recorded, so edits, additions and deletions made while paused all show up.
+/// Files whose size and mode match their snapshot and that were not modified
+/// after it are skipped, so a long pause does not re-hash the whole tree.
+fn catch_up_batch(since: DateTime<Utc>, ctx: &BatchContext) -> Result<Batch> {
+    let stamps = ctx.storage.snapshot_stamps()?;
+    let mut paths = BTreeSet::new();
+    let mut unchanged = HashSet::new();
+    let walker = WalkDir::new(&ctx.project_root)
+        .min_depth(1)
+        .into_iter()
+        .filter_entry(|e| !ctx.ignore.is_ignored(e.path(), e.file_type().is_dir()));
+    for entry in walker.filter_map(|e| e.ok()) {
+        let path = entry.into_path();
+        if let Some(rel) = util::relative_path(&ctx.project_root, &path) {
+            if stamps
+                .get(&rel)
+                .is_some_and(|stamp| matches_snapshot(&path, stamp))
+            {
+                unchanged.insert(path);
+                continue;
+            }
+        }
+        paths.insert(path);
+    }
+    let known = ctx
+        .storage
+        .snapshot_paths()?
+        .into_iter()
+        .chain(ctx.storage.directory_paths()?);
+    for rel in known {
+        let path = ctx.project_root.join(rel);
+        if !unchanged.contains(&path) {
+            paths.insert(path);
+        }
+    }
+    let mut event = Event::new(EventKind::Any);
+    for path in paths {
+        event = event.add_path(path);
+    }
+    Ok(Batch {
+        events: vec![event],
+        started_at: since,
+        ended_at: util::now_utc(),
+        closed_by: CloseReason::CatchUp,
+        git: ctx
+            .tag_git
+            .then(|| git::read_context(&ctx.project_root))
+            .flatten(),
+    })
+}
+
+fn matches_snapshot(path: &Path, stamp: &SnapshotStamp) -> bool {
+    let Ok(meta) = fs::symlink_metadata(path) else {
+        return false;
+    };
+    let Ok(modified) = meta.modified() else {
+        return false;
+    };
+    stamp.size == Some(meta.len())
+        && DateTime::<Utc>::from(modified) <= stamp.updated_at
+        && (stamp.mode.is_none() || stamp.mode == util::worktree_mode(path))
+}
+
+fn log_batch_result(result: Result<Result<()>, tokio::task::JoinError>) {
+    match result {
+        Ok(Ok(())) => {}
+        Ok(Err(err)) => tracing::error!(error = %err, "failed to process batch"),
+        Err(err) => tracing::error!(error = %err, "batch worker panicked"),
+    }
+}
+
+fn build_worker_pool(jobs: usize) -> Result<ThreadPool> {
+    ThreadPoolBuilder::new()
+        .num_threads(jobs)
+        .thread_name(|idx| format!("meowdiff-diff-{idx}"))
+        .build()
+        .context("failed to build diff worker pool")
 }
 
 fn create_watcher(tx: mpsc::Sender<Event>) -> Result<RecommendedWatcher> {
@@ -134,116 +687,323 @@ fn create_watcher(tx: mpsc::Sender<Event>) -> Result<RecommendedWatcher> {
     Ok(watcher)
 }
 
-fn process_batch(
-    batch: microbatch::Batch,
+#[derive(Clone)]
+struct BatchContext {
     project_root: PathBuf,
     storage: Arc<StorageEngine>,
     ignore: Arc<IgnoreMatcher>,
-) -> Result<()> {
-    let unique_paths = collect_paths(&batch.events, &project_root, &ignore);
+    diff_options: Arc<DiffOptions>,
+    pool: Arc<ThreadPool>,
+    cache: Arc<SnapshotCache>,
+    tag_git: bool,
+    /// Label attached to new records, set over the control socket.
+    label: Option<String>,
+    records: broadcast::Sender<Arc<RecordEvent>>,
+    echo: bool,
+}
+
+fn process_batch(batch: Batch, ctx: &BatchContext) -> Result<()> {
+    let project_root = &ctx.project_root;
+    let storage = &ctx.storage;
+    let ignore = &ctx.ignore;
+    let (unique_paths, atomic_targets) = collect_paths(&batch.events, project_root, ignore);
     if unique_paths.is_empty() {
         return Ok(());
     }
+    settle_atomic_saves(&atomic_targets, project_root);
 
-    let artifacts = build_artifacts(&unique_paths, &project_root, &storage)?;
+    let expanded = expand_directories(&unique_paths, project_root, storage, ignore)?;
+    let mut artifacts: Vec<FileArtifact> = expanded
+        .directories
+        .iter()
+        .map(|(path, op)| directory_artifact(path, op.clone()))
+        .collect();
+    artifacts.extend(build_artifacts(&expanded.files, ctx)?);
     if artifacts.is_empty() {
         return Ok(());
     }
 
+    let (meta, patch) = assemble_record(
+        storage,
+        batch.started_at,
+        batch.ended_at,
+        &artifacts,
+        batch.git.filter(|_| ctx.tag_git),
+        ctx.label.clone(),
+    )?;
+
+    let compressed_patch = compress_patch(&patch)?;
+    storage.commit_record(&meta, &compressed_patch, &artifacts)?;
+    storage.register_record(&meta)?;
+    tracing::info!(record_id = %meta.record_id, files = meta.files.len(), "recorded batch");
+
+    if ctx.echo && !patch.trim().is_empty() {
+        print!("{}", render_record(&meta, Some(&patch)));
+    }
+    if ctx.records.receiver_count() > 0 {
+        let event = RecordEvent {
+            meta,
+            patch: Some(patch),
+        };
+        // no subscribers is not an error
+        let _ = ctx.records.send(Arc::new(event));
+    }
+
+    for artifact in artifacts {
+        ctx.cache
+            .apply_committed(&artifact.record, artifact.after_blob);
+    }
+    let cache = ctx.cache.stats();
+    tracing::debug!(
+        hits = cache.hits,
+        misses = cache.misses,
+        entries = cache.entries,
+        bytes = cache.bytes,
+        "snapshot cache"
+    );
+    Ok(())
+}
+
+/// Builds the record for `artifacts`, chained after the store's latest
+/// one, along with its combined patch text.
+fn assemble_record(
+    storage: &StorageEngine,
+    started_at: DateTime<Utc>,
+    ended_at: DateTime<Utc>,
+    artifacts: &[FileArtifact],
+    git: Option<GitContext>,
+    label: Option<String>,
+) -> Result<(RecordMeta, String)> {
     let file_records: Vec<FileRecord> = artifacts.iter().map(|a| a.record.clone()).collect();
     let stats = aggregate_stats(&file_records);
     let prev_record_id = storage.latest_record_id()?;
-    let record_id = generate_record_id(storage.project_id(), batch.started_at, &file_records);
+    let record_id = generate_record_id(storage.project_id(), started_at, &file_records);
 
     let meta = RecordMeta {
-        record_id: record_id.clone(),
+        record_id,
         project_id: storage.project_id().to_string(),
-        started_at: batch.started_at,
-        ended_at: batch.ended_at,
+        started_at,
+        ended_at,
         files: file_records,
         stats,
         prev_record_id,
         tool_version: util::tool_version(),
+        git,
+        label,
     };
 
     let mut patch = String::new();
-    for artifact in &artifacts {
+    for artifact in artifacts {
         patch.push_str(&artifact.patch);
         if !artifact.patch.ends_with('\n') {
             patch.push('\n');
         }
         patch.push('\n');
     }
-
-    let compressed_patch = compress_patch(&patch)?;
-    storage.commit_record(&meta, &compressed_patch, &artifacts)?;
-    storage.register_touch()?;
-    tracing::info!(record_id = %meta.record_id, files = meta.files.len(), "recorded batch");
-
-    if !patch.trim().is_empty() {
-        println!(
-            "record {} (files: {}, +{}, -{})",
-            record_id, meta.stats.files, meta.stats.lines_added, meta.stats.lines_removed
-        );
-        let colored = colorize_patch(&patch);
-        print!("{}\n\n", colored);
-    }
-    Ok(())
+    Ok((meta, patch))
 }
 
+/// Relative paths touched by a batch, plus the real files behind any editor
+/// temp/backup files seen, which may be briefly missing mid atomic save.
 fn collect_paths(
     events: &[Event],
     project_root: &Path,
     ignore: &IgnoreMatcher,
-) -> BTreeSet<String> {
+) -> (BTreeSet<String>, BTreeSet<String>) {
     let mut paths = BTreeSet::new();
+    let mut atomic_targets = BTreeSet::new();
+    let probes = vim_probes(events);
     for event in events {
         for path in &event.paths {
+            if probes.contains(path) {
+                continue;
+            }
             if let Some(rel) = util::relative_path(project_root, path) {
                 let abs = project_root.join(&rel);
+                if ignore.is_editor_artifact(&abs) {
+                    let target = editor_save_target(&abs)
+                        .and_then(|target| util::relative_path(project_root, &target));
+                    if let Some(target) = target {
+                        if !ignore.is_ignored(&project_root.join(&target), false) {
+                            paths.insert(target.clone());
+                            atomic_targets.insert(target);
+                        }
+                    }
+                    continue;
+                }
                 if !ignore.is_ignored(&abs, abs.is_dir()) {
                     paths.insert(rel);
                 }
             }
         }
     }
-    paths
+    (paths, atomic_targets)
+}
+
+/// Vim probe files created and deleted again within the batch.
+fn vim_probes(events: &[Event]) -> HashSet<&PathBuf> {
+    let mut created = HashSet::new();
+    let mut probes = HashSet::new();
+    for event in events {
+        for path in event.paths.iter().filter(|path| is_vim_probe(path)) {
+            match event.kind {
+                EventKind::Create(_) => {
+                    created.insert(path);
+                }
+                EventKind::Remove(_) if created.contains(path) => {
+                    probes.insert(path);
+                }
+                _ => {}
+            }
+        }
+    }
+    probes.retain(|path| path.symlink_metadata().is_err());
+    probes
 }
 
-fn build_artifacts(
+/// Gives editors that save via rename a moment to move the new file into
+/// place, so the save is recorded as one Modified instead of Deleted/Added.
+fn settle_atomic_saves(targets: &BTreeSet<String>, project_root: &Path) {
+    let deadline = Instant::now() + ATOMIC_SAVE_SETTLE;
+    for target in targets {
+        let absolute = project_root.join(target);
+        while absolute.symlink_metadata().is_err() && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+}
+
+pub struct ExpandedPaths {
+    pub files: BTreeSet<String>,
+    pub directories: Vec<(String, FileOp)>,
+}
+
+/// Splits event paths into files to diff and directory-level changes.
+///
+/// A new directory is walked so files moved or copied in with it are picked
+/// up; a vanished directory is expanded against `latest_snapshots` so every
+/// file it contained is recorded as deleted in the same record.
+pub fn expand_directories(
     paths: &BTreeSet<String>,
     project_root: &Path,
     storage: &StorageEngine,
-) -> Result<Vec<FileArtifact>> {
-    let mut artifacts = Vec::new();
-    for rel_path in paths.iter() {
+    ignore: &IgnoreMatcher,
+) -> Result<ExpandedPaths> {
+    let mut files = BTreeSet::new();
+    let mut created = BTreeSet::new();
+    let mut removed = BTreeSet::new();
+    for rel_path in paths {
+        if rel_path.is_empty() {
+            continue;
+        }
         let absolute = project_root.join(rel_path);
-        let after_blob = match fs::metadata(&absolute) {
-            Ok(meta) => {
-                if meta.is_dir() {
+        match fs::symlink_metadata(&absolute) {
+            Ok(meta) if meta.is_dir() => {
+                if storage.has_directory(rel_path)? {
                     continue;
                 }
-                Some(fs::read(&absolute)?)
+                created.insert(rel_path.clone());
+                let walker = WalkDir::new(&absolute)
+                    .min_depth(1)
+                    .into_iter()
+                    .filter_entry(|e| !ignore.is_ignored(e.path(), e.file_type().is_dir()));
+                for entry in walker.filter_map(|e| e.ok()) {
+                    let Some(rel) = util::relative_path(project_root, entry.path()) else {
+                        continue;
+                    };
+                    if entry.file_type().is_dir() {
+                        if !storage.has_directory(&rel)? {
+                            created.insert(rel);
+                        }
+                    } else {
+                        files.insert(rel);
+                    }
+                }
             }
-            Err(_) => None,
-        };
-        let before_sha = storage.fetch_snapshot(rel_path)?;
-        let before_blob = match before_sha {
-            Some(ref sha) => Some(storage.read_blob(sha)?),
-            None => None,
-        };
-        let input = FileInput {
-            path: rel_path.clone(),
-            before: before_blob,
-            after: after_blob,
-        };
-        if let Some(artifact) = build_file_artifact(input)? {
+            Ok(_) => {
+                files.insert(rel_path.clone());
+            }
+            Err(_) => {
+                let nested = storage.snapshot_paths_under(rel_path)?;
+                let was_dir = storage.has_directory(rel_path)? || !nested.is_empty();
+                if !was_dir {
+                    files.insert(rel_path.clone());
+                    continue;
+                }
+                files.extend(nested);
+                for dir in storage.directories_under(rel_path)? {
+                    if !project_root.join(&dir).exists() {
+                        removed.insert(dir);
+                    }
+                }
+                removed.insert(rel_path.clone());
+            }
+        }
+    }
+    let mut changes: Vec<(String, FileOp)> = created
+        .into_iter()
+        .map(|path| (path, FileOp::DirCreated))
+        .collect();
+    // children before parents so the record can be replayed in order
+    let mut removed: Vec<String> = removed.into_iter().collect();
+    removed.sort_by_key(|path| std::cmp::Reverse(path.matches('/').count()));
+    changes.extend(removed.into_iter().map(|path| (path, FileOp::DirRemoved)));
+    Ok(ExpandedPaths {
+        files,
+        directories: changes,
+    })
+}
+
+fn build_artifacts(paths: &BTreeSet<String>, ctx: &BatchContext) -> Result<Vec<FileArtifact>> {
+    let paths: Vec<&String> = paths.iter().collect();
+    // indexed parallel collect keeps results in path order
+    let results: Vec<Result<Option<FileArtifact>>> = ctx.pool.install(|| {
+        paths
+            .par_iter()
+            .map(|rel_path| build_path_artifact(rel_path, ctx))
+            .collect()
+    });
+    let mut artifacts = Vec::new();
+    for result in results {
+        if let Some(artifact) = result? {
             artifacts.push(artifact);
         }
     }
     Ok(artifacts)
 }
 
+fn build_path_artifact(rel_path: &str, ctx: &BatchContext) -> Result<Option<FileArtifact>> {
+    let absolute = ctx.project_root.join(rel_path);
+    let (after_blob, after_mode) = match util::read_worktree_entry(&absolute)? {
+        Some((data, mode)) => (Some(data), Some(mode)),
+        None if absolute.is_dir() => return Ok(None),
+        None => (None, None),
+    };
+    let previous = match ctx.cache.get(rel_path) {
+        Some(hit) => Some(hit),
+        None => match ctx.storage.fetch_snapshot(rel_path)? {
+            Some(info) => {
+                let loaded = CachedSnapshot {
+                    content: Arc::new(ctx.storage.read_blob(&info.sha)?),
+                    sha: info.sha,
+                    mode: info.mode,
+                };
+                ctx.cache.insert(rel_path, loaded.clone());
+                Some(loaded)
+            }
+            None => None,
+        },
+    };
+    let input = FileInput {
+        path: rel_path.to_string(),
+        before: previous.as_ref().map(|prev| prev.content.as_ref().clone()),
+        after: after_blob,
+        before_mode: previous.and_then(|prev| prev.mode),
+        after_mode,
+    };
+    build_file_artifact(input, &ctx.diff_options)
+}
+
 fn generate_record_id(project_id: &str, started_at: DateTime<Utc>, files: &[FileRecord]) -> String {
     let mut hasher = Hasher::new();
     hasher.update(project_id.as_bytes());
@@ -258,24 +1018,3 @@ fn generate_record_id(project_id: &str, started_at: DateTime<Utc>, files: &[File
     let encoded = hex::encode(hash.as_bytes());
     encoded.chars().take(12).collect()
 }
-
-fn prime_baseline(project_root: &Path, storage: &StorageEngine, ignore: &IgnoreMatcher) -> Result<()> {
-    let mut count = 0usize;
-    for entry in WalkDir::new(project_root).into_iter().filter_map(|e| e.ok()) {
-        let path = entry.path();
-        if entry.file_type().is_dir() {
-            continue;
-        }
-        if ignore.is_ignored(path, false) {
-            continue;
-        }
-        if let Some(rel) = util::relative_path(project_root, path) {
-            let data = fs::read(path)
-                .with_context(|| format!("failed to read {}", path.display()))?;
-            storage.seed_snapshot(&rel, &data)?;
-            count += 1;
-        }
-    }
-    tracing::info!(files = count, "baseline snapshots prepared");
-    Ok(())
-}
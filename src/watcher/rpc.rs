@@ -0,0 +1,324 @@
+//! Line-delimited JSON-RPC 2.0 over a Unix socket in `meta/`.
+//!
+//! Every request is one JSON object per line and gets one response line.
+//! `subscribe` turns the connection into a stream of `record` notifications
+//! until the client disconnects.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio::sync::oneshot;
+
+use crate::models::RecordMeta;
+
+pub const SOCKET_FILENAME: &str = "watch.sock";
+
+/// How long a client waits on a watcher that stopped answering. Generous
+/// because `flush` replies only after the pending batch is committed.
+pub const CALL_TIMEOUT: Duration = Duration::from_secs(60);
+
+pub const PARSE_ERROR: i64 = -32700;
+pub const INVALID_REQUEST: i64 = -32600;
+pub const METHOD_NOT_FOUND: i64 = -32601;
+pub const INVALID_PARAMS: i64 = -32602;
+pub const INTERNAL_ERROR: i64 = -32603;
+
+/// A request forwarded from a socket connection to the watch loop.
+pub struct RpcRequest {
+    pub method: String,
+    pub params: Value,
+    pub reply: oneshot::Sender<Result<Value, RpcError>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+}
+
+impl RpcError {
+    pub fn new(code: i64, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+
+    pub fn invalid_params(message: impl Into<String>) -> Self {
+        Self::new(INVALID_PARAMS, message)
+    }
+}
+
+impl From<anyhow::Error> for RpcError {
+    fn from(err: anyhow::Error) -> Self {
+        Self::new(INTERNAL_ERROR, format!("{err:#}"))
+    }
+}
+
+impl std::fmt::Display for RpcError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} (code {})", self.message, self.code)
+    }
+}
+
+impl std::error::Error for RpcError {}
+
+/// Published after every committed record.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordEvent {
+    pub meta: RecordMeta,
+    /// Uncompressed patch; only sent to subscribers that asked for it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub patch: Option<String>,
+}
+
+fn response(id: Value, result: Result<Value, RpcError>) -> Value {
+    match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err(error) => json!({ "jsonrpc": "2.0", "id": id, "error": error }),
+    }
+}
+
+#[cfg(unix)]
+pub use unix::{bind, call, serve, subscribe, Subscription};
+
+#[cfg(unix)]
+mod unix {
+    use std::io::{BufRead, BufReader, Write};
+    use std::os::unix::fs::PermissionsExt;
+    use std::path::Path;
+
+    use anyhow::{anyhow, bail, Context, Result};
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader as AsyncBufReader};
+    use tokio::net::{UnixListener, UnixStream};
+    use tokio::sync::broadcast::error::RecvError;
+    use tokio::sync::{broadcast, mpsc};
+
+    use super::*;
+
+    /// Binds the control socket, replacing a stale one left by a crashed
+    /// watcher. Callers must hold the watch lock.
+    pub fn bind(path: &Path) -> Result<UnixListener> {
+        if path.exists() {
+            std::fs::remove_file(path)
+                .with_context(|| format!("failed to remove stale socket {}", path.display()))?;
+        }
+        let listener = UnixListener::bind(path)
+            .with_context(|| format!("failed to bind control socket {}", path.display()))?;
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))
+            .with_context(|| format!("failed to restrict {}", path.display()))?;
+        Ok(listener)
+    }
+
+    pub async fn serve(
+        listener: UnixListener,
+        requests: mpsc::Sender<RpcRequest>,
+        records: broadcast::Sender<Arc<RecordEvent>>,
+    ) {
+        loop {
+            match listener.accept().await {
+                Ok((stream, _)) => {
+                    let requests = requests.clone();
+                    let records = records.clone();
+                    tokio::spawn(async move {
+                        if let Err(err) = handle_connection(stream, requests, records).await {
+                            tracing::debug!(error = %err, "control connection closed");
+                        }
+                    });
+                }
+                Err(err) => {
+                    tracing::warn!(error = %err, "control socket accept failed");
+                    return;
+                }
+            }
+        }
+    }
+
+    async fn handle_connection(
+        stream: UnixStream,
+        requests: mpsc::Sender<RpcRequest>,
+        records: broadcast::Sender<Arc<RecordEvent>>,
+    ) -> Result<()> {
+        let (read, mut write) = stream.into_split();
+        let mut lines = AsyncBufReader::new(read).lines();
+        while let Some(line) = lines.next_line().await? {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let request: Value = match serde_json::from_str(&line) {
+                Ok(value) => value,
+                Err(err) => {
+                    let error = RpcError::new(PARSE_ERROR, err.to_string());
+                    write_line(&mut write, &response(Value::Null, Err(error))).await?;
+                    continue;
+                }
+            };
+            let id = request.get("id").cloned().unwrap_or(Value::Null);
+            let Some(method) = request.get("method").and_then(Value::as_str) else {
+                let error = RpcError::new(INVALID_REQUEST, "missing method");
+                write_line(&mut write, &response(id, Err(error))).await?;
+                continue;
+            };
+            let params = request.get("params").cloned().unwrap_or(Value::Null);
+
+            if method == "subscribe" {
+                let with_patch = params
+                    .get("patch")
+                    .and_then(Value::as_bool)
+                    .unwrap_or(false);
+                // subscribe before acknowledging so no record slips through
+                let mut rx = records.subscribe();
+                let ack = json!({ "subscribed": true });
+                write_line(&mut write, &response(id, Ok(ack))).await?;
+                loop {
+                    let received = tokio::select! {
+                        received = rx.recv() => received,
+                        // subscribers only listen; EOF means they went away
+                        line = lines.next_line() => match line {
+                            Ok(Some(_)) => continue,
+                            Ok(None) | Err(_) => return Ok(()),
+                        },
+                    };
+                    let params = match received {
+                        Ok(event) => {
+                            let mut event = event.as_ref().clone();
+                            if !with_patch {
+                                event.patch = None;
+                            }
+                            serde_json::to_value(event)?
+                        }
+                        Err(RecvError::Lagged(skipped)) => {
+                            let note = json!({ "jsonrpc": "2.0", "method": "lagged", "params": { "skipped": skipped } });
+                            write_line(&mut write, &note).await?;
+                            continue;
+                        }
+                        Err(RecvError::Closed) => return Ok(()),
+                    };
+                    let note = json!({ "jsonrpc": "2.0", "method": "record", "params": params });
+                    write_line(&mut write, &note).await?;
+                }
+            }
+
+            let (reply, rx) = oneshot::channel();
+            let forwarded = RpcRequest {
+                method: method.to_string(),
+                params,
+                reply,
+            };
+            let result = if requests.send(forwarded).await.is_err() {
+                Err(RpcError::new(INTERNAL_ERROR, "watcher is shutting down"))
+            } else {
+                rx.await.unwrap_or_else(|_| {
+                    Err(RpcError::new(INTERNAL_ERROR, "watcher dropped the request"))
+                })
+            };
+            write_line(&mut write, &response(id, result)).await?;
+        }
+        Ok(())
+    }
+
+    async fn write_line(write: &mut tokio::net::unix::OwnedWriteHalf, value: &Value) -> Result<()> {
+        let mut line = serde_json::to_vec(value)?;
+        line.push(b'\n');
+        write.write_all(&line).await?;
+        Ok(())
+    }
+
+    /// Sends one request and waits for its response.
+    pub fn call(socket: &Path, method: &str, params: Value) -> Result<Value> {
+        let mut stream = connect(socket)?;
+        send_request(&mut stream, method, params)?;
+        let mut reader = BufReader::new(stream);
+        read_response(&mut reader)
+    }
+
+    /// Stream of records committed by the watcher from now on.
+    pub struct Subscription {
+        reader: BufReader<std::os::unix::net::UnixStream>,
+    }
+
+    pub fn subscribe(socket: &Path, with_patch: bool) -> Result<Subscription> {
+        let mut stream = connect(socket)?;
+        send_request(&mut stream, "subscribe", json!({ "patch": with_patch }))?;
+        let mut reader = BufReader::new(stream);
+        read_response(&mut reader)?;
+        // records may be far apart; only the acknowledgement is timed
+        reader.get_ref().set_read_timeout(None)?;
+        Ok(Subscription { reader })
+    }
+
+    fn connect(socket: &Path) -> Result<std::os::unix::net::UnixStream> {
+        let stream = std::os::unix::net::UnixStream::connect(socket)
+            .with_context(|| format!("failed to connect to {}", socket.display()))?;
+        stream.set_read_timeout(Some(CALL_TIMEOUT))?;
+        stream.set_write_timeout(Some(CALL_TIMEOUT))?;
+        Ok(stream)
+    }
+
+    impl Iterator for Subscription {
+        type Item = Result<RecordEvent>;
+
+        fn next(&mut self) -> Option<Self::Item> {
+            loop {
+                let mut line = String::new();
+                match self.reader.read_line(&mut line) {
+                    Ok(0) => return None,
+                    Ok(_) => {}
+                    Err(err) => return Some(Err(err.into())),
+                }
+                let message: Value = match serde_json::from_str(&line) {
+                    Ok(value) => value,
+                    Err(err) => return Some(Err(err.into())),
+                };
+                match message.get("method").and_then(Value::as_str) {
+                    Some("record") => {
+                        let params = message.get("params").cloned().unwrap_or(Value::Null);
+                        return Some(serde_json::from_value(params).map_err(Into::into));
+                    }
+                    Some("lagged") => {
+                        tracing::warn!(params = %message["params"], "subscription fell behind");
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    fn send_request(
+        stream: &mut std::os::unix::net::UnixStream,
+        method: &str,
+        params: Value,
+    ) -> Result<()> {
+        let request = json!({ "jsonrpc": "2.0", "id": 1, "method": method, "params": params });
+        let mut line = serde_json::to_vec(&request)?;
+        line.push(b'\n');
+        stream.write_all(&line)?;
+        Ok(())
+    }
+
+    fn read_response(reader: &mut impl BufRead) -> Result<Value> {
+        let mut line = String::new();
+        let read = reader
+            .read_line(&mut line)
+            .map_err(|err| match err.kind() {
+                std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut => {
+                    anyhow!("watcher did not answer within {}s", CALL_TIMEOUT.as_secs())
+                }
+                _ => err.into(),
+            })?;
+        if read == 0 {
+            bail!("watcher closed the control socket");
+        }
+        let mut message: Value = serde_json::from_str(&line)?;
+        if let Some(error) = message.get("error") {
+            let error: RpcError = serde_json::from_value(error.clone())?;
+            return Err(anyhow!(error));
+        }
+        Ok(message
+            .get_mut("result")
+            .map(Value::take)
+            .unwrap_or(Value::Null))
+    }
+}
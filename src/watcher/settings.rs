@@ -0,0 +1,103 @@
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use super::{BatchPolicy, GitOptions, WatchOptions};
+use crate::pipeline::{DiffOptions, NotebookOptions};
+use crate::util;
+
+const SETTINGS_FILENAME: &str = "watch.json";
+
+/// Watch flags a project runs with under `meowdiff daemon`, which has no
+/// command line per project. Saved by `meowdiff enable` next to the lock.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WatchSettings {
+    pub window_ms: u64,
+    /// 0 = never.
+    pub max_batch_ms: u64,
+    /// 0 = unlimited.
+    pub max_batch_events: usize,
+    /// 0 = unlimited.
+    pub max_batch_files: usize,
+    pub per_file: bool,
+    pub cache_mb: usize,
+    pub semantic: bool,
+    pub notebook: bool,
+    pub notebook_ignore_outputs: bool,
+    pub notebook_ignore_execution_count: bool,
+    pub notebook_ignore_metadata: bool,
+    pub git_tags: bool,
+    pub git_collapse: bool,
+}
+
+impl Default for WatchSettings {
+    fn default() -> Self {
+        Self {
+            window_ms: 50,
+            max_batch_ms: 5000,
+            max_batch_events: 10000,
+            max_batch_files: 0,
+            per_file: false,
+            cache_mb: 64,
+            semantic: false,
+            notebook: false,
+            notebook_ignore_outputs: false,
+            notebook_ignore_execution_count: false,
+            notebook_ignore_metadata: false,
+            git_tags: true,
+            git_collapse: false,
+        }
+    }
+}
+
+impl WatchSettings {
+    /// Settings saved for the project, or the defaults when none were.
+    pub fn load(meta_dir: &Path) -> Result<Self> {
+        let path = meta_dir.join(SETTINGS_FILENAME);
+        match fs::read(&path) {
+            Ok(bytes) => serde_json::from_slice(&bytes)
+                .with_context(|| format!("failed to parse {}", path.display())),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(err).with_context(|| format!("failed to read {}", path.display())),
+        }
+    }
+
+    pub fn save(&self, meta_dir: &Path) -> Result<()> {
+        util::ensure_dir(meta_dir)?;
+        let path = meta_dir.join(SETTINGS_FILENAME);
+        util::write_atomic(&path, &serde_json::to_vec_pretty(self)?)
+    }
+
+    /// Watch options with these settings applied and everything else default.
+    pub fn options(&self) -> WatchOptions {
+        WatchOptions {
+            batch: BatchPolicy {
+                window: Duration::from_millis(self.window_ms),
+                max_duration: (self.max_batch_ms > 0)
+                    .then(|| Duration::from_millis(self.max_batch_ms)),
+                max_events: (self.max_batch_events > 0).then_some(self.max_batch_events),
+                max_files: (self.max_batch_files > 0).then_some(self.max_batch_files),
+                per_write: self.per_file,
+            },
+            cache_bytes: self.cache_mb * 1024 * 1024,
+            git: GitOptions {
+                tag_records: self.git_tags,
+                collapse_rewrites: self.git_collapse,
+            },
+            diff: DiffOptions {
+                semantic: self.semantic,
+                notebook: NotebookOptions {
+                    enabled: self.notebook,
+                    ignore_outputs: self.notebook_ignore_outputs,
+                    ignore_execution_count: self.notebook_ignore_execution_count,
+                    ignore_metadata: self.notebook_ignore_metadata,
+                },
+            },
+            ..Default::default()
+        }
+    }
+}
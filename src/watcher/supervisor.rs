@@ -0,0 +1,227 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use rayon::ThreadPool;
+use tokio::sync::Notify;
+use tokio::task::JoinHandle;
+use tracing::Instrument;
+
+use super::{build_worker_pool, shutdown_signal, watch, WatchLock, WatchOptions, WatchSettings};
+use crate::runtime::ReadySignal;
+use crate::storage::{read_registry_global, ProjectEntry};
+use crate::util;
+
+/// How often the registry is re-read for enable/disable changes.
+const RECONCILE_INTERVAL: Duration = Duration::from_secs(1);
+const RESTART_BACKOFF_MIN: Duration = Duration::from_secs(2);
+const RESTART_BACKOFF_MAX: Duration = Duration::from_secs(60);
+
+pub struct SupervisorOptions {
+    /// Worker threads shared by all projects; 0 picks one per CPU.
+    pub jobs: usize,
+    pub ready: ReadySignal,
+}
+
+/// Directory holding the supervisor's own lock and logs.
+pub fn supervisor_dir() -> Result<PathBuf> {
+    Ok(util::meowdiff_root()?.join("daemon"))
+}
+
+struct Supervised {
+    path: PathBuf,
+    /// Enabling a running project again restarts it with its new settings.
+    enabled_at: Option<i64>,
+    stop: Arc<Notify>,
+    handle: JoinHandle<Result<()>>,
+    /// The daemon asked it to stop, as opposed to `meowdiff stop`.
+    stopping: bool,
+}
+
+/// An enabled project whose watcher failed, waiting to be retried.
+struct Parked {
+    retry_at: Instant,
+    delay: Duration,
+}
+
+/// Runs one watcher per enabled registry project in this process and keeps
+/// the set in line with `meowdiff enable` / `disable`.
+pub async fn supervise(options: SupervisorOptions) -> Result<()> {
+    let dir = supervisor_dir()?;
+    let lock = WatchLock::acquire_supervisor(&dir)?;
+    let pool = Arc::new(build_worker_pool(options.jobs)?);
+    let mut supervisor = Supervisor {
+        pool,
+        running: HashMap::new(),
+        parked: HashMap::new(),
+    };
+    options.ready.ready();
+    tracing::info!("daemon started");
+
+    let mut shutdown = Box::pin(shutdown_signal());
+    let mut tick = tokio::time::interval(RECONCILE_INTERVAL);
+    loop {
+        tokio::select! {
+            reason = &mut shutdown => {
+                tracing::info!("{reason} received, stopping all watchers");
+                break;
+            }
+            _ = tick.tick() => supervisor.reconcile().await,
+        }
+    }
+    supervisor.stop_all().await;
+    lock.release();
+    Ok(())
+}
+
+struct Supervisor {
+    pool: Arc<ThreadPool>,
+    running: HashMap<String, Supervised>,
+    parked: HashMap<String, Parked>,
+}
+
+impl Supervisor {
+    async fn reconcile(&mut self) {
+        self.reap().await;
+        let entries = match read_registry_global() {
+            Ok(entries) => entries,
+            Err(err) => {
+                tracing::warn!(error = %err, "failed to read registry");
+                return;
+            }
+        };
+        let enabled: HashMap<&str, &ProjectEntry> = entries
+            .iter()
+            .filter(|entry| entry.enabled && !entry.stopped)
+            .map(|entry| (entry.project_id.as_str(), entry))
+            .collect();
+
+        for (project_id, watcher) in self.running.iter_mut() {
+            if watcher.stopping {
+                continue;
+            }
+            let reason = match enabled.get(project_id.as_str()) {
+                None => "project disabled or stopped",
+                Some(entry) if entry.enabled_at != watcher.enabled_at => {
+                    "project enabled again, restarting with its settings"
+                }
+                Some(_) => continue,
+            };
+            tracing::info!(project_id, path = %watcher.path.display(), "{reason}");
+            watcher.stopping = true;
+            watcher.stop.notify_one();
+        }
+        self.parked
+            .retain(|id, _| enabled.contains_key(id.as_str()));
+
+        for (project_id, entry) in enabled {
+            if self.running.contains_key(project_id) {
+                continue;
+            }
+            if let Some(parked) = self.parked.get(project_id) {
+                if Instant::now() < parked.retry_at {
+                    continue;
+                }
+            }
+            let path = PathBuf::from(&entry.path);
+            if !path.is_dir() {
+                self.fail(project_id, &path, "project path no longer exists");
+                continue;
+            }
+            if let Err(err) = self.start(entry, path.clone()) {
+                self.fail(project_id, &path, &format!("{err:#}"));
+            }
+        }
+    }
+
+    fn start(&mut self, entry: &ProjectEntry, path: PathBuf) -> Result<()> {
+        let project_id = entry.project_id.as_str();
+        let meta_dir = util::meowdiff_root()?.join(project_id).join("meta");
+        let settings = WatchSettings::load(&meta_dir)?;
+        tracing::info!(project_id, path = %path.display(), "starting watcher");
+        let stop = Arc::new(Notify::new());
+        let options = WatchOptions {
+            project_root: path.clone(),
+            stop: Some(stop.clone()),
+            supervised: true,
+            pool: Some(self.pool.clone()),
+            echo: false,
+            ..settings.options()
+        };
+        let span = tracing::info_span!("project", id = project_id);
+        let handle = tokio::spawn(watch(options).instrument(span));
+        self.running.insert(
+            project_id.to_string(),
+            Supervised {
+                path,
+                enabled_at: entry.enabled_at,
+                stop,
+                handle,
+                stopping: false,
+            },
+        );
+        Ok(())
+    }
+
+    /// Collects watchers that have exited and decides whether to restart them.
+    async fn reap(&mut self) {
+        let finished: Vec<String> = self
+            .running
+            .iter()
+            .filter(|(_, watcher)| watcher.handle.is_finished())
+            .map(|(id, _)| id.clone())
+            .collect();
+        for project_id in finished {
+            let watcher = self.running.remove(&project_id).expect("finished watcher");
+            let result = match watcher.handle.await {
+                Ok(result) => result,
+                Err(err) => Err(anyhow::anyhow!("watcher task panicked: {err}")),
+            };
+            match result {
+                Ok(()) if watcher.stopping => {}
+                // `meowdiff stop` marks the project stopped before asking the
+                // watcher to exit, so reconcile leaves it down; any other
+                // exit is retried
+                Ok(()) => self.fail(&project_id, &watcher.path, "watcher exited"),
+                Err(err) => self.fail(&project_id, &watcher.path, &format!("{err:#}")),
+            }
+        }
+    }
+
+    fn fail(&mut self, project_id: &str, path: &Path, reason: &str) {
+        let delay = match self.parked.get(project_id) {
+            Some(parked) => (parked.delay * 2).min(RESTART_BACKOFF_MAX),
+            None => RESTART_BACKOFF_MIN,
+        };
+        tracing::warn!(
+            project_id,
+            path = %path.display(),
+            retry_in_secs = delay.as_secs(),
+            "watcher failed: {reason}"
+        );
+        self.parked.insert(
+            project_id.to_string(),
+            Parked {
+                retry_at: Instant::now() + delay,
+                delay,
+            },
+        );
+    }
+
+    async fn stop_all(&mut self) {
+        for watcher in self.running.values() {
+            watcher.stop.notify_one();
+        }
+        for (project_id, watcher) in self.running.drain() {
+            match watcher.handle.await {
+                Ok(Ok(())) => {}
+                Ok(Err(err)) => {
+                    tracing::warn!(project_id, error = %err, "watcher exited with error")
+                }
+                Err(err) => tracing::warn!(project_id, error = %err, "watcher task panicked"),
+            }
+        }
+    }
+}
@@ -0,0 +1,143 @@
+mod common;
+
+use common::{record, TestProject};
+use meowdiff::storage::{StorageEngine, TimelineFilter};
+use tempfile::tempdir;
+
+fn history(project: &TestProject) -> StorageEngine {
+    let storage = project.open();
+    record(
+        &storage,
+        "aaaa00000001",
+        0,
+        "a.txt",
+        Some(b"v0\n"),
+        Some(b"v1\n"),
+    );
+    record(
+        &storage,
+        "aaaa00000002",
+        1,
+        "a.txt",
+        Some(b"v1\n"),
+        Some(b"v2\n"),
+    );
+    storage
+}
+
+#[test]
+fn now_needs_a_record() {
+    let project = TestProject::new();
+    let storage = project.open();
+    assert!(storage.resolve_record("now").is_err());
+}
+
+#[test]
+fn tags_and_now_resolve_to_records() {
+    let project = TestProject::new();
+    let storage = history(&project);
+    storage
+        .set_tag("before-refactor", "aaaa00000001", false)
+        .unwrap();
+    assert_eq!(
+        storage.resolve_record("before-refactor").unwrap(),
+        "aaaa00000001"
+    );
+    assert_eq!(storage.resolve_record("now").unwrap(), "aaaa00000002");
+    assert!(storage.resolve_record("missing").is_err());
+}
+
+#[test]
+fn moving_a_tag_needs_force() {
+    let project = TestProject::new();
+    let storage = history(&project);
+    storage.set_tag("release", "aaaa00000001", false).unwrap();
+    assert!(storage.set_tag("release", "now", false).is_err());
+    let (_, previous) = storage.set_tag("release", "now", true).unwrap();
+    assert_eq!(previous.as_deref(), Some("aaaa00000001"));
+}
+
+#[test]
+fn reserved_names_and_record_ids_are_not_tags() {
+    let project = TestProject::new();
+    let storage = history(&project);
+    assert!(storage.set_tag("now", "now", false).is_err());
+    assert!(storage.set_tag("a..b", "now", false).is_err());
+    assert!(storage.set_tag("aaaa00000002", "now", false).is_err());
+}
+
+#[test]
+fn timeline_shows_notes_tags_and_bookmarks() {
+    let project = TestProject::new();
+    let storage = history(&project);
+    storage
+        .set_tag("before-refactor", "aaaa00000001", false)
+        .unwrap();
+    storage.add_note("before-refactor", "first pass").unwrap();
+    storage.add_note("aaaa00000001", "works").unwrap();
+    storage.add_bookmark("now", Some("green build")).unwrap();
+    let timeline = storage.timeline(&Default::default()).unwrap();
+    assert_eq!(timeline[1].notes.as_deref(), Some("first pass; works"));
+    assert_eq!(timeline[1].tags, ["before-refactor"]);
+    assert!(timeline[0].bookmarked && !timeline[1].bookmarked);
+}
+
+#[test]
+fn limited_timeline_keeps_its_own_annotations() {
+    let project = TestProject::new();
+    let storage = history(&project);
+    storage.set_tag("first", "aaaa00000001", false).unwrap();
+    storage.set_tag("second", "aaaa00000002", false).unwrap();
+    storage.add_note("aaaa00000002", "latest").unwrap();
+    storage.add_bookmark("aaaa00000001", None).unwrap();
+    let filter = TimelineFilter {
+        limit: Some(1),
+        ..Default::default()
+    };
+    let timeline = storage.timeline(&filter).unwrap();
+    assert_eq!(timeline.len(), 1);
+    assert_eq!(timeline[0].tags, ["second"]);
+    assert_eq!(timeline[0].notes.as_deref(), Some("latest"));
+    assert!(!timeline[0].bookmarked);
+}
+
+#[test]
+fn tags_work_as_diff_endpoints() {
+    let project = TestProject::new();
+    let storage = history(&project);
+    storage
+        .set_tag("before-refactor", "aaaa00000001", false)
+        .unwrap();
+    let from = storage.resolve_record("before-refactor").unwrap();
+    let to = storage.resolve_record("now").unwrap();
+    let artifacts = storage
+        .diff_records(&from, &to, &Default::default())
+        .unwrap();
+    assert_eq!(artifacts.len(), 1);
+    assert!(artifacts[0].patch.contains("-v1\n+v2"));
+}
+
+#[test]
+fn copied_records_bring_their_annotations() {
+    let project = TestProject::new();
+    let storage = history(&project);
+    storage
+        .set_tag("before-refactor", "aaaa00000001", false)
+        .unwrap();
+    storage.add_note("aaaa00000001", "first pass").unwrap();
+    storage.add_note("aaaa00000001", "works").unwrap();
+    storage.add_bookmark("now", Some("green build")).unwrap();
+
+    let other_checkout = tempdir().unwrap();
+    let other = project.open_checkout(other_checkout.path());
+    assert_eq!(other.import_history(&storage).unwrap(), 2);
+    assert_eq!(
+        other.resolve_record("before-refactor").unwrap(),
+        "aaaa00000001"
+    );
+    assert_eq!(other.notes("aaaa00000001").unwrap().len(), 2);
+    assert_eq!(
+        other.bookmarks().unwrap()[0].message.as_deref(),
+        Some("green build")
+    );
+}
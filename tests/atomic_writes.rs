@@ -0,0 +1,113 @@
+mod common;
+
+use std::process::Command;
+
+use anyhow::anyhow;
+use common::TestProject;
+use meowdiff::util;
+use tempfile::tempdir;
+
+/// Pid of a process that has already exited.
+fn dead_pid() -> u32 {
+    let mut child = Command::new("true").spawn().unwrap();
+    child.wait().unwrap();
+    child.id()
+}
+
+fn entries(dir: &std::path::Path) -> Vec<String> {
+    let mut names: Vec<String> = std::fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .collect();
+    names.sort();
+    names
+}
+
+#[test]
+fn write_atomic_replaces_without_leftovers() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("nested").join("meta.json");
+    util::write_atomic(&path, b"first").unwrap();
+    util::write_atomic(&path, b"second").unwrap();
+    assert_eq!(std::fs::read(&path).unwrap(), b"second");
+    assert_eq!(entries(path.parent().unwrap()), vec!["meta.json"]);
+}
+
+#[test]
+fn failed_write_keeps_previous_content() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("blob.zst");
+    util::write_atomic(&path, b"complete").unwrap();
+
+    let err = util::write_atomic_with(&path, |file| {
+        use std::io::Write;
+        file.write_all(b"trunc")?;
+        Err(anyhow!("simulated crash"))
+    })
+    .unwrap_err();
+    assert!(err.to_string().contains("simulated crash"));
+    assert_eq!(std::fs::read(&path).unwrap(), b"complete");
+    assert_eq!(entries(dir.path()), vec!["blob.zst"]);
+
+    // a blob that never finished must not appear at all
+    let fresh = dir.path().join("fresh.zst");
+    let _ = util::write_atomic_with(&fresh, |_| Err(anyhow!("simulated crash")));
+    assert!(!fresh.exists());
+}
+
+#[test]
+fn sweep_removes_temp_files_of_dead_writers_only() {
+    let dir = tempdir().unwrap();
+    let nested = dir.path().join("records").join("aaaa00000001");
+    std::fs::create_dir_all(&nested).unwrap();
+    let dead = format!(".meta.json.{}.0.tmp", dead_pid());
+    let live = format!(".meta.json.{}.3.tmp", std::process::id());
+    for name in [dead.as_str(), live.as_str(), "meta.json", ".hidden.tmp"] {
+        std::fs::write(nested.join(name), b"x").unwrap();
+    }
+
+    assert_eq!(util::sweep_temp_files(dir.path()).unwrap(), 1);
+    assert_eq!(
+        entries(&nested),
+        [".hidden.tmp", live.as_str(), "meta.json"]
+    );
+}
+
+#[test]
+fn opening_a_store_sweeps_interrupted_writes() {
+    let project = TestProject::new();
+    let blobs_dir = project.open().paths().blobs_dir.clone();
+    let leftover = blobs_dir.join(format!(".abc.zst.{}.7.tmp", dead_pid()));
+    std::fs::write(&leftover, b"partial").unwrap();
+
+    project.open();
+    assert!(!leftover.exists());
+}
+
+#[test]
+fn truncated_legacy_blob_is_dropped_once_and_rewritten() {
+    let project = TestProject::new();
+    let storage = project.open();
+    let data = b"hello blob\n";
+    storage.seed_snapshot("a.txt", data, None).unwrap();
+    let sha = util::hash_bytes(data);
+    let blob = storage
+        .paths()
+        .blobs_dir
+        .join(&sha[..2])
+        .join(format!("{sha}.zst"));
+    let written = std::fs::read(&blob).unwrap();
+    let truncated = &written[..written.len() / 2];
+    std::fs::write(&blob, truncated).unwrap();
+
+    // a store already checked is not rescanned on open
+    let storage = project.open();
+    assert!(storage.read_blob(&sha).is_err());
+
+    // one that predates the check drops the torn blob
+    std::fs::remove_file(storage.paths().meta_dir.join("blobs-verified")).unwrap();
+    let storage = project.open();
+    assert!(!blob.exists());
+    storage.ensure_blob(&sha, Some(data)).unwrap();
+    assert_eq!(storage.read_blob(&sha).unwrap(), data);
+}
@@ -0,0 +1,100 @@
+mod common;
+
+use common::{change, commit, record, TestProject};
+use meowdiff::storage::StorageEngine;
+use tempfile::tempdir;
+
+/// a.txt starts as `one two` in the baseline; record 1 appends `three`,
+/// record 2 (labelled) adds `zero` and rewrites `two`.
+fn history(project: &TestProject) -> StorageEngine {
+    let storage = project.open();
+    storage.seed_snapshot("a.txt", b"one\ntwo\n", None).unwrap();
+    record(
+        &storage,
+        "aaaa00000001",
+        0,
+        "a.txt",
+        Some(b"one\ntwo\n"),
+        Some(b"one\ntwo\nthree\n"),
+    );
+    commit(
+        &storage,
+        "aaaa00000002",
+        2,
+        Some("rename"),
+        b"patch",
+        &[change(
+            "a.txt",
+            Some(b"one\ntwo\nthree\n"),
+            Some(b"zero\none\nTWO\nthree\n"),
+        )],
+    );
+    storage
+}
+
+#[test]
+fn attributes_lines_to_last_touching_record() {
+    let project = TestProject::new();
+    let storage = history(&project);
+    let lines = storage.blame("a.txt").unwrap();
+    let summary: Vec<(&str, Option<&str>)> = lines
+        .iter()
+        .map(|line| (line.content.as_str(), line.record_id.as_deref()))
+        .collect();
+    assert_eq!(
+        summary,
+        [
+            ("zero", Some("aaaa00000002")),
+            ("one", None),
+            ("TWO", Some("aaaa00000002")),
+            ("three", Some("aaaa00000001")),
+        ]
+    );
+    assert_eq!(lines[0].label.as_deref(), Some("rename"));
+    assert_eq!(lines[3].line, 4);
+}
+
+#[test]
+fn created_file_goes_back_to_the_record_that_added_it() {
+    let project = TestProject::new();
+    let storage = project.open();
+    record(&storage, "aaaa00000001", 0, "a.txt", None, Some(b"fresh\n"));
+    let lines = storage.blame("a.txt").unwrap();
+    assert_eq!(lines[0].record_id.as_deref(), Some("aaaa00000001"));
+}
+
+#[test]
+fn untracked_file_is_an_error() {
+    let project = TestProject::new();
+    let storage = history(&project);
+    assert!(storage.blame("missing.txt").is_err());
+}
+
+#[test]
+fn imported_records_are_skipped() {
+    let project = TestProject::new();
+    let storage = history(&project);
+    let other_checkout = tempdir().unwrap();
+    let other = project.open_checkout(other_checkout.path());
+    record(
+        &other,
+        "bbbb00000001",
+        1,
+        "a.txt",
+        Some(b"elsewhere\n"),
+        Some(b"one\ntwo\nthree\n"),
+    );
+    storage.import_history(&other).unwrap();
+    let lines = storage.blame("a.txt").unwrap();
+    assert_eq!(lines[1].record_id, None);
+    assert_eq!(lines[3].record_id.as_deref(), Some("aaaa00000001"));
+}
+
+#[test]
+fn content_the_records_do_not_explain_is_an_error() {
+    let project = TestProject::new();
+    let storage = history(&project);
+    storage.seed_snapshot("a.txt", b"edited\n", None).unwrap();
+    let err = storage.blame("a.txt").unwrap_err();
+    assert!(err.to_string().contains("is broken at record aaaa00000002"));
+}
@@ -5,3 +5,14 @@ fn cli_displays_help() {
     let mut cmd = Command::cargo_bin("meowdiff").expect("binary exists");
     cmd.arg("--help").assert().success();
 }
+
+#[test]
+fn semantic_diff_rejects_json_and_stat() {
+    for flag in ["--json", "--stat"] {
+        let mut cmd = Command::cargo_bin("meowdiff").expect("binary exists");
+        cmd.args(["diff", "now", "--semantic", flag])
+            .assert()
+            .failure()
+            .code(2);
+    }
+}
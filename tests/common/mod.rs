@@ -0,0 +1,113 @@
+//! Fixtures shared by the storage integration tests.
+#![allow(dead_code)]
+
+use std::path::Path;
+
+use chrono::{DateTime, TimeZone, Utc};
+use meowdiff::models::{FileOp, FileRecord, FileStats, RecordMeta};
+use meowdiff::pipeline::{aggregate_stats, FileArtifact};
+use meowdiff::storage::StorageEngine;
+use meowdiff::util;
+use tempfile::TempDir;
+
+/// A project checkout with its own meowdiff root, so tests never share
+/// `~/.meowdiff` or touch `HOME`.
+pub struct TestProject {
+    pub root: TempDir,
+    pub checkout: TempDir,
+}
+
+impl TestProject {
+    pub fn new() -> Self {
+        Self {
+            root: tempfile::tempdir().unwrap(),
+            checkout: tempfile::tempdir().unwrap(),
+        }
+    }
+
+    pub fn open(&self) -> StorageEngine {
+        StorageEngine::open_in(self.root.path(), self.checkout.path()).unwrap()
+    }
+
+    /// The store of another checkout under the same meowdiff root.
+    pub fn open_checkout(&self, checkout: &Path) -> StorageEngine {
+        StorageEngine::open_in(self.root.path(), checkout).unwrap()
+    }
+}
+
+/// 2026-01-01 12:`minute`:00 UTC, the start of the test records.
+pub fn at(minute: u32) -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(2026, 1, 1, 12, minute, 0).unwrap()
+}
+
+/// A regular file going from `before` to `after`; `None` on either side
+/// makes it a create or a delete.
+pub fn change(path: &str, before: Option<&[u8]>, after: Option<&[u8]>) -> FileArtifact {
+    let record = FileRecord {
+        path: path.into(),
+        op: match (before, after) {
+            (None, Some(_)) => FileOp::Added,
+            (Some(_), None) => FileOp::Deleted,
+            _ => FileOp::Modified,
+        },
+        before_sha: before.map(util::hash_bytes),
+        after_sha: after.map(util::hash_bytes),
+        stats: FileStats::default(),
+        before_mode: before.map(|_| 0o100644),
+        after_mode: after.map(|_| 0o100644),
+        symlink_target: None,
+    };
+    FileArtifact {
+        record,
+        patch: String::new(),
+        semantic: None,
+        before_blob: before.map(<[u8]>::to_vec),
+        after_blob: after.map(<[u8]>::to_vec),
+    }
+}
+
+/// Commits `files` as record `id`, started at [`at`]`(minute)` and chained
+/// to the newest record.
+pub fn commit(
+    storage: &StorageEngine,
+    id: &str,
+    minute: u32,
+    label: Option<&str>,
+    patch: &[u8],
+    files: &[FileArtifact],
+) -> RecordMeta {
+    let records: Vec<FileRecord> = files.iter().map(|file| file.record.clone()).collect();
+    let meta = RecordMeta {
+        record_id: id.into(),
+        project_id: storage.project_id().into(),
+        started_at: at(minute),
+        ended_at: at(minute) + chrono::Duration::seconds(1),
+        stats: aggregate_stats(&records),
+        files: records,
+        prev_record_id: storage.latest_record_id().unwrap(),
+        tool_version: util::tool_version(),
+        git: None,
+        label: label.map(str::to_string),
+    };
+    storage.commit_record(&meta, patch, files).unwrap();
+    meta
+}
+
+/// Records one change to `path`.
+pub fn record(
+    storage: &StorageEngine,
+    id: &str,
+    minute: u32,
+    path: &str,
+    before: Option<&[u8]>,
+    after: Option<&[u8]>,
+) -> RecordMeta {
+    commit(
+        storage,
+        id,
+        minute,
+        None,
+        b"patch",
+        &[change(path, before, after)],
+    )
+}
@@ -0,0 +1,109 @@
+mod common;
+
+use std::thread;
+
+use assert_cmd::Command;
+use chrono::Duration;
+use common::{record, TestProject};
+use meowdiff::util;
+use meowdiff::watcher::{ControlState, ControlWatch};
+use tempfile::tempdir;
+
+#[test]
+fn timed_pause_expires() {
+    let now = util::now_utc();
+    let mut state = ControlState::default();
+    state.pause(now, Some(now + Duration::minutes(10)));
+    assert!(state.is_paused(now + Duration::minutes(9)));
+    assert!(!state.is_paused(now + Duration::minutes(11)));
+
+    // pausing again keeps the original start so catch-up covers it all
+    state.pause(now + Duration::minutes(1), None);
+    assert_eq!(state.paused_at, Some(now));
+    assert!(state.is_paused(now + Duration::days(1)));
+
+    state.resume();
+    assert!(!state.is_paused(now));
+}
+
+#[test]
+fn pause_rejects_durations_too_large() {
+    let home = tempdir().unwrap();
+    let checkout = tempdir().unwrap();
+    for duration in ["99999999999999999d", "9000000000000s"] {
+        Command::cargo_bin("meowdiff")
+            .expect("binary exists")
+            .env("HOME", home.path())
+            .args(["pause", "--for", duration, "--path"])
+            .arg(checkout.path())
+            .assert()
+            .failure()
+            .stderr(predicates::str::contains("duration too large"));
+    }
+}
+
+#[test]
+fn control_watch_reports_transitions() {
+    let dir = tempdir().unwrap();
+    let mut watch = ControlWatch::new(dir.path()).unwrap();
+    assert!(!watch.is_paused());
+
+    let mut state = ControlState::default();
+    let paused_at = util::now_utc();
+    state.pause(paused_at, None);
+    state.muted.push("logs/".into());
+    state.save(dir.path()).unwrap();
+
+    let change = watch.refresh().unwrap();
+    assert!(change.paused);
+    assert_eq!(change.muted, Some(vec!["logs/".to_string()]));
+    assert!(watch.is_paused());
+
+    state.resume();
+    state.save(dir.path()).unwrap();
+    let change = watch.refresh().unwrap();
+    assert_eq!(change.resumed_from, Some(paused_at));
+    assert_eq!(change.muted, None);
+    assert!(!watch.is_paused());
+}
+
+#[test]
+fn concurrent_updates_keep_every_change() {
+    let dir = tempdir().unwrap();
+    let handles: Vec<_> = (0..8)
+        .map(|n| {
+            let meta_dir = dir.path().to_path_buf();
+            thread::spawn(move || {
+                ControlState::update(&meta_dir, |state| {
+                    state.muted.push(format!("dir{n}/"));
+                    Ok(())
+                })
+                .unwrap();
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    assert_eq!(ControlState::load(dir.path()).unwrap().muted.len(), 8);
+}
+
+#[test]
+fn snapshots_remember_size_for_catch_up() {
+    let project = TestProject::new();
+    let storage = project.open();
+    storage.seed_snapshot("a.txt", b"v0\n", None).unwrap();
+    record(
+        &storage,
+        "aaaa00000001",
+        0,
+        "b.txt",
+        None,
+        Some(b"longer\n"),
+    );
+
+    let stamps = storage.snapshot_stamps().unwrap();
+    assert_eq!(stamps["a.txt"].size, Some(3));
+    assert_eq!(stamps["b.txt"].size, Some(7));
+    assert!(stamps["b.txt"].updated_at > common::at(0));
+}
@@ -0,0 +1,233 @@
+mod common;
+
+use std::path::Path;
+
+use assert_cmd::Command;
+use common::{commit, record};
+use meowdiff::models::FileOp;
+use meowdiff::pipeline::directory_artifact;
+use meowdiff::storage::{Registry, StorageEngine, WatcherStatus};
+use meowdiff::util;
+use meowdiff::watcher::WatchSettings;
+use serde_json::Value;
+use tempfile::tempdir;
+
+fn meowdiff(home: &Path) -> Command {
+    let mut cmd = Command::cargo_bin("meowdiff").expect("binary exists");
+    cmd.env("HOME", home);
+    cmd
+}
+
+fn projects(home: &Path) -> Vec<Value> {
+    let output = meowdiff(home)
+        .args(["projects", "--json"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    serde_json::from_slice(&output.stdout).unwrap()
+}
+
+#[test]
+fn enable_and_disable_toggle_registry_entry() {
+    let home = tempdir().unwrap();
+    let project = tempdir().unwrap();
+    std::fs::write(project.path().join("a.txt"), "a\n").unwrap();
+
+    meowdiff(home.path())
+        .arg("enable")
+        .arg(project.path())
+        .assert()
+        .success();
+    let entries = projects(home.path());
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0]["enabled"], true);
+    assert!(entries[0]["enabled_at"].is_i64());
+    assert_eq!(entries[0]["watcher"]["state"], "idle");
+
+    meowdiff(home.path())
+        .arg("disable")
+        .arg(project.path())
+        .assert()
+        .success();
+    let entries = projects(home.path());
+    assert_eq!(entries[0]["enabled"], false);
+    assert!(entries[0].get("enabled_at").is_none());
+}
+
+#[test]
+fn prune_forgets_projects_whose_root_is_gone() {
+    let home = tempdir().unwrap();
+    let project = tempdir().unwrap();
+    meowdiff(home.path())
+        .args(["status", "--path"])
+        .arg(project.path())
+        .assert()
+        .success();
+    assert_eq!(projects(home.path()).len(), 1);
+
+    let project_path = project.path().to_path_buf();
+    drop(project);
+    assert!(!project_path.exists());
+    meowdiff(home.path())
+        .args(["projects", "--prune"])
+        .assert()
+        .success();
+    assert!(projects(home.path()).is_empty());
+}
+
+#[test]
+fn relocate_rekeys_moved_checkout() {
+    let home = tempdir().unwrap();
+    let parent = tempdir().unwrap();
+    let old_path = parent.path().join("old");
+    let new_path = parent.path().join("new");
+    std::fs::create_dir(&old_path).unwrap();
+    std::fs::write(old_path.join("a.txt"), "a\n").unwrap();
+    meowdiff(home.path())
+        .arg("enable")
+        .arg(&old_path)
+        .assert()
+        .success();
+    let old_id = projects(home.path())[0]["project_id"]
+        .as_str()
+        .unwrap()
+        .to_string();
+
+    std::fs::rename(&old_path, &new_path).unwrap();
+    meowdiff(home.path())
+        .arg("relocate")
+        .arg(&old_path)
+        .arg(&new_path)
+        .assert()
+        .success();
+
+    let entries = projects(home.path());
+    assert_eq!(entries.len(), 1);
+    assert_ne!(entries[0]["project_id"], old_id.as_str());
+    assert_eq!(
+        entries[0]["path"],
+        new_path.canonicalize().unwrap().to_str().unwrap()
+    );
+    assert_eq!(entries[0]["enabled"], true);
+    assert!(!home.path().join(".meowdiff").join(&old_id).exists());
+
+    meowdiff(home.path())
+        .arg("relocate")
+        .arg(&old_path)
+        .arg(&new_path)
+        .assert()
+        .failure();
+}
+
+#[test]
+fn relocate_merges_into_existing_history() {
+    let home = tempdir().unwrap();
+    let root = home.path().join(".meowdiff");
+    let parent = tempdir().unwrap();
+    let old_path = parent.path().join("old");
+    let new_path = parent.path().join("new");
+    std::fs::create_dir_all(old_path.join("d")).unwrap();
+    std::fs::write(old_path.join("a.txt"), "a\n").unwrap();
+    let old = StorageEngine::open_in(&root, &old_path).unwrap();
+    let old_id = old.project_id().to_string();
+    record(&old, "aaaa00000001", 30, "a.txt", None, Some(b"a\n"));
+    commit(
+        &old,
+        "aaaa00000002",
+        31,
+        None,
+        b"patch",
+        &[directory_artifact("d", FileOp::DirCreated)],
+    );
+    drop(old);
+
+    std::fs::rename(&old_path, &new_path).unwrap();
+    std::fs::write(new_path.join("b.txt"), "b\n").unwrap();
+    let new = StorageEngine::open_in(&root, &new_path).unwrap();
+    record(&new, "bbbb00000001", 0, "b.txt", None, Some(b"b\n"));
+    drop(new);
+
+    let relocate = |merge: bool| {
+        let mut cmd = meowdiff(home.path());
+        cmd.arg("relocate").arg(&old_path).arg(&new_path);
+        if merge {
+            cmd.arg("--merge");
+        }
+        cmd.assert()
+    };
+    relocate(false).failure();
+    let output = relocate(true).success().get_output().stdout.clone();
+    assert!(String::from_utf8(output)
+        .unwrap()
+        .contains("Merged 2 record(s)"));
+
+    let merged = StorageEngine::open_in(&root, &new_path).unwrap();
+    assert_eq!(merged.timeline(&Default::default()).unwrap().len(), 3);
+    assert!(merged.has_directory("d").unwrap());
+    // the newer merged records do not take over this checkout's chain
+    assert_eq!(
+        merged.latest_record_id().unwrap().as_deref(),
+        Some("bbbb00000001")
+    );
+    assert_eq!(
+        merged.tree_at("bbbb00000001").unwrap()["b.txt"].sha,
+        util::hash_bytes(b"b\n")
+    );
+    assert!(!root.join(&old_id).exists());
+    assert_eq!(projects(home.path()).len(), 1);
+}
+
+#[test]
+fn enable_saves_watch_flags_for_the_daemon() {
+    let home = tempdir().unwrap();
+    let project = tempdir().unwrap();
+    let enable = |flags: &[&str]| {
+        meowdiff(home.path())
+            .arg("enable")
+            .arg(project.path())
+            .args(flags)
+            .assert()
+            .success();
+    };
+    let settings = || {
+        let storage =
+            StorageEngine::open_in(&home.path().join(".meowdiff"), project.path()).unwrap();
+        WatchSettings::load(&storage.paths().meta_dir).unwrap()
+    };
+
+    enable(&["--per-file", "--semantic", "--max-batch-ms", "0"]);
+    let saved = settings();
+    assert!(saved.per_file && saved.semantic);
+    let options = saved.options();
+    assert!(options.batch.per_write && options.diff.semantic);
+    assert_eq!(options.batch.max_duration, None);
+
+    // enabling again replaces them
+    enable(&[]);
+    assert_eq!(settings(), WatchSettings::default());
+}
+
+#[test]
+fn failed_startup_reports_the_watcher_stopped() {
+    let home = tempdir().unwrap();
+    let project = tempdir().unwrap();
+    // not a git checkout, so priming the baseline fails after registering
+    meowdiff(home.path())
+        .args(["watch", "--baseline-from", "HEAD", "--path"])
+        .arg(project.path())
+        .assert()
+        .failure();
+
+    let root = home.path().join(".meowdiff");
+    let project_id = StorageEngine::open_in(&root, project.path())
+        .unwrap()
+        .project_id()
+        .to_string();
+    let entry = Registry::open_in(&root)
+        .unwrap()
+        .find(&project_id)
+        .unwrap()
+        .unwrap();
+    assert_eq!(entry.watcher_state, WatcherStatus::Stopped);
+    assert_eq!(entry.watcher_pid, None);
+}
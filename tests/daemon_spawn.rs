@@ -0,0 +1,47 @@
+use std::process::Command;
+use std::time::Duration;
+
+use meowdiff::runtime::spawn_detached;
+use tempfile::tempdir;
+
+/// A stand-in daemon running `script` with the readiness pipe in its env.
+fn child(script: &str) -> Command {
+    let mut cmd = Command::new("sh");
+    cmd.args(["-c", script]);
+    cmd
+}
+
+#[test]
+fn ready_child_returns_its_pid() {
+    let logs = tempdir().unwrap();
+    let cmd = child(r#"echo ready >&"$MEOWDIFF_READY_FD""#);
+    let pid = spawn_detached(cmd, logs.path(), Duration::from_secs(10)).unwrap();
+    assert!(pid > 0);
+}
+
+#[test]
+fn reported_failure_is_returned() {
+    let logs = tempdir().unwrap();
+    let cmd = child(r#"echo "error: store is locked" >&"$MEOWDIFF_READY_FD"; exit 1"#);
+    let err = spawn_detached(cmd, logs.path(), Duration::from_secs(10)).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "watcher daemon failed to start: store is locked"
+    );
+}
+
+#[test]
+fn silent_exit_is_an_error() {
+    let logs = tempdir().unwrap();
+    let err = spawn_detached(child("exit 3"), logs.path(), Duration::from_secs(10)).unwrap_err();
+    assert!(err.to_string().contains("exited during startup"));
+}
+
+#[test]
+fn missing_readiness_times_out() {
+    let logs = tempdir().unwrap();
+    // keeps the pipe open without writing to it
+    let cmd = child("sleep 5");
+    let err = spawn_detached(cmd, logs.path(), Duration::from_millis(200)).unwrap_err();
+    assert!(err.to_string().contains("did not report readiness"));
+}
@@ -0,0 +1,157 @@
+mod common;
+
+use std::collections::BTreeSet;
+use std::fs;
+
+use assert_cmd::Command;
+use common::{change, commit, TestProject};
+use meowdiff::ignore::IgnoreMatcher;
+use meowdiff::models::FileOp;
+use meowdiff::pipeline::directory_artifact;
+use meowdiff::storage::StorageEngine;
+use meowdiff::watcher::{expand_directories, ExpandedPaths};
+use tempfile::tempdir;
+
+fn expand(storage: &StorageEngine, paths: &[&str]) -> ExpandedPaths {
+    let root = storage.project_root();
+    let paths: BTreeSet<String> = paths.iter().map(|path| path.to_string()).collect();
+    expand_directories(&paths, root, storage, &IgnoreMatcher::new(root).unwrap()).unwrap()
+}
+
+fn dirs(expanded: &ExpandedPaths) -> Vec<(&str, FileOp)> {
+    expanded
+        .directories
+        .iter()
+        .map(|(path, op)| (path.as_str(), op.clone()))
+        .collect()
+}
+
+#[test]
+fn new_directory_is_walked_for_files_and_subdirectories() {
+    let project = TestProject::new();
+    let storage = project.open();
+    fs::create_dir_all(project.checkout.path().join("d/e")).unwrap();
+    fs::write(project.checkout.path().join("d/e/f.txt"), "f\n").unwrap();
+
+    let expanded = expand(&storage, &["d"]);
+    assert_eq!(
+        dirs(&expanded),
+        [("d", FileOp::DirCreated), ("d/e", FileOp::DirCreated)]
+    );
+    assert_eq!(expanded.files, BTreeSet::from(["d/e/f.txt".to_string()]));
+}
+
+#[test]
+fn removed_directory_deletes_snapshotted_files_children_first() {
+    let project = TestProject::new();
+    let storage = project.open();
+    storage.seed_directory("d").unwrap();
+    storage.seed_directory("d/e").unwrap();
+    storage.seed_snapshot("d/e/a.txt", b"a\n", None).unwrap();
+
+    let expanded = expand(&storage, &["d"]);
+    assert_eq!(
+        dirs(&expanded),
+        [("d/e", FileOp::DirRemoved), ("d", FileOp::DirRemoved)]
+    );
+    assert_eq!(expanded.files, BTreeSet::from(["d/e/a.txt".to_string()]));
+}
+
+#[test]
+fn removed_directory_skips_files_never_snapshotted() {
+    let project = TestProject::new();
+    let storage = project.open();
+    storage.seed_directory("d").unwrap();
+
+    let expanded = expand(&storage, &["d"]);
+    assert_eq!(dirs(&expanded), [("d", FileOp::DirRemoved)]);
+    assert!(expanded.files.is_empty());
+}
+
+#[test]
+fn dir_removed_drops_everything_below_it() {
+    let project = TestProject::new();
+    let storage = project.open();
+    storage.seed_directory("d").unwrap();
+    storage.seed_directory("d/e").unwrap();
+    storage.seed_directory("dd").unwrap();
+    storage.seed_snapshot("d/e/a.txt", b"a\n", None).unwrap();
+    storage.seed_snapshot("dd/b.txt", b"b\n", None).unwrap();
+
+    commit(
+        &storage,
+        "aaaa00000001",
+        0,
+        None,
+        b"patch",
+        &[directory_artifact("d", FileOp::DirRemoved)],
+    );
+    assert!(!storage.has_directory("d").unwrap());
+    assert!(!storage.has_directory("d/e").unwrap());
+    assert!(storage.snapshot_paths_under("d").unwrap().is_empty());
+    // a sibling sharing the prefix is left alone
+    assert!(storage.has_directory("dd").unwrap());
+    assert_eq!(storage.snapshot_paths_under("dd").unwrap(), ["dd/b.txt"]);
+}
+
+#[test]
+fn dir_created_is_tracked() {
+    let project = TestProject::new();
+    let storage = project.open();
+    commit(
+        &storage,
+        "aaaa00000001",
+        0,
+        None,
+        b"patch",
+        &[directory_artifact("d", FileOp::DirCreated)],
+    );
+    assert!(storage.has_directory("d").unwrap());
+}
+
+#[test]
+fn directory_priming_is_remembered_without_subdirectories() {
+    let project = TestProject::new();
+    assert!(!project.open().directories_primed().unwrap());
+    project.open().mark_directories_primed().unwrap();
+    assert!(project.open().directories_primed().unwrap());
+}
+
+#[test]
+fn restore_revert_removes_created_directory() {
+    let home = tempdir().unwrap();
+    let checkout = tempdir().unwrap();
+    let storage = StorageEngine::open_in(&home.path().join(".meowdiff"), checkout.path()).unwrap();
+    fs::create_dir(checkout.path().join("d")).unwrap();
+    fs::write(checkout.path().join("d/a.txt"), "a\n").unwrap();
+    let meta = commit(
+        &storage,
+        "aaaa00000001",
+        0,
+        None,
+        b"patch",
+        &[
+            directory_artifact("d", FileOp::DirCreated),
+            change("d/a.txt", None, Some(b"a\n")),
+        ],
+    );
+    drop(storage);
+
+    let restore = |apply: bool| {
+        let mut cmd = Command::cargo_bin("meowdiff").expect("binary exists");
+        cmd.env("HOME", home.path())
+            .args(["restore", &meta.record_id, "--revert", "--path"])
+            .arg(checkout.path());
+        if apply {
+            cmd.arg("--apply");
+        }
+        cmd.assert().success()
+    };
+    let dry_run = restore(false);
+    let output = String::from_utf8(dry_run.get_output().stdout.clone()).unwrap();
+    assert!(output.contains("Would restore 1 files and 1 directories:"));
+    assert!(checkout.path().join("d/a.txt").exists());
+
+    restore(true);
+    assert!(!checkout.path().join("d").exists());
+}
@@ -0,0 +1,54 @@
+use std::path::{Path, PathBuf};
+
+use meowdiff::ignore::{editor_save_target, is_editor_artifact, is_vim_probe, IgnoreMatcher};
+
+#[test]
+fn recognizes_common_editor_temp_files() {
+    for name in [
+        "src/main.rs~",
+        "src/.main.rs.swp",
+        "src/main.rs___jb_tmp___",
+        "src/main.rs___jb_old___",
+        "src/.#main.rs",
+        "src/#main.rs#",
+    ] {
+        assert!(is_editor_artifact(Path::new(name)), "{name}");
+        assert_eq!(
+            editor_save_target(Path::new(name)),
+            Some(PathBuf::from("src/main.rs")),
+            "{name}"
+        );
+    }
+}
+
+#[test]
+fn vim_probe_names_follow_vims_sequence() {
+    for name in ["src/4913", "src/5036", "src/5159"] {
+        assert!(is_vim_probe(Path::new(name)), "{name}");
+    }
+    for name in ["src/4912", "src/5000", "src/1234", "src/4913.txt"] {
+        assert!(!is_vim_probe(Path::new(name)), "{name}");
+    }
+    // a file merely named like a probe is not ignored
+    assert!(!is_editor_artifact(Path::new("src/4913")));
+}
+
+#[test]
+fn negated_rules_override_editor_artifacts() {
+    let root = tempfile::tempdir().unwrap();
+    std::fs::write(root.path().join(".meowdiffignore"), "!*.swp\n").unwrap();
+    let ignore = IgnoreMatcher::new(root.path()).unwrap();
+    let swap = root.path().join(".main.rs.swp");
+    assert!(!ignore.is_ignored(&swap, false));
+    assert!(!ignore.is_editor_artifact(&swap));
+    let backup = root.path().join("main.rs~");
+    assert!(ignore.is_ignored(&backup, false));
+    assert!(ignore.is_editor_artifact(&backup));
+}
+
+#[test]
+fn leaves_regular_files_alone() {
+    for name in ["src/main.rs", "notes.md", "1234", "swp", "a#b"] {
+        assert!(!is_editor_artifact(Path::new(name)), "{name}");
+    }
+}
@@ -0,0 +1,76 @@
+use meowdiff::models::FileOp;
+use meowdiff::pipeline::{build_file_artifact, DiffOptions, FileInput};
+
+#[test]
+fn mode_only_change_produces_mode_headers() {
+    let input = FileInput {
+        path: "run.sh".into(),
+        before: Some(b"echo hi\n".to_vec()),
+        after: Some(b"echo hi\n".to_vec()),
+        before_mode: Some(0o100644),
+        after_mode: Some(0o100755),
+    };
+    let artifact = build_file_artifact(input, &DiffOptions::default())
+        .unwrap()
+        .expect("mode change is recorded");
+    assert_eq!(artifact.record.op, FileOp::Modified);
+    assert!(artifact
+        .patch
+        .starts_with("diff --git a/run.sh b/run.sh\nold mode 100644\nnew mode 100755\n"));
+}
+
+#[test]
+fn symlink_target_is_recorded() {
+    let input = FileInput {
+        path: "current".into(),
+        before: Some(b"v1".to_vec()),
+        after: Some(b"v2".to_vec()),
+        before_mode: Some(0o120777),
+        after_mode: Some(0o120777),
+    };
+    let artifact = build_file_artifact(input, &DiffOptions::default())
+        .unwrap()
+        .unwrap();
+    assert_eq!(artifact.record.symlink_target.as_deref(), Some("v2"));
+}
+
+#[test]
+fn file_to_symlink_is_a_delete_and_a_create() {
+    let input = FileInput {
+        path: "current".into(),
+        before: Some(b"text\n".to_vec()),
+        after: Some(b"target".to_vec()),
+        before_mode: Some(0o100644),
+        after_mode: Some(0o120777),
+    };
+    let artifact = build_file_artifact(input, &DiffOptions::default())
+        .unwrap()
+        .unwrap();
+    assert!(artifact.patch.starts_with(
+        "diff --git a/current b/current\ndeleted file mode 100644\n--- a/current\n+++ /dev/null\n"
+    ));
+    assert!(artifact.patch.contains(
+        "diff --git a/current b/current\nnew file mode 120777\n--- /dev/null\n+++ b/current\n"
+    ));
+    assert_eq!(
+        (artifact.record.stats.added, artifact.record.stats.removed),
+        (1, 1)
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn symlink_replaces_empty_directory() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("current");
+    std::fs::create_dir(&path).unwrap();
+    meowdiff::util::write_worktree_entry(&path, b"elsewhere", Some(0o120777)).unwrap();
+    assert_eq!(
+        std::fs::read_link(&path).unwrap(),
+        std::path::Path::new("elsewhere")
+    );
+
+    std::fs::remove_file(&path).unwrap();
+    std::fs::create_dir_all(path.join("inner")).unwrap();
+    assert!(meowdiff::util::write_worktree_entry(&path, b"elsewhere", Some(0o120777)).is_err());
+}
@@ -0,0 +1,91 @@
+use std::path::Path;
+use std::process::Command as StdCommand;
+
+use assert_cmd::Command;
+use meowdiff::storage::StorageEngine;
+use serde_json::Value;
+use tempfile::tempdir;
+
+fn meowdiff(home: &Path, project: &Path) -> Command {
+    let mut cmd = Command::cargo_bin("meowdiff").expect("binary exists");
+    cmd.env("HOME", home).current_dir(project);
+    cmd
+}
+
+fn git(root: &Path, args: &[&str]) {
+    let status = StdCommand::new("git")
+        .arg("-C")
+        .arg(root)
+        .args(["-c", "user.name=Test", "-c", "user.email=test@example.com"])
+        .args(args)
+        .status()
+        .unwrap();
+    assert!(status.success(), "git {args:?} failed");
+}
+
+fn rebaseline(home: &Path, project: &Path, args: &[&str]) -> Value {
+    let output = meowdiff(home, project)
+        .arg("rebaseline")
+        .args(args)
+        .arg("--json")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    serde_json::from_slice(&output.stdout).unwrap()
+}
+
+#[test]
+fn rebaseline_switches_between_commit_and_working_tree() {
+    let home = tempdir().unwrap();
+    let repo = tempdir().unwrap();
+    let root = repo.path();
+    git(root, &["init", "-q"]);
+    std::fs::write(root.join("a.txt"), "one\n").unwrap();
+    git(root, &["add", "a.txt"]);
+    git(root, &["commit", "-q", "-m", "init"]);
+    std::fs::write(root.join("a.txt"), "one\ntwo\n").unwrap();
+
+    let record = rebaseline(home.path(), root, &["--from-git", "HEAD"]);
+    assert_eq!(record["files"][0]["path"], "a.txt");
+    assert_eq!(record["files"][0]["op"], "added");
+    assert!(record["label"]
+        .as_str()
+        .unwrap()
+        .starts_with("rebaseline from HEAD ("));
+    assert!(rebaseline(home.path(), root, &["--from-git", "HEAD"]).is_null());
+
+    // the uncommitted line now shows up against the commit
+    let record = rebaseline(home.path(), root, &["--from-tree"]);
+    assert_eq!(record["files"][0]["op"], "modified");
+    assert_eq!(record["stats"]["lines_added"], 1);
+    assert_eq!(record["label"], "rebaseline from working tree");
+
+    meowdiff(home.path(), root)
+        .args(["rebaseline", "--from-git", "no-such-rev"])
+        .assert()
+        .failure();
+}
+
+#[test]
+fn rebaseline_diffs_with_the_saved_watch_settings() {
+    let home = tempdir().unwrap();
+    let repo = tempdir().unwrap();
+    let root = repo.path();
+    git(root, &["init", "-q"]);
+    std::fs::write(root.join("config.json"), "{\"a\": 1}\n").unwrap();
+    git(root, &["add", "config.json"]);
+    git(root, &["commit", "-q", "-m", "init"]);
+    meowdiff(home.path(), root)
+        .args(["enable", "--semantic"])
+        .assert()
+        .success();
+
+    rebaseline(home.path(), root, &["--from-git", "HEAD"]);
+    std::fs::write(root.join("config.json"), "{\"a\": 2}\n").unwrap();
+    let record = rebaseline(home.path(), root, &["--from-tree"]);
+    let storage = StorageEngine::open_in(&home.path().join(".meowdiff"), root).unwrap();
+    let semantic = storage
+        .read_semantic(record["record_id"].as_str().unwrap())
+        .unwrap();
+    assert_eq!(semantic[0].path, "config.json");
+}
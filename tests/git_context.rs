@@ -0,0 +1,96 @@
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use meowdiff::git;
+use meowdiff::models::GitOperation;
+use meowdiff::watcher::{BatchCounters, BatchPolicy, Batcher};
+use notify::event::{EventKind, ModifyKind};
+use notify::Event;
+use tempfile::tempdir;
+
+const SHA: &str = "0123456789abcdef0123456789abcdef01234567";
+
+#[test]
+fn reads_branch_and_head_from_loose_ref() {
+    let dir = tempdir().unwrap();
+    let git_dir = dir.path().join(".git");
+    fs::create_dir_all(git_dir.join("refs/heads")).unwrap();
+    fs::write(git_dir.join("HEAD"), "ref: refs/heads/main\n").unwrap();
+    fs::write(git_dir.join("refs/heads/main"), format!("{SHA}\n")).unwrap();
+
+    let ctx = git::read_context(dir.path()).expect("git repo");
+    assert_eq!(ctx.branch.as_deref(), Some("main"));
+    assert_eq!(ctx.head.as_deref(), Some(SHA));
+    assert_eq!(ctx.operation, None);
+    assert!(!git::is_rewriting(dir.path()));
+}
+
+#[test]
+fn resolves_packed_refs_and_detects_rebase() {
+    let dir = tempdir().unwrap();
+    let git_dir = dir.path().join(".git");
+    fs::create_dir_all(git_dir.join("rebase-merge")).unwrap();
+    fs::write(git_dir.join("HEAD"), "ref: refs/heads/feature\n").unwrap();
+    fs::write(
+        git_dir.join("packed-refs"),
+        format!("# pack-refs with: peeled fully-peeled sorted\n{SHA} refs/heads/feature\n"),
+    )
+    .unwrap();
+
+    let ctx = git::read_context(dir.path()).expect("git repo");
+    assert_eq!(ctx.head.as_deref(), Some(SHA));
+    assert_eq!(ctx.operation, Some(GitOperation::Rebase));
+    assert!(git::is_rewriting(dir.path()));
+
+    // a rebase stopped for conflict resolution is user editing, not a rewrite
+    fs::write(git_dir.join("rebase-merge/stopped-sha"), SHA).unwrap();
+    assert!(!git::is_rewriting(dir.path()));
+}
+
+#[test]
+fn detached_head_has_no_branch() {
+    let dir = tempdir().unwrap();
+    fs::create_dir_all(dir.path().join(".git")).unwrap();
+    fs::write(dir.path().join(".git/HEAD"), format!("{SHA}\n")).unwrap();
+
+    let ctx = git::read_context(dir.path()).expect("git repo");
+    assert_eq!(ctx.branch, None);
+    assert_eq!(ctx.head.as_deref(), Some(SHA));
+    assert!(git::read_context(&dir.path().join("missing")).is_none());
+}
+
+#[test]
+fn short_sha_never_splits_a_character() {
+    assert_eq!(git::short_sha(SHA), "01234567");
+    assert_eq!(git::short_sha("abc"), "abc");
+    // the eighth byte falls inside `é`
+    assert_eq!(git::short_sha("abcdefgé"), "abcdefgé");
+}
+
+#[tokio::test]
+async fn batch_keeps_git_context_from_when_it_opened() {
+    let dir = tempdir().unwrap();
+    let git_dir = dir.path().join(".git");
+    fs::create_dir_all(&git_dir).unwrap();
+    fs::write(git_dir.join("HEAD"), "ref: refs/heads/main\n").unwrap();
+
+    let (tx, rx) = tokio::sync::mpsc::channel(16);
+    let policy = BatchPolicy {
+        window: Duration::from_secs(60),
+        ..Default::default()
+    };
+    let mut batcher =
+        Batcher::new(rx, policy, Arc::new(BatchCounters::default())).with_git_context(dir.path());
+    tx.send(Event::new(EventKind::Modify(ModifyKind::Any)).add_path(PathBuf::from("a.txt")))
+        .await
+        .unwrap();
+    // open the batch, then switch branches before it closes
+    let pending = tokio::time::timeout(Duration::from_millis(50), batcher.next_batch()).await;
+    assert!(pending.is_err());
+    fs::write(git_dir.join("HEAD"), "ref: refs/heads/other\n").unwrap();
+
+    let batch = batcher.flush().expect("batch is open");
+    assert_eq!(batch.git.unwrap().branch.as_deref(), Some("main"));
+}
@@ -0,0 +1,132 @@
+mod common;
+
+use std::path::Path;
+use std::process::Command;
+use std::time::Duration;
+
+use common::{change, commit, TestProject};
+use meowdiff::git::{replay_to_git, ReplayOptions};
+use meowdiff::storage::StorageEngine;
+
+fn git(root: &Path, args: &[&str]) -> String {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(root)
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "git {args:?} failed");
+    String::from_utf8(output.stdout).unwrap().trim().to_string()
+}
+
+/// A git checkout with a baseline of `v0` and three records taking a.txt to
+/// `v3`, the first two within five minutes of each other.
+fn history(project: &TestProject) -> StorageEngine {
+    let root = project.checkout.path();
+    git(root, &["init", "-q", "-b", "main"]);
+    std::fs::write(root.join("a.txt"), "v0\n").unwrap();
+
+    let storage = project.open();
+    storage
+        .seed_snapshot("a.txt", b"v0\n", Some(0o100644))
+        .unwrap();
+    let edit = |before: &[u8], after: &[u8]| [change("a.txt", Some(before), Some(after))];
+    commit(
+        &storage,
+        "aaaa00000001",
+        0,
+        Some("first edit"),
+        b"patch",
+        &edit(b"v0\n", b"v1\n"),
+    );
+    commit(
+        &storage,
+        "aaaa00000002",
+        2,
+        None,
+        b"patch",
+        &edit(b"v1\n", b"v2\n"),
+    );
+    commit(
+        &storage,
+        "aaaa00000003",
+        10,
+        None,
+        b"patch",
+        &edit(b"v2\n", b"v3\n"),
+    );
+    storage
+}
+
+fn options() -> ReplayOptions {
+    ReplayOptions {
+        branch: "meowdiff/session".into(),
+        filter: Default::default(),
+        squash_by: None,
+        force: false,
+    }
+}
+
+#[test]
+fn replays_each_record_as_a_commit() {
+    let project = TestProject::new();
+    let storage = history(&project);
+    let root = project.checkout.path();
+
+    let summary = replay_to_git(&storage, &options()).unwrap();
+    assert_eq!(summary.commits, 4);
+    assert_eq!(git(root, &["show", "meowdiff/session~3:a.txt"]), "v0");
+    assert_eq!(git(root, &["show", "meowdiff/session:a.txt"]), "v3");
+    assert_eq!(
+        git(root, &["log", "-1", "--format=%s", "meowdiff/session~2"]),
+        "first edit"
+    );
+    assert!(git(root, &["log", "-1", "--format=%B", "meowdiff/session"])
+        .contains("MeowDiff-Record: aaaa00000003"));
+    assert_eq!(
+        git(root, &["log", "-1", "--format=%aI", "meowdiff/session"]),
+        "2026-01-01T12:10:00+00:00"
+    );
+}
+
+#[test]
+fn replacing_a_branch_needs_force() {
+    let project = TestProject::new();
+    let storage = history(&project);
+    let mut options = options();
+    replay_to_git(&storage, &options).unwrap();
+    assert!(replay_to_git(&storage, &options).is_err());
+    options.force = true;
+    assert_eq!(replay_to_git(&storage, &options).unwrap().commits, 4);
+}
+
+#[test]
+fn squashing_folds_nearby_records() {
+    let project = TestProject::new();
+    let storage = history(&project);
+    let options = ReplayOptions {
+        squash_by: Some(Duration::from_secs(5 * 60)),
+        ..options()
+    };
+    let summary = replay_to_git(&storage, &options).unwrap();
+    assert_eq!(summary.commits, 3);
+    assert_eq!(
+        git(
+            project.checkout.path(),
+            &["show", "meowdiff/session~1:a.txt"]
+        ),
+        "v2"
+    );
+}
+
+#[test]
+fn replay_leaves_the_checkout_alone() {
+    let project = TestProject::new();
+    let storage = history(&project);
+    let root = project.checkout.path();
+    replay_to_git(&storage, &options()).unwrap();
+    // no commits on main, nothing staged
+    assert_eq!(git(root, &["symbolic-ref", "HEAD"]), "refs/heads/main");
+    assert!(git(root, &["branch", "--list", "main"]).is_empty());
+    assert_eq!(git(root, &["status", "--porcelain"]), "?? a.txt");
+}
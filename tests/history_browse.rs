@@ -0,0 +1,143 @@
+mod common;
+
+use chrono::{TimeZone, Utc};
+use common::{at, record, TestProject};
+use meowdiff::storage::{StorageEngine, Tree};
+use tempfile::tempdir;
+
+/// Baseline a.txt `v0`; record 1 edits it, record 2 adds b.txt, record 3
+/// deletes a.txt.
+fn history(project: &TestProject) -> StorageEngine {
+    let storage = project.open();
+    storage
+        .seed_snapshot("a.txt", b"v0\n", Some(0o100644))
+        .unwrap();
+    record(
+        &storage,
+        "aaaa00000001",
+        0,
+        "a.txt",
+        Some(b"v0\n"),
+        Some(b"v1\n"),
+    );
+    record(&storage, "aaaa00000002", 5, "b.txt", None, Some(b"new\n"));
+    record(&storage, "aaaa00000003", 10, "a.txt", Some(b"v1\n"), None);
+    storage
+}
+
+fn contents(storage: &StorageEngine, tree: &Tree) -> Vec<(String, String)> {
+    tree.iter()
+        .map(|(path, file)| {
+            let data = storage.read_blob(&file.sha).unwrap();
+            (path.clone(), String::from_utf8(data).unwrap())
+        })
+        .collect()
+}
+
+fn pairs(items: &[(&str, &str)]) -> Vec<(String, String)> {
+    items
+        .iter()
+        .map(|(path, text)| (path.to_string(), text.to_string()))
+        .collect()
+}
+
+#[test]
+fn before_any_record_is_the_baseline() {
+    let project = TestProject::new();
+    let storage = history(&project);
+    let before = Utc.with_ymd_and_hms(2025, 12, 31, 0, 0, 0).unwrap();
+    assert_eq!(
+        contents(&storage, &storage.tree_at_time(before).unwrap()),
+        pairs(&[("a.txt", "v0\n")])
+    );
+}
+
+#[test]
+fn between_records_is_the_earlier_state() {
+    let project = TestProject::new();
+    let storage = history(&project);
+    assert_eq!(
+        contents(&storage, &storage.tree_at_time(at(7)).unwrap()),
+        pairs(&[("a.txt", "v1\n"), ("b.txt", "new\n")])
+    );
+}
+
+#[test]
+fn deleted_files_drop_out() {
+    let project = TestProject::new();
+    let storage = history(&project);
+    assert_eq!(
+        contents(&storage, &storage.tree_at_time(at(30)).unwrap()),
+        pairs(&[("b.txt", "new\n")])
+    );
+}
+
+#[test]
+fn time_of_a_record_matches_its_tree() {
+    let project = TestProject::new();
+    let storage = history(&project);
+    assert_eq!(
+        storage
+            .tree_at_time(at(5) + chrono::Duration::seconds(30))
+            .unwrap(),
+        storage.tree_at("aaaa00000002").unwrap()
+    );
+}
+
+#[test]
+fn imported_records_are_not_undone() {
+    let project = TestProject::new();
+    let storage = history(&project);
+    let other_checkout = tempdir().unwrap();
+    let other = project.open_checkout(other_checkout.path());
+    record(
+        &other,
+        "bbbb00000001",
+        7,
+        "a.txt",
+        Some(b"elsewhere\n"),
+        Some(b"moved on\n"),
+    );
+    assert_eq!(storage.import_history(&other).unwrap(), 1);
+
+    assert_eq!(
+        contents(&storage, &storage.tree_at_time(at(6)).unwrap()),
+        pairs(&[("a.txt", "v1\n"), ("b.txt", "new\n")])
+    );
+    let err = storage.tree_at("bbbb00000001").unwrap_err();
+    assert!(err.to_string().contains("not in the history of this tree"));
+}
+
+#[test]
+fn records_ending_together_keep_commit_order() {
+    let project = TestProject::new();
+    let storage = project.open();
+    record(&storage, "bbbb00000002", 0, "a.txt", None, Some(b"v1\n"));
+    record(
+        &storage,
+        "aaaa00000001",
+        0,
+        "a.txt",
+        Some(b"v1\n"),
+        Some(b"v2\n"),
+    );
+    assert_eq!(
+        storage.latest_record_id().unwrap().as_deref(),
+        Some("aaaa00000001")
+    );
+    assert_eq!(
+        contents(&storage, &storage.tree_at("bbbb00000002").unwrap()),
+        pairs(&[("a.txt", "v1\n")])
+    );
+}
+
+#[test]
+fn a_tree_that_drifted_from_its_records_is_an_error() {
+    let project = TestProject::new();
+    let storage = history(&project);
+    storage.seed_snapshot("b.txt", b"edited\n", None).unwrap();
+    let err = storage.tree_at("aaaa00000001").unwrap_err();
+    assert!(err
+        .to_string()
+        .contains("history is broken at record aaaa00000002"));
+}
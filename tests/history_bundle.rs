@@ -0,0 +1,186 @@
+mod common;
+
+use common::{at, record, TestProject};
+use meowdiff::storage::{StorageEngine, TimelineFilter, Tree};
+use meowdiff::util;
+use tempfile::tempdir;
+
+fn exported(project: &TestProject) -> (StorageEngine, std::path::PathBuf) {
+    let source = project.open();
+    record(&source, "aaaa00000001", 0, "a.txt", None, Some(b"one\n"));
+    record(
+        &source,
+        "aaaa00000002",
+        1,
+        "a.txt",
+        Some(b"one\n"),
+        Some(b"two\n"),
+    );
+    let bundle = project.root.path().join("history.mdiff");
+    let manifest = source
+        .export_bundle(&TimelineFilter::default(), &bundle)
+        .unwrap();
+    assert_eq!(manifest.records, vec!["aaaa00000001", "aaaa00000002"]);
+    assert_eq!(manifest.blobs, 2);
+    (source, bundle)
+}
+
+#[test]
+fn bundle_roundtrip_copies_records_and_blobs() {
+    let project = TestProject::new();
+    let (_, bundle) = exported(&project);
+    let target_root = tempdir().unwrap();
+    let target = project.open_checkout(target_root.path());
+    let (_, added) = target.import_bundle(&bundle).unwrap();
+    assert_eq!(added, 2);
+    let meta = target.read_record_meta("aaaa00000002").unwrap();
+    assert_eq!(meta.project_id, target.project_id());
+    assert_eq!(
+        target.read_blob(&util::hash_bytes(b"two\n")).unwrap(),
+        b"two\n"
+    );
+}
+
+#[test]
+fn importing_a_bundle_twice_adds_nothing() {
+    let project = TestProject::new();
+    let (_, bundle) = exported(&project);
+    let target_root = tempdir().unwrap();
+    let target = project.open_checkout(target_root.path());
+    assert_eq!(target.import_bundle(&bundle).unwrap().1, 2);
+    assert_eq!(target.import_bundle(&bundle).unwrap().1, 0);
+}
+
+#[test]
+fn tampered_blob_rejects_whole_bundle() {
+    let project = TestProject::new();
+    let (source, bundle) = exported(&project);
+    let sha = util::hash_bytes(b"one\n");
+    let blob = source
+        .paths()
+        .blobs_dir
+        .join(&sha[..2])
+        .join(format!("{sha}.zst"));
+    std::fs::write(&blob, zstd::encode_all(&b"eno\n"[..], 0).unwrap()).unwrap();
+    source
+        .export_bundle(&TimelineFilter::default(), &bundle)
+        .unwrap();
+
+    let fresh_root = tempdir().unwrap();
+    let fresh = project.open_checkout(fresh_root.path());
+    let err = fresh.import_bundle(&bundle).unwrap_err();
+    assert!(format!("{err:#}").contains("does not match its hash"));
+    assert!(fresh.latest_record_id().unwrap().is_none());
+}
+
+#[test]
+fn paths_leaving_the_tree_reject_the_bundle() {
+    let project = TestProject::new();
+    let (_, bundle) = exported(&project);
+    let unpacked = tempdir().unwrap();
+    let decoder = zstd::Decoder::new(std::fs::File::open(&bundle).unwrap()).unwrap();
+    tar::Archive::new(decoder).unpack(unpacked.path()).unwrap();
+    let meta_path = unpacked.path().join("records/aaaa00000001/meta.json");
+    let meta = std::fs::read_to_string(&meta_path).unwrap();
+    std::fs::write(&meta_path, meta.replace("\"a.txt\"", "\"../escape.txt\"")).unwrap();
+    let encoder = zstd::Encoder::new(std::fs::File::create(&bundle).unwrap(), 0).unwrap();
+    let mut archive = tar::Builder::new(encoder);
+    archive.append_dir_all(".", unpacked.path()).unwrap();
+    archive.into_inner().unwrap().finish().unwrap();
+
+    let fresh_root = tempdir().unwrap();
+    let fresh = project.open_checkout(fresh_root.path());
+    let err = fresh.import_bundle(&bundle).unwrap_err();
+    assert!(format!("{err:#}").contains("invalid path \"../escape.txt\""));
+    assert!(fresh.latest_record_id().unwrap().is_none());
+}
+
+#[test]
+fn symlinked_record_files_are_not_copied() {
+    let project = TestProject::new();
+    let source = project.open();
+    record(&source, "aaaa00000001", 0, "a.txt", None, Some(b"one\n"));
+    let outside = project.root.path().join("secret");
+    std::fs::write(&outside, "secret\n").unwrap();
+    let record_dir = source.paths().records_dir.join("aaaa00000001");
+    std::os::unix::fs::symlink(&outside, record_dir.join("semantic.json")).unwrap();
+    std::fs::write(record_dir.join("extra.txt"), "extra\n").unwrap();
+
+    let other_root = tempdir().unwrap();
+    let other = project.open_checkout(other_root.path());
+    let err = other.import_history(&source).unwrap_err();
+    assert!(format!("{err:#}").contains("is not a regular file"));
+
+    std::fs::remove_file(record_dir.join("semantic.json")).unwrap();
+    assert_eq!(other.import_history(&source).unwrap(), 1);
+    let copied = other.paths().records_dir.join("aaaa00000001");
+    assert!(copied.join("diff.patch.zst").exists());
+    assert!(!copied.join("extra.txt").exists());
+}
+
+#[test]
+fn newer_imported_records_leave_the_local_chain_alone() {
+    let project = TestProject::new();
+    let source = project.open();
+    record(
+        &source,
+        "bbbb00000001",
+        30,
+        "a.txt",
+        None,
+        Some(b"theirs\n"),
+    );
+    record(
+        &source,
+        "bbbb00000002",
+        31,
+        "a.txt",
+        Some(b"theirs\n"),
+        Some(b"theirs again\n"),
+    );
+    let bundle = project.root.path().join("history.mdiff");
+    source
+        .export_bundle(&TimelineFilter::default(), &bundle)
+        .unwrap();
+
+    let local_root = tempdir().unwrap();
+    let local = project.open_checkout(local_root.path());
+    record(&local, "aaaa00000001", 0, "a.txt", None, Some(b"one\n"));
+    record(
+        &local,
+        "aaaa00000002",
+        1,
+        "a.txt",
+        Some(b"one\n"),
+        Some(b"two\n"),
+    );
+    assert_eq!(local.import_bundle(&bundle).unwrap().1, 2);
+    assert_eq!(
+        local.latest_record_id().unwrap().as_deref(),
+        Some("aaaa00000002")
+    );
+
+    let sha_of = |tree: Tree| tree["a.txt"].sha.clone();
+    assert_eq!(
+        sha_of(local.tree_at("aaaa00000001").unwrap()),
+        util::hash_bytes(b"one\n")
+    );
+    assert_eq!(
+        sha_of(local.tree_at_time(at(1)).unwrap()),
+        util::hash_bytes(b"one\n")
+    );
+
+    let next = record(
+        &local,
+        "aaaa00000003",
+        40,
+        "a.txt",
+        Some(b"two\n"),
+        Some(b"three\n"),
+    );
+    assert_eq!(next.prev_record_id.as_deref(), Some("aaaa00000002"));
+    assert_eq!(
+        sha_of(local.tree_at("aaaa00000002").unwrap()),
+        util::hash_bytes(b"two\n")
+    );
+}
@@ -0,0 +1,124 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use meowdiff::watcher::{BatchCounters, BatchPolicy, Batcher, CloseReason};
+use notify::event::{CreateKind, EventKind, ModifyKind, RemoveKind};
+use notify::Event;
+use tokio::sync::mpsc;
+
+fn write_event(path: &str) -> Event {
+    Event::new(EventKind::Modify(ModifyKind::Any)).add_path(PathBuf::from(path))
+}
+
+#[tokio::test]
+async fn per_file_policy_splits_batches_by_path() {
+    let (tx, rx) = mpsc::channel(16);
+    let counters = Arc::new(BatchCounters::default());
+    let policy = BatchPolicy {
+        per_write: true,
+        ..Default::default()
+    };
+    let mut batcher = Batcher::new(rx, policy, counters.clone());
+    for path in ["a.txt", "a.txt", "b.txt"] {
+        tx.send(write_event(path)).await.unwrap();
+    }
+    drop(tx);
+
+    for path in ["a.txt", "a.txt", "b.txt"] {
+        let batch = batcher.next_batch().await.unwrap();
+        assert_eq!(batch.events.len(), 1);
+        assert_eq!(batch.events[0].paths[0], PathBuf::from(path));
+        assert_eq!(batch.closed_by, CloseReason::Write);
+    }
+    assert!(batcher.next_batch().await.is_none());
+    assert_eq!(counters.snapshot().write, 3);
+}
+
+#[tokio::test]
+async fn max_files_policy_keeps_repeated_writes_together() {
+    let (tx, rx) = mpsc::channel(16);
+    let counters = Arc::new(BatchCounters::default());
+    let policy = BatchPolicy {
+        max_files: Some(1),
+        ..Default::default()
+    };
+    let mut batcher = Batcher::new(rx, policy, counters.clone());
+    for path in ["a.txt", "a.txt", "b.txt"] {
+        tx.send(write_event(path)).await.unwrap();
+    }
+    drop(tx);
+
+    let first = batcher.next_batch().await.unwrap();
+    assert_eq!(first.events.len(), 2);
+    assert_eq!(first.closed_by, CloseReason::MaxFiles);
+    let second = batcher.next_batch().await.unwrap();
+    assert_eq!(second.events[0].paths[0], PathBuf::from("b.txt"));
+    assert!(batcher.next_batch().await.is_none());
+    assert_eq!(counters.snapshot().max_files, 1);
+}
+
+#[tokio::test]
+async fn max_duration_closes_busy_batch() {
+    let (tx, rx) = mpsc::channel(1024);
+    let policy = BatchPolicy {
+        window: Duration::from_millis(50),
+        max_duration: Some(Duration::from_millis(120)),
+        ..Default::default()
+    };
+    let mut batcher = Batcher::new(rx, policy, Arc::new(BatchCounters::default()));
+    let writer = tokio::spawn(async move {
+        for _ in 0..40 {
+            if tx.send(write_event("log.txt")).await.is_err() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    });
+    let batch = batcher.next_batch().await.unwrap();
+    assert_eq!(batch.closed_by, CloseReason::MaxDuration);
+    assert!(batch.events.len() < 40);
+    writer.abort();
+}
+
+#[tokio::test]
+async fn flush_closes_open_batch_with_queued_events() {
+    let (tx, rx) = mpsc::channel(16);
+    let counters = Arc::new(BatchCounters::default());
+    let policy = BatchPolicy {
+        window: Duration::from_secs(60),
+        ..Default::default()
+    };
+    let mut batcher = Batcher::new(rx, policy, counters.clone());
+    assert!(batcher.flush().is_none());
+
+    for path in ["a.txt", "b.txt"] {
+        tx.send(write_event(path)).await.unwrap();
+    }
+    let batch = batcher.flush().expect("queued events are flushed");
+    assert_eq!(batch.events.len(), 2);
+    assert_eq!(batch.closed_by, CloseReason::Flush);
+    assert_eq!(counters.snapshot().flush, 1);
+    assert!(batcher.flush().is_none());
+}
+
+#[tokio::test]
+async fn per_write_policy_keeps_vim_probe_with_its_delete() {
+    let (tx, rx) = mpsc::channel(16);
+    let policy = BatchPolicy {
+        per_write: true,
+        ..Default::default()
+    };
+    let mut batcher = Batcher::new(rx, policy, Arc::new(BatchCounters::default()));
+    tx.send(Event::new(EventKind::Create(CreateKind::File)).add_path(PathBuf::from("4913")))
+        .await
+        .unwrap();
+    tx.send(Event::new(EventKind::Remove(RemoveKind::File)).add_path(PathBuf::from("4913")))
+        .await
+        .unwrap();
+    drop(tx);
+
+    let batch = batcher.next_batch().await.unwrap();
+    assert_eq!(batch.events.len(), 2);
+    assert_eq!(batch.closed_by, CloseReason::Write);
+}
@@ -0,0 +1,55 @@
+use meowdiff::models::CellStats;
+use meowdiff::pipeline::{notebook_patch, NotebookOptions};
+
+fn notebook(cells: &str) -> Vec<u8> {
+    format!(r#"{{"cells": [{cells}], "metadata": {{}}, "nbformat": 4}}"#).into_bytes()
+}
+
+#[test]
+fn notebook_diff_ignores_outputs_and_counts() {
+    let before = notebook(
+        r#"{"cell_type": "code", "source": ["x = 1\n"], "execution_count": 1, "outputs": [{"output_type": "stream", "text": "a"}]},
+           {"cell_type": "code", "source": "print(x)", "execution_count": 2, "outputs": []}"#,
+    );
+    let after = notebook(
+        r#"{"cell_type": "code", "source": ["x = 1\n"], "execution_count": 7, "outputs": [{"output_type": "stream", "text": "b"}]},
+           {"cell_type": "code", "source": "print(x + 1)", "execution_count": 8, "outputs": []},
+           {"cell_type": "markdown", "source": "Notes", "metadata": {}}"#,
+    );
+    let options = NotebookOptions {
+        enabled: true,
+        ignore_outputs: true,
+        ignore_execution_count: true,
+        ignore_metadata: true,
+    };
+    let (patch, stats) =
+        notebook_patch("a.ipynb", Some(&before), Some(&after), &options).expect("notebook parses");
+    assert_eq!(
+        stats.cells,
+        Some(CellStats {
+            added: 1,
+            removed: 0,
+            modified: 1,
+            ignored_only: false,
+        })
+    );
+    assert!(patch.contains("+print(x + 1)"));
+    assert!(!patch.contains("execution_count"));
+}
+
+#[test]
+fn notebook_diff_with_only_ignored_changes_is_empty() {
+    let before =
+        notebook(r#"{"cell_type": "code", "source": "x", "execution_count": 1, "outputs": []}"#);
+    let after =
+        notebook(r#"{"cell_type": "code", "source": "x", "execution_count": 2, "outputs": []}"#);
+    let options = NotebookOptions {
+        enabled: true,
+        ignore_execution_count: true,
+        ..Default::default()
+    };
+    let (patch, stats) =
+        notebook_patch("a.ipynb", Some(&before), Some(&after), &options).expect("notebook parses");
+    assert!(patch.is_empty());
+    assert!(stats.cells.unwrap().ignored_only);
+}
@@ -0,0 +1,13 @@
+use meowdiff::ignore::PathGlobs;
+use tempfile::tempdir;
+
+#[test]
+fn globs_match_relative_record_paths() {
+    let dir = tempdir().unwrap();
+    let globs = PathGlobs::new(dir.path(), &["*.rs".into(), "docs/".into()]).unwrap();
+    assert!(globs.matches_relative("main.rs"));
+    assert!(globs.matches_relative("src/deep/lib.rs"));
+    assert!(globs.matches_relative("docs/guide.md"));
+    assert!(!globs.matches_relative("README.md"));
+    assert!(!globs.matches_relative("src/docs.txt"));
+}
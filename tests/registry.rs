@@ -0,0 +1,96 @@
+use std::path::PathBuf;
+
+mod common;
+
+use common::{record, TestProject};
+use meowdiff::storage::{Registry, WatcherStatus};
+use tempfile::tempdir;
+
+#[test]
+fn concurrent_writers_keep_every_entry() {
+    let root = tempdir().unwrap();
+    Registry::open_in(root.path()).unwrap();
+    let handles: Vec<_> = (0..8)
+        .map(|n| {
+            let root = root.path().to_path_buf();
+            std::thread::spawn(move || {
+                let registry = Registry::open_in(&root).unwrap();
+                let project_id = format!("project{n}");
+                for i in 0..20 {
+                    registry
+                        .touch(&project_id, &PathBuf::from(format!("/src/{n}")))
+                        .unwrap();
+                    registry.set_last_record(&project_id, i).unwrap();
+                }
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    let entries = Registry::open_in(root.path()).unwrap().list().unwrap();
+    assert_eq!(entries.len(), 8);
+    assert!(entries.iter().all(|entry| entry.last_record_at == Some(19)));
+}
+
+#[test]
+fn imports_legacy_json_once() {
+    let root = tempdir().unwrap();
+    std::fs::write(
+        root.path().join("registry.json"),
+        r#"{"projects":[{"project_id":"abc","path":"/src/abc","last_seen":10,"enabled":true,"enabled_at":5}]}"#,
+    )
+    .unwrap();
+    let registry = Registry::open_in(root.path()).unwrap();
+    let entry = registry.find("abc").unwrap().unwrap();
+    assert_eq!(entry.path, "/src/abc");
+    assert!(entry.enabled);
+    assert_eq!(entry.enabled_at, Some(5));
+    assert!(!root.path().join("registry.json").exists());
+    assert!(root.path().join("registry.json.bak").exists());
+}
+
+#[test]
+fn tracks_watcher_state_and_removal() {
+    let root = tempdir().unwrap();
+    let registry = Registry::open_in(root.path()).unwrap();
+    registry.touch("abc", &PathBuf::from("/src/abc")).unwrap();
+    registry
+        .set_watcher("abc", WatcherStatus::Supervised, Some(42))
+        .unwrap();
+    registry.set_storage_bytes("abc", 2048).unwrap();
+    let entry = registry.find("abc").unwrap().unwrap();
+    assert_eq!(entry.watcher_state, WatcherStatus::Supervised);
+    assert_eq!(entry.watcher_pid, Some(42));
+    assert_eq!(entry.storage_bytes, Some(2048));
+
+    assert!(registry.set_enabled("missing", true).is_err());
+    assert!(registry.remove("abc").unwrap());
+    assert!(registry.find("abc").unwrap().is_none());
+}
+
+#[test]
+fn enabling_clears_a_manual_stop() {
+    let root = tempdir().unwrap();
+    let registry = Registry::open_in(root.path()).unwrap();
+    registry.touch("abc", &PathBuf::from("/src/abc")).unwrap();
+    registry.set_enabled("abc", true).unwrap();
+    registry.set_stopped("abc", true).unwrap();
+    assert!(registry.find("abc").unwrap().unwrap().stopped);
+
+    let entry = registry.set_enabled("abc", true).unwrap();
+    assert!(entry.enabled && !entry.stopped);
+}
+
+#[test]
+fn records_add_their_size_without_a_rescan() {
+    let project = TestProject::new();
+    let storage = project.open();
+    storage.refresh_storage_size().unwrap();
+    let size = || storage.list_projects().unwrap()[0].storage_bytes.unwrap();
+    let before = size();
+
+    let meta = record(&storage, "aaaa00000001", 0, "a.txt", None, Some(b"hello\n"));
+    storage.register_record(&meta).unwrap();
+    assert!(size() > before);
+}
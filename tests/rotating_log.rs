@@ -0,0 +1,25 @@
+use std::fs;
+use std::io::Write;
+
+use meowdiff::runtime::{RotatingLog, CURRENT_LOG};
+use tempfile::tempdir;
+
+#[test]
+fn rolls_over_at_size_cap_and_prunes_old_files() {
+    let dir = tempdir().unwrap();
+    let mut log = RotatingLog::with_limits(dir.path(), 32, 2).unwrap();
+    for i in 0..5 {
+        log.write_all(format!("line {i} of twenty bytes\n").as_bytes())
+            .unwrap();
+    }
+    log.flush().unwrap();
+
+    let current = fs::read_to_string(dir.path().join(CURRENT_LOG)).unwrap();
+    assert_eq!(current, "line 4 of twenty bytes\n");
+    let archived = fs::read_dir(dir.path())
+        .unwrap()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_name() != CURRENT_LOG)
+        .count();
+    assert_eq!(archived, 2);
+}
@@ -0,0 +1,204 @@
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use meowdiff::storage::StorageEngine;
+use meowdiff::watcher::{rpc_call, rpc_subscribe, RpcError};
+use serde_json::{json, Value};
+use tempfile::{tempdir, TempDir};
+
+const INVALID_REQUEST: i64 = -32600;
+const PARSE_ERROR: i64 = -32700;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+/// A foreground watcher on a scratch checkout, stopped when dropped.
+struct Watcher {
+    _home: TempDir,
+    checkout: TempDir,
+    project_id: String,
+    socket: PathBuf,
+    child: Child,
+}
+
+impl Watcher {
+    fn start() -> Self {
+        let home = tempdir().unwrap();
+        let checkout = tempdir().unwrap();
+        std::fs::write(checkout.path().join("a.txt"), "a\n").unwrap();
+        let storage =
+            StorageEngine::open_in(&home.path().join(".meowdiff"), checkout.path()).unwrap();
+        let project_id = storage.project_id().to_string();
+        let socket = storage.paths().meta_dir.join("watch.sock");
+        drop(storage);
+
+        let child = Command::new(env!("CARGO_BIN_EXE_meowdiff"))
+            .env("HOME", home.path())
+            .args(["watch", "--path"])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .arg(checkout.path())
+            .spawn()
+            .unwrap();
+        let watcher = Self {
+            _home: home,
+            checkout,
+            project_id,
+            socket,
+            child,
+        };
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while UnixStream::connect(&watcher.socket).is_err() {
+            assert!(Instant::now() < deadline, "watcher did not start");
+            thread::sleep(Duration::from_millis(50));
+        }
+        watcher
+    }
+
+    fn call(&self, method: &str, params: Value) -> Value {
+        rpc_call(&self.socket, method, params).unwrap()
+    }
+
+    fn error_code(&self, method: &str, params: Value) -> i64 {
+        let err = rpc_call(&self.socket, method, params).unwrap_err();
+        err.downcast_ref::<RpcError>().expect("rpc error").code
+    }
+}
+
+impl Drop for Watcher {
+    fn drop(&mut self) {
+        let _ = rpc_call(&self.socket, "shutdown", Value::Null);
+        let _ = self.child.wait();
+    }
+}
+
+/// Sends raw lines on one connection and returns one response per line.
+fn exchange(socket: &Path, lines: &[&str]) -> Vec<Value> {
+    let mut stream = UnixStream::connect(socket).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(10)))
+        .unwrap();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    lines
+        .iter()
+        .map(|line| {
+            writeln!(stream, "{line}").unwrap();
+            let mut response = String::new();
+            reader.read_line(&mut response).unwrap();
+            serde_json::from_str(&response).unwrap()
+        })
+        .collect()
+}
+
+#[test]
+fn status_describes_the_watcher() {
+    let watcher = Watcher::start();
+    let status = watcher.call("status", Value::Null);
+    assert_eq!(status["project_id"], watcher.project_id.as_str());
+    assert_eq!(status["paused"], false);
+    assert_eq!(status["subscribers"], 0);
+    assert!(status["batches"].is_object());
+}
+
+#[test]
+fn pause_and_resume_round_trip() {
+    let watcher = Watcher::start();
+    let paused = watcher.call("pause", json!({ "seconds": 600 }));
+    assert_eq!(paused["paused"], true);
+    assert!(paused["paused_until"].is_string());
+
+    let resumed = watcher.call("resume", Value::Null);
+    assert_eq!(resumed["paused"], false);
+    assert!(resumed["paused_at"].is_null());
+}
+
+#[test]
+fn label_applies_to_later_records() {
+    let watcher = Watcher::start();
+    assert_eq!(
+        watcher.call("label", json!({ "text": "wip" })),
+        json!({ "label": "wip" })
+    );
+    assert_eq!(watcher.call("status", Value::Null)["label"], "wip");
+    assert_eq!(watcher.call("label", Value::Null)["label"], Value::Null);
+}
+
+#[test]
+fn bad_params_and_methods_have_error_codes() {
+    let watcher = Watcher::start();
+    assert_eq!(
+        watcher.error_code("pause", json!({ "seconds": "soon" })),
+        INVALID_PARAMS
+    );
+    assert_eq!(
+        watcher.error_code("label", json!({ "text": 5 })),
+        INVALID_PARAMS
+    );
+    assert_eq!(watcher.error_code("rewind", Value::Null), METHOD_NOT_FOUND);
+}
+
+#[test]
+fn pause_beyond_the_calendar_is_rejected() {
+    let watcher = Watcher::start();
+    for seconds in [u64::MAX, i64::MAX as u64] {
+        assert_eq!(
+            watcher.error_code("pause", json!({ "seconds": seconds })),
+            INVALID_PARAMS
+        );
+    }
+    assert_eq!(watcher.call("status", Value::Null)["paused"], false);
+}
+
+#[test]
+fn malformed_lines_get_errors_and_keep_the_connection() {
+    let watcher = Watcher::start();
+    let responses = exchange(
+        &watcher.socket,
+        &[
+            "not json",
+            r#"{"jsonrpc":"2.0","id":7}"#,
+            r#"{"jsonrpc":"2.0","id":8,"method":"status"}"#,
+        ],
+    );
+    assert_eq!(responses[0]["id"], Value::Null);
+    assert_eq!(responses[0]["error"]["code"], PARSE_ERROR);
+    assert_eq!(responses[1]["id"], 7);
+    assert_eq!(responses[1]["error"]["code"], INVALID_REQUEST);
+    assert_eq!(responses[2]["id"], 8);
+    assert_eq!(
+        responses[2]["result"]["project_id"],
+        watcher.project_id.as_str()
+    );
+}
+
+#[test]
+fn flush_commits_pending_changes() {
+    let watcher = Watcher::start();
+    let mut subscription = rpc_subscribe(&watcher.socket, false).unwrap();
+    std::fs::write(watcher.checkout.path().join("a.txt"), "b\n").unwrap();
+    // give the watcher a moment to see the write
+    thread::sleep(Duration::from_millis(300));
+
+    let flushed = watcher.call("flush", Value::Null);
+    let event = subscription.next().unwrap().unwrap();
+    assert_eq!(flushed["latest_record"], event.meta.record_id.as_str());
+    assert_eq!(event.meta.files[0].path, "a.txt");
+    assert!(event.patch.is_none());
+}
+
+#[test]
+fn closed_subscriptions_are_dropped() {
+    let watcher = Watcher::start();
+    let subscription = rpc_subscribe(&watcher.socket, true).unwrap();
+    assert_eq!(watcher.call("status", Value::Null)["subscribers"], 1);
+
+    drop(subscription);
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while watcher.call("status", Value::Null)["subscribers"] != 0 {
+        assert!(Instant::now() < deadline, "subscriber was not dropped");
+        thread::sleep(Duration::from_millis(20));
+    }
+}
@@ -0,0 +1,134 @@
+mod common;
+
+use common::{change, commit, TestProject};
+use meowdiff::ignore::PathGlobs;
+use meowdiff::pipeline::{compress_patch, FileArtifact};
+use meowdiff::storage::{LineSide, SearchQuery, StorageEngine};
+use regex::Regex;
+
+fn record(storage: &StorageEngine, id: &str, minute: u32, path: &str, patch: &str) {
+    let file = FileArtifact {
+        patch: patch.to_string(),
+        ..change(path, None, None)
+    };
+    let stored = compress_patch(&format!("{patch}\n\n")).unwrap();
+    commit(storage, id, minute, None, &stored, &[file]);
+}
+
+/// Three records: `fetch_user` is added to lib.rs, mentioned in the README,
+/// then renamed away again.
+fn history(project: &TestProject) -> StorageEngine {
+    let storage = project.open();
+    record(
+        &storage,
+        "aaaa00000001",
+        0,
+        "src/lib.rs",
+        "--- a/src/lib.rs\n+++ b/src/lib.rs\n@@ -1,2 +1,2 @@\n fn main() {\n-    old_helper();\n+    fetch_user(42);\n",
+    );
+    record(
+        &storage,
+        "aaaa00000002",
+        1,
+        "README.md",
+        "--- a/README.md\n+++ b/README.md\n@@ -1 +1,2 @@\n # Title\n+Call fetch_user to load one.\n",
+    );
+    record(
+        &storage,
+        "aaaa00000003",
+        2,
+        "src/lib.rs",
+        "--- a/src/lib.rs\n+++ b/src/lib.rs\n@@ -1,2 +1,2 @@\n fn main() {\n-    fetch_user(42);\n+    fetch_account(42);\n",
+    );
+    storage
+}
+
+fn ids(
+    storage: &StorageEngine,
+    pattern: &str,
+    side: LineSide,
+    files: Option<&PathGlobs>,
+) -> Vec<String> {
+    let pattern = Regex::new(pattern).unwrap();
+    let query = SearchQuery {
+        pattern: &pattern,
+        side,
+        files,
+        filter: Default::default(),
+    };
+    storage
+        .search(&query)
+        .unwrap()
+        .into_iter()
+        .map(|hit| format!("{} {}", hit.record_id, hit.path))
+        .collect()
+}
+
+#[test]
+fn finds_hunks_newest_first() {
+    let project = TestProject::new();
+    let storage = history(&project);
+    assert_eq!(
+        ids(&storage, r"fetch_user\(\d+\)", LineSide::Either, None),
+        ["aaaa00000003 src/lib.rs", "aaaa00000001 src/lib.rs"]
+    );
+}
+
+#[test]
+fn side_restricts_to_added_or_removed_lines() {
+    let project = TestProject::new();
+    let storage = history(&project);
+    assert_eq!(
+        ids(&storage, "fetch_user", LineSide::Added, None),
+        ["aaaa00000002 README.md", "aaaa00000001 src/lib.rs"]
+    );
+    assert_eq!(
+        ids(&storage, "fetch_user", LineSide::Removed, None),
+        ["aaaa00000003 src/lib.rs"]
+    );
+}
+
+#[test]
+fn file_globs_restrict_paths() {
+    let project = TestProject::new();
+    let storage = history(&project);
+    let rust = PathGlobs::new(storage.project_root(), &["*.rs".to_string()]).unwrap();
+    assert_eq!(
+        ids(&storage, "fetch_user", LineSide::Added, Some(&rust)),
+        ["aaaa00000001 src/lib.rs"]
+    );
+}
+
+#[test]
+fn context_lines_never_match() {
+    let project = TestProject::new();
+    let storage = history(&project);
+    assert!(ids(&storage, "fn main", LineSide::Either, None).is_empty());
+}
+
+#[test]
+fn patterns_without_literals_still_match() {
+    let project = TestProject::new();
+    let storage = history(&project);
+    assert_eq!(
+        ids(&storage, r"^\s+\w+\(", LineSide::Removed, None),
+        ["aaaa00000003 src/lib.rs", "aaaa00000001 src/lib.rs"]
+    );
+}
+
+#[test]
+fn hits_carry_hunk_and_matching_lines() {
+    let project = TestProject::new();
+    let storage = history(&project);
+    let pattern = Regex::new("old_helper").unwrap();
+    let hits = storage
+        .search(&SearchQuery {
+            pattern: &pattern,
+            side: LineSide::Either,
+            files: None,
+            filter: Default::default(),
+        })
+        .unwrap();
+    assert_eq!(hits[0].lines, ["-    old_helper();"]);
+    assert!(hits[0].hunk.starts_with("@@ -1,2 +1,2 @@\n"));
+}
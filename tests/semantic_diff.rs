@@ -0,0 +1,39 @@
+use meowdiff::models::SemanticOp;
+use meowdiff::pipeline::{build_file_artifact, semantic_diff, DiffOptions, FileInput};
+
+#[test]
+fn semantic_diff_reports_changed_paths() {
+    let before = br#"{"dependencies": {"serde": "1.0.1", "tokio": "1"}}"#;
+    let after = br#"{"dependencies": {"serde": "1.0.2", "anyhow": "1"}}"#;
+    let diff = semantic_diff("package.json", before, after).expect("json parses");
+    let summary: Vec<(&str, &SemanticOp)> = diff
+        .changes
+        .iter()
+        .map(|c| (c.path.as_str(), &c.op))
+        .collect();
+    assert_eq!(
+        summary,
+        vec![
+            ("dependencies.anyhow", &SemanticOp::Added),
+            ("dependencies.serde", &SemanticOp::Changed),
+            ("dependencies.tokio", &SemanticOp::Removed),
+        ]
+    );
+}
+
+#[test]
+fn semantic_diff_falls_back_on_parse_error() {
+    let options = DiffOptions {
+        semantic: true,
+        ..Default::default()
+    };
+    let input = FileInput {
+        path: "config.yaml".into(),
+        before: Some(b"a: 1\n".to_vec()),
+        after: Some(b"a: [1\n".to_vec()),
+        ..Default::default()
+    };
+    let artifact = build_file_artifact(input, &options).unwrap().unwrap();
+    assert!(artifact.semantic.is_none());
+    assert!(artifact.patch.contains("+a: [1"));
+}
@@ -0,0 +1,97 @@
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::process::{Command as StdCommand, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use assert_cmd::Command;
+use meowdiff::runtime::{render_unit, ServiceTarget, SERVICE_ENV};
+use meowdiff::storage::StorageEngine;
+use meowdiff::watcher::rpc_call;
+use tempfile::tempdir;
+
+#[test]
+fn project_unit_runs_foreground_watcher() {
+    let target = ServiceTarget::Project {
+        project_id: "abc123".into(),
+        root: PathBuf::from("/home/me/src/app"),
+    };
+    assert_eq!(target.unit_name(), "meowdiff-abc123.service");
+    let unit = render_unit(Path::new("/usr/local/bin/meowdiff"), &target);
+    assert!(unit.contains("Description=MeowDiff watcher for /home/me/src/app\n"));
+    assert!(unit.contains(
+        "ExecStart=/usr/local/bin/meowdiff watch --foreground --log-stderr --path /home/me/src/app\n"
+    ));
+    assert!(unit.contains(&format!(
+        "Environment={SERVICE_ENV}=meowdiff-abc123.service\n"
+    )));
+    assert!(unit.contains("Restart=on-failure\n"));
+    assert!(unit.contains("[Install]\nWantedBy=default.target\n"));
+}
+
+#[test]
+fn daemon_unit_runs_supervisor() {
+    let unit = render_unit(Path::new("/opt/meowdiff"), &ServiceTarget::Daemon);
+    assert!(unit.contains("ExecStart=/opt/meowdiff daemon --foreground --log-stderr\n"));
+    assert!(unit.contains("Environment=MEOWDIFF_SERVICE=meowdiff-daemon.service\n"));
+}
+
+#[test]
+fn exec_arguments_are_quoted() {
+    let target = ServiceTarget::Project {
+        project_id: "abc123".into(),
+        root: PathBuf::from("/home/me/My Projects/100% \"done\""),
+    };
+    let unit = render_unit(Path::new("/usr/bin/meowdiff"), &target);
+    assert!(unit.contains(r#"--path "/home/me/My Projects/100%% \"done\"""#));
+    assert!(unit.contains("Description=MeowDiff watcher for /home/me/My Projects/100%% \"done\"\n"));
+}
+
+#[test]
+fn status_and_uninstall_do_not_create_a_store() {
+    let home = tempdir().unwrap();
+    let project = tempdir().unwrap();
+    for args in [&["service", "status"][..], &["service", "uninstall"]] {
+        Command::cargo_bin("meowdiff")
+            .unwrap()
+            .env("HOME", home.path())
+            .env("XDG_CONFIG_HOME", home.path().join(".config"))
+            .args(args)
+            .arg("--path")
+            .arg(project.path())
+            .assert()
+            .success();
+    }
+    assert!(!home.path().join(".meowdiff").exists());
+}
+
+#[test]
+fn log_stderr_keeps_foreground_logs_off_disk() {
+    let home = tempdir().unwrap();
+    let project = tempdir().unwrap();
+    let storage = StorageEngine::open_in(&home.path().join(".meowdiff"), project.path()).unwrap();
+    let meta_dir = storage.paths().meta_dir.clone();
+    drop(storage);
+
+    let child = StdCommand::new(env!("CARGO_BIN_EXE_meowdiff"))
+        .env("HOME", home.path())
+        .args(["watch", "--foreground", "--log-stderr", "--path"])
+        .arg(project.path())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let socket = meta_dir.join("watch.sock");
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while UnixStream::connect(&socket).is_err() {
+        assert!(Instant::now() < deadline, "watcher did not start");
+        thread::sleep(Duration::from_millis(50));
+    }
+    rpc_call(&socket, "shutdown", serde_json::Value::Null).unwrap();
+    let output = child.wait_with_output().unwrap();
+
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("watcher started"));
+    assert!(!stderr.contains('\x1b'));
+    assert!(!meta_dir.join("logs").exists());
+}
@@ -0,0 +1,53 @@
+use std::sync::Arc;
+
+use meowdiff::models::{FileOp, FileRecord, FileStats};
+use meowdiff::watcher::{CachedSnapshot, SnapshotCache};
+
+fn snapshot(sha: &str, size: usize) -> CachedSnapshot {
+    CachedSnapshot {
+        sha: sha.to_string(),
+        mode: None,
+        content: Arc::new(vec![0u8; size]),
+    }
+}
+
+#[test]
+fn cache_evicts_least_recently_used_by_bytes() {
+    let cache = SnapshotCache::new(100);
+    cache.insert("a", snapshot("1", 40));
+    cache.insert("b", snapshot("2", 40));
+    assert!(cache.get("a").is_some());
+    cache.insert("c", snapshot("3", 40));
+    assert!(cache.get("b").is_none());
+    assert!(cache.get("a").is_some());
+    assert_eq!(cache.stats().bytes, 80);
+}
+
+#[test]
+fn cache_follows_committed_records() {
+    let cache = SnapshotCache::new(1024);
+    cache.insert("src/foo/a.rs", snapshot("1", 4));
+    cache.insert("src/main.rs", snapshot("2", 4));
+    let record = FileRecord {
+        path: "src/main.rs".into(),
+        op: FileOp::Modified,
+        before_sha: Some("2".into()),
+        after_sha: Some("3".into()),
+        stats: FileStats::default(),
+        before_mode: None,
+        after_mode: None,
+        symlink_target: None,
+    };
+    cache.apply_committed(&record, Some(b"new".to_vec()));
+    assert_eq!(cache.get("src/main.rs").unwrap().sha, "3");
+
+    let removed = FileRecord {
+        path: "src/foo".into(),
+        op: FileOp::DirRemoved,
+        after_sha: None,
+        before_sha: None,
+        ..record
+    };
+    cache.apply_committed(&removed, None);
+    assert!(cache.get("src/foo/a.rs").is_none());
+}